@@ -0,0 +1,22 @@
+//! `cargo +nightly fuzz run wav_parse`: arbitrary bytes must never make `Wav::parse`
+//! panic, and whatever it accepts must survive `encode` and parse back unchanged.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The server is a binary crate, so the module is compiled in directly.
+#[allow(dead_code)]
+#[path = "../../src/wav.rs"]
+mod wav;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(parsed) = wav::Wav::parse(data) else {
+        return;
+    };
+    let _ = parsed.duration();
+    let reparsed = wav::Wav::parse(&parsed.encode()).expect("encoded WAV must parse");
+    assert_eq!(reparsed.sample_rate, parsed.sample_rate);
+    assert_eq!(reparsed.channels, parsed.channels);
+    assert_eq!(reparsed.samples, parsed.samples);
+});
@@ -0,0 +1,971 @@
+use crate::engine::{Engine, EngineKind};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Serializes read-modify-write cycles on the config file within one process.
+static CONFIG_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct AppConfig {
+    pub voicevox_default_speaker: Option<u32>,
+    pub aivis_default_speaker: Option<u32>,
+    pub macos_default_voice: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voicevox_default_speed: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aivis_default_speed: Option<f32>,
+    /// Extra engines by name, usable from `speak_auto` and the other engine-generic tools.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub engines: BTreeMap<String, EngineConfig>,
+    /// Per-speaker default prosody keyed by speaker ID (as a string, e.g. `"3"`).
+    ///
+    /// Precedence for each value: per-call argument > speaker override > engine default
+    /// (`*_default_speed`) > engine's own value. The global multiplier/offset below are
+    /// then applied on top of whichever value won.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub speaker_overrides: BTreeMap<String, SpeakerOverride>,
+    /// Logical voices (e.g. `"narrator"`) bundling an engine, speaker and prosody, used
+    /// through the `voice` argument of `speak_auto`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub named_voices: BTreeMap<String, NamedVoice>,
+    /// Preset phrases by key (e.g. `"done"` → `"完了しました"`) for `speak_phrase`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub quick_phrases: BTreeMap<String, String>,
+    /// Multiplied onto every VOICEVOX/Aivis speed, including explicit per-call values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global_speed_multiplier: Option<f32>,
+    /// Added to every VOICEVOX/Aivis pitchScale, including explicit per-call values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub global_pitch_offset: Option<f32>,
+    /// Local port of each engine. Defaults to 50021 (VOICEVOX) and 10101 (Aivis Speech).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voicevox_port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aivis_port: Option<u16>,
+    /// e.g. `http://192.168.0.10:50021` for a remote engine.
+    /// Overridden by `SPEAK_MCP_VOICEVOX_URL`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voicevox_base_url: Option<String>,
+    /// Overridden by `SPEAK_MCP_AIVIS_URL`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aivis_base_url: Option<String>,
+    /// When set, every utterance is appended to this local JSON Lines file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_log_path: Option<String>,
+    /// Include the spoken text in the usage log (otherwise only its length).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub log_text: bool,
+    /// Rotate the usage log to `<path>.1` once it exceeds this size. Defaults to 1 MiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_log_max_bytes: Option<u64>,
+    /// When set, speech that failed because the engine was unreachable or returned a
+    /// server error is saved to this JSON Lines file for `retry_failed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_log_path: Option<String>,
+    /// Drop the oldest saved failures once the file exceeds this size. Defaults to
+    /// 256 KiB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_log_max_bytes: Option<u64>,
+    /// Split text longer than this many characters into sentences, synthesizing them
+    /// in parallel while playing in order. Unset disables splitting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_min_chars: Option<usize>,
+    /// Silence inserted between sentences when `chunk_min_chars` splits the text.
+    /// Defaults to 0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inter_sentence_ms: Option<u64>,
+    /// Minimum silence between two queued utterances, even from separate tool calls.
+    /// 0 or unset disables it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inter_utterance_ms: Option<u64>,
+    /// What a speak call does while another utterance is playing: wait (`queue`, the
+    /// default), cut it off (`interrupt`) or stay silent (`drop`).
+    #[serde(default, skip_serializing_if = "PlaybackMode::is_queue")]
+    pub playback_mode: PlaybackMode,
+    /// How many synthesis requests may run at once on each engine. Defaults to 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_synthesis: Option<usize>,
+    /// `max_concurrent_synthesis` per engine name, for engines that need a different
+    /// limit (e.g. a rate-limited cloud engine).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub engine_max_concurrent_synthesis: BTreeMap<String, usize>,
+    /// Play audio in-process with rodio instead of afplay / PowerShell. Also enables
+    /// playback on platforms without an external player (e.g. Linux).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub use_internal_player: bool,
+    /// Volume for the built-in player, 1.0 = unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_player_volume: Option<f32>,
+    /// Directory for playback temp files when the system temp directory is unusable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_dir: Option<String>,
+    /// Extra attempts when the audio player exits with an error. Defaults to 0.
+    /// A missing player is never retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playback_retries: Option<u32>,
+    /// Seconds a player may run past the audio's length before it is treated as hung,
+    /// killed and reported as a timeout. Defaults to 10; 0 turns the watchdog off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playback_timeout_secs: Option<u64>,
+    /// Resample every synthesized WAV to this rate (e.g. 24000) so output from engines
+    /// with different native rates can be mixed. Audio already at this rate is untouched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_sample_rate: Option<u32>,
+    /// Engines `speak_auto` tries, in order, when no `engine` is given; the first
+    /// reachable one is used. Empty means VOICEVOX.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub engine_priority: Vec<String>,
+    /// After `engine_priority` falls back past a dead engine, keep using the one that
+    /// worked for `sticky_engine_secs` instead of probing the dead one first every call.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sticky_working_engine: bool,
+    /// How long `sticky_working_engine` remembers the engine. Defaults to 300 seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sticky_engine_secs: Option<u64>,
+    /// Engine `speak_auto` uses for English text when no `engine` is given: a registered
+    /// engine name, or `"say"` on macOS. Unset keeps everything on VOICEVOX.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub english_engine: Option<String>,
+    /// Shell command every synthesized WAV is passed through before playback or saving.
+    /// Reads stdin and writes stdout, or uses `{in}` / `{out}` file placeholders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_process_command: Option<String>,
+    /// Shell command run after a speak call has finished playing, e.g. to switch off a
+    /// "speaking" light. `{engine}`, `{speaker}` and `{duration}` (seconds) are filled in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_complete_command: Option<String>,
+    /// Ports probed at startup for VOICEVOX-compatible engines on localhost, as numbers
+    /// or `"50020-50030"` ranges. Engines found are registered as `port<N>`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub autodiscover_ports: Vec<PortSpec>,
+    /// Index used by `skip_if_exists` to recognize files saved earlier. Defaults to
+    /// `save_index.json` next to the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_index_path: Option<String>,
+    /// File format `save_speech` uses per engine name when the call doesn't choose one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_save_formats: BTreeMap<String, SaveFormat>,
+    /// Whether speak calls play the audio, return it to the client or both, per engine
+    /// name, when the call doesn't pass `return_audio`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_audio_disposition: BTreeMap<String, AudioDisposition>,
+    /// Extra HTTP headers per engine name, sent with every request to that engine
+    /// (speaker lists and synthesis). `${VAR}` in a value is read from the environment.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub engine_headers: BTreeMap<String, BTreeMap<String, String>>,
+    /// Report the kana reading the engine chose for each speak call (in `_meta.kana`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify_kana: bool,
+    /// Expected readings (katakana or hiragana) for words such as names and jargon. With
+    /// `verify_kana`, a reading that doesn't contain the expected one is flagged.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub kana_overrides: BTreeMap<String, String>,
+    /// Speaker used per engine name when the requested one is rejected as unknown, e.g.
+    /// after an engine update removed a style.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fallback_speakers: BTreeMap<String, u32>,
+    /// TLS settings per engine name, for https engines behind a self-signed or private
+    /// CA certificate. Engines without an entry use strict verification.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub engine_tls: BTreeMap<String, EngineTls>,
+    /// Explicit HTTP proxy, e.g. `http://proxy.example.com:8080`. Without it the
+    /// `HTTP_PROXY` / `HTTPS_PROXY` / `NO_PROXY` environment variables are used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_url: Option<String>,
+    /// Memory budget for the synthesized-audio cache. Defaults to 16 MiB; 0 disables it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_cache_max_bytes: Option<u64>,
+    /// How long audio from `prepare_speech` stays playable. Defaults to 300 seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prepared_ttl_secs: Option<u64>,
+    /// How long a `speak_stream_append` session may sit idle before it is dropped along
+    /// with its unspoken text. Defaults to 60 seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_session_ttl_secs: Option<u64>,
+    /// How long an engine reachability probe is reused by `engine_status`. Defaults to 5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_cache_ttl_secs: Option<u64>,
+    /// Whether speaking with an unreachable engine fails the call (`error`, the default)
+    /// or succeeds with a note that it was skipped (`skip`).
+    #[serde(default, skip_serializing_if = "OfflineBehavior::is_error")]
+    pub offline_behavior: OfflineBehavior,
+    /// Load each engine's default speaker model at startup, in the background, so the
+    /// first speak call doesn't wait for it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub warm_up_speakers: bool,
+    /// Longest verbose detail (`verbose: true`) returned before it is cut off with a note.
+    /// Defaults to 2000 characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbose_max_chars: Option<usize>,
+    /// Append verbose detail to the message instead of returning it as a separate content
+    /// block, for clients that only show the first block.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verbose_inline: bool,
+    /// Most speaker styles listed as choices in a tool schema. An engine with more gets a
+    /// plain integer `speaker` instead. Unlimited by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_schema_oneof: Option<usize>,
+    /// Wait this long before the startup `/speakers` fetch. Defaults to 0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub startup_delay_ms: Option<u64>,
+    /// Retry `/speakers` in the background every N seconds (backing off) for engines
+    /// that were offline at startup. Unset or 0 disables it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_speaker_poll_secs: Option<u64>,
+    /// Whether an empty or whitespace-only `text` is reported with `is_error: true`
+    /// (the default) or as a normal response. Either way nothing is sent to the engine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub empty_text_is_error: Option<bool>,
+    /// What to do with URLs and email addresses in the text.
+    #[serde(default, skip_serializing_if = "UrlHandling::is_keep")]
+    pub url_handling: UrlHandling,
+    /// How line breaks in the text are read.
+    #[serde(default, skip_serializing_if = "NewlineMode::is_lines")]
+    pub newline_mode: NewlineMode,
+    /// What to do with parenthesized asides such as "（補足）".
+    #[serde(default, skip_serializing_if = "ParentheticalHandling::is_keep")]
+    pub parenthetical_handling: ParentheticalHandling,
+    /// Also serve the HTTP mode on this localhost port next to MCP. Needs a build with
+    /// the `http` feature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_port: Option<u16>,
+    /// Speaker (style) IDs pinned at the top of the speaker lists in speak-config.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub favorite_speakers: Vec<u32>,
+    /// Let `config.session.json` (written by speak-config's "Apply") override keys of
+    /// this file. The server deletes that file at startup, so overrides last until the
+    /// next restart.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub use_session_config: bool,
+    /// Language of the server's response messages: "ja" (default) or "en". Unknown
+    /// values fall back to English.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_language: Option<String>,
+    /// Longest `text` the speak tools accept, in characters. Longer text is rejected
+    /// unless `truncate_over_limit` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_text_chars: Option<usize>,
+    /// Instead of rejecting text over `max_text_chars`, cut it at a sentence boundary
+    /// and say that the rest was omitted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncate_over_limit: bool,
+    /// Rewrite markdown tables and lists into sentences before synthesis.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub linearize_markdown: bool,
+    /// Speak markdown emphasis (`**x**`, `*x*`) with raised intonation and pitch by
+    /// synthesizing each emphasized span separately. When off the markers are dropped.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub emphasis_prosody: bool,
+    /// Multiplies `intonationScale` inside emphasis (default 1.5).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emphasis_intonation_scale: Option<f32>,
+    /// Added to `pitchScale` inside emphasis (default 0.03).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emphasis_pitch_offset: Option<f32>,
+    /// Read runs of digits one digit at a time by default (per-call `spell_numbers`
+    /// overrides it).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub spell_numbers: bool,
+    /// Regexes whose matches are always read digit by digit, e.g. order numbers.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spell_number_patterns: Vec<String>,
+    /// Spoken before every utterance, e.g. a short cue word. Empty disables it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text_prefix: String,
+    /// Spoken after every utterance. Empty disables it.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text_suffix: String,
+}
+
+/// An additional VOICEVOX-compatible endpoint registered under a name in `engines`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EngineConfig {
+    pub base_url: String,
+    #[serde(default)]
+    pub kind: EngineKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_speaker: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_speed: Option<f32>,
+}
+
+/// Default prosody for one speaker, used when a call doesn't specify it.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SpeakerOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Absolute `pitchScale` (engine default is 0.0).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f32>,
+    /// Absolute `intonationScale` (engine default is 1.0).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intonation: Option<f32>,
+}
+
+/// TLS options for one engine.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct EngineTls {
+    /// Skip certificate verification entirely. Anyone on the network path can then
+    /// impersonate the engine and read the text sent to it; prefer `ca_cert_path`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub accept_invalid_certs: bool,
+    /// PEM file with an extra CA (or the self-signed certificate itself) to trust for
+    /// this engine, in addition to the system roots.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert_path: Option<String>,
+}
+
+/// File format for saved audio.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveFormat {
+    #[default]
+    Wav,
+    Mp3,
+}
+
+/// What a speak call does with the synthesized audio.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioDisposition {
+    /// Play it on this machine.
+    #[default]
+    Play,
+    /// Send it back to the client (base64 in `_meta.audio`) without playing it.
+    Return,
+    /// Play it and send it back.
+    Both,
+}
+
+impl AudioDisposition {
+    pub fn plays(self) -> bool {
+        self != AudioDisposition::Return
+    }
+
+    pub fn returns(self) -> bool {
+        self != AudioDisposition::Play
+    }
+}
+
+/// How URLs and email addresses are spoken.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlHandling {
+    /// Read as-is (character by character on most engines).
+    #[default]
+    Keep,
+    /// Remove them.
+    Drop,
+    /// Replace with "リンク" / "メールアドレス".
+    Placeholder,
+    /// Read only the domain, e.g. `example.com`.
+    Domain,
+}
+
+impl UrlHandling {
+    fn is_keep(&self) -> bool {
+        *self == UrlHandling::Keep
+    }
+}
+
+/// One entry of `autodiscover_ports`: a port or an inclusive `"from-to"` range.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum PortSpec {
+    Port(u16),
+    Range(String),
+}
+
+impl PortSpec {
+    /// The ports this entry covers; a malformed range covers none.
+    pub fn ports(&self) -> Vec<u16> {
+        match self {
+            PortSpec::Port(port) => vec![*port],
+            PortSpec::Range(range) => {
+                let bounds = range.split_once('-').and_then(|(from, to)| {
+                    Some((
+                        from.trim().parse::<u16>().ok()?,
+                        to.trim().parse::<u16>().ok()?,
+                    ))
+                });
+                match bounds {
+                    Some((from, to)) => (from..=to).collect(),
+                    None => {
+                        warn_once(format!("autodiscover_ports: invalid range {:?}", range));
+                        Vec::new()
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How line breaks in the text are read.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NewlineMode {
+    /// Every line break is a sentence break; runs of blank lines are squeezed to one.
+    #[default]
+    Lines,
+    /// Single line breaks are wrapping and get joined; blank lines separate paragraphs,
+    /// which become chunk boundaries.
+    Paragraphs,
+    /// Line breaks are passed to the engine untouched.
+    Raw,
+}
+
+impl NewlineMode {
+    fn is_lines(&self) -> bool {
+        *self == NewlineMode::Lines
+    }
+}
+
+/// What a new speak call does while another utterance is playing or queued.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaybackMode {
+    /// Wait for its turn.
+    #[default]
+    Queue,
+    /// Stop the current utterance, discard the waiting ones and play right away.
+    Interrupt,
+    /// Don't speak at all.
+    Drop,
+}
+
+impl PlaybackMode {
+    fn is_queue(&self) -> bool {
+        *self == PlaybackMode::Queue
+    }
+}
+
+/// What a speak call does when its engine can't be reached.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OfflineBehavior {
+    /// Fail the call.
+    #[default]
+    Error,
+    /// Succeed with a note that nothing was spoken.
+    Skip,
+}
+
+impl OfflineBehavior {
+    fn is_error(&self) -> bool {
+        *self == OfflineBehavior::Error
+    }
+}
+
+/// How text in `()` / `（）` is spoken.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ParentheticalHandling {
+    /// Read as-is, brackets included.
+    #[default]
+    Keep,
+    /// Remove the aside together with its brackets.
+    Drop,
+    /// Drop the brackets and set the aside off with short pauses.
+    Soften,
+}
+
+impl ParentheticalHandling {
+    fn is_keep(&self) -> bool {
+        *self == ParentheticalHandling::Keep
+    }
+}
+
+/// A named engine + speaker + prosody bundle from `named_voices`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NamedVoice {
+    /// Registered engine name, e.g. `"voicevox"` or a key of `engines`.
+    pub engine: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    /// Absolute `pitchScale`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    fn parse(self, content: &str) -> Result<AppConfig> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::from_str(content)?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+        })
+    }
+
+    fn serialize(self, config: &AppConfig) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(config)?,
+            ConfigFormat::Toml => toml::to_string_pretty(config)?,
+        })
+    }
+}
+
+/// Picks `config.toml` if it exists in `dir`, otherwise `config.json` (the default).
+fn config_file_in(mut dir: PathBuf) -> PathBuf {
+    dir.push("config.toml");
+    if dir.exists() {
+        return dir;
+    }
+    dir.set_file_name("config.json");
+    dir
+}
+
+impl AppConfig {
+    pub fn speaker_override(&self, speaker_id: u32) -> Option<&SpeakerOverride> {
+        self.speaker_overrides.get(&speaker_id.to_string())
+    }
+
+    /// Sets the default speaker of a built-in engine or an `engines` entry. Returns
+    /// false if no engine has that name.
+    pub fn set_default_speaker(&mut self, engine_name: &str, speaker: u32) -> bool {
+        let builtin = Engine::ALL
+            .into_iter()
+            .find(|e| e.name().eq_ignore_ascii_case(engine_name));
+        match builtin {
+            Some(Engine::Voicevox) => self.voicevox_default_speaker = Some(speaker),
+            Some(Engine::Aivis) => self.aivis_default_speaker = Some(speaker),
+            None => match self
+                .engines
+                .iter_mut()
+                .find(|(name, _)| name.eq_ignore_ascii_case(engine_name))
+            {
+                Some((_, entry)) => entry.default_speaker = Some(speaker),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    /// Sets the default speed of a built-in engine or an `engines` entry. Returns false
+    /// if no engine has that name.
+    pub fn set_default_speed(&mut self, engine_name: &str, speed: f32) -> bool {
+        let builtin = Engine::ALL
+            .into_iter()
+            .find(|e| e.name().eq_ignore_ascii_case(engine_name));
+        match builtin {
+            Some(Engine::Voicevox) => self.voicevox_default_speed = Some(speed),
+            Some(Engine::Aivis) => self.aivis_default_speed = Some(speed),
+            None => match self
+                .engines
+                .iter_mut()
+                .find(|(name, _)| name.eq_ignore_ascii_case(engine_name))
+            {
+                Some((_, entry)) => entry.default_speed = Some(speed),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    /// The engine's entry in `engine_max_concurrent_synthesis`, else
+    /// `max_concurrent_synthesis`. Engine names are matched case-insensitively.
+    pub fn max_concurrent_synthesis_for(&self, engine_name: &str) -> Option<usize> {
+        self.engine_max_concurrent_synthesis
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(engine_name))
+            .map(|(_, limit)| *limit)
+            .or(self.max_concurrent_synthesis)
+    }
+
+    /// Engine names are matched case-insensitively, like `find_engine`.
+    pub fn fallback_speaker(&self, engine_name: &str) -> Option<u32> {
+        self.fallback_speakers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(engine_name))
+            .map(|(_, speaker)| *speaker)
+    }
+
+    /// The engine's `default_audio_disposition`, `play` when it has none. Engine names
+    /// are matched case-insensitively.
+    pub fn audio_disposition(&self, engine_name: &str) -> AudioDisposition {
+        self.default_audio_disposition
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(engine_name))
+            .map(|(_, disposition)| *disposition)
+            .unwrap_or_default()
+    }
+
+    /// Engine names are matched case-insensitively, like `find_engine`.
+    pub fn default_save_format(&self, engine_name: &str) -> Option<SaveFormat> {
+        self.default_save_formats
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(engine_name))
+            .map(|(_, format)| *format)
+    }
+}
+
+fn exe_dir_config_path() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    Some(config_file_in(exe.parent()?.to_path_buf()))
+}
+
+/// Prints each distinct warning once per process, so a config problem is reported
+/// without repeating on every per-call reload.
+fn warn_once(message: String) {
+    static WARNED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+    let mut warned = WARNED.lock().unwrap_or_else(|e| e.into_inner());
+    if !warned.contains(&message) {
+        eprintln!("⚠️ speak-mcp config: {}", message);
+        warned.insert(message);
+    }
+}
+
+/// Resolves `~/speak-mcp/config.{toml,json}`.
+///
+/// Without a home directory it falls back, in order, to `$XDG_CONFIG_HOME/speak-mcp/`,
+/// the executable's directory and finally the current directory, warning once about
+/// which one was picked.
+pub fn get_config_path() -> PathBuf {
+    if let Some(mut home) = dirs::home_dir() {
+        home.push("speak-mcp");
+        return config_file_in(home);
+    }
+
+    let xdg = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    if let Some(xdg) = xdg {
+        let path = config_file_in(xdg.join("speak-mcp"));
+        warn_once(format!(
+            "home directory not found, using $XDG_CONFIG_HOME: {}",
+            path.display()
+        ));
+        return path;
+    }
+
+    if let Some(path) = exe_dir_config_path() {
+        warn_once(format!(
+            "home directory not found, using the executable's directory: {}",
+            path.display()
+        ));
+        return path;
+    }
+
+    let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let path = config_file_in(cwd);
+    warn_once(format!(
+        "home directory and executable path unavailable, using the current directory: {}",
+        path.display()
+    ));
+    path
+}
+
+/// Reads and parses a config file, returning `None` if it is missing or malformed.
+/// The format is chosen from the file extension (`.toml`, anything else is JSON).
+pub fn read_config(path: &Path) -> Option<AppConfig> {
+    try_read_config(path).ok().flatten()
+}
+
+/// Like `read_config`, but a file that exists and can't be read or parsed is an error
+/// instead of `None`, so writers can refuse to replace it.
+fn try_read_config(path: &Path) -> Result<Option<AppConfig>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow::anyhow!("cannot read {}: {}", path.display(), e)),
+    };
+    ConfigFormat::of(path)
+        .parse(&content)
+        .map(Some)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "{} is not a valid config ({}); fix or remove it before changing settings",
+                path.display(),
+                e
+            )
+        })
+}
+
+/// The effective config: the persistent file, overlaid with the session overrides
+/// written by speak-config's "Apply" when `use_session_config` is set.
+pub fn load_config() -> AppConfig {
+    let config = load_persistent_config();
+    if !config.use_session_config {
+        return config;
+    }
+    apply_session_overrides(config)
+}
+
+/// `config.session.json` next to the config file. It holds only the keys to override
+/// (e.g. `{"voicevox_default_speaker": 3}`); everything else comes from the config file.
+pub fn session_config_path() -> PathBuf {
+    get_config_path().with_file_name("config.session.json")
+}
+
+fn apply_session_overrides(config: AppConfig) -> AppConfig {
+    let Ok(content) = fs::read_to_string(session_config_path()) else {
+        return config;
+    };
+    let overrides = match serde_json::from_str::<serde_json::Value>(&content) {
+        Ok(serde_json::Value::Object(overrides)) => overrides,
+        _ => {
+            warn_once("config.session.json is not a JSON object; ignoring it".to_string());
+            return config;
+        }
+    };
+    let Ok(serde_json::Value::Object(mut merged)) = serde_json::to_value(&config) else {
+        return config;
+    };
+    merged.extend(overrides);
+    serde_json::from_value(serde_json::Value::Object(merged)).unwrap_or_else(|e| {
+        warn_once(format!(
+            "config.session.json is invalid ({}); ignoring it",
+            e
+        ));
+        config
+    })
+}
+
+/// Replaces the session overrides with `overrides`.
+pub fn write_session_config(overrides: serde_json::Map<String, serde_json::Value>) -> Result<()> {
+    let path = session_config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(&overrides)?;
+    write_atomically(&path, content.as_bytes())
+}
+
+/// Removes the session overrides, if any, so the persistent config applies again.
+pub fn clear_session_config() -> Result<()> {
+    match fs::remove_file(session_config_path()) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The config file alone, without session overrides. Writers start from this so a
+/// temporary override is never persisted by accident.
+fn load_persistent_config() -> AppConfig {
+    let path = get_config_path();
+    if let Some(config) = read_config(&path) {
+        return config;
+    }
+
+    // Fallback check for local config if home one failed or didn't exist
+    if let Some(local_path) = exe_dir_config_path() {
+        if path != local_path {
+            if let Some(config) = read_config(&local_path) {
+                return config;
+            }
+        }
+    }
+
+    AppConfig::default()
+}
+
+/// The base for `update_config`: like `load_persistent_config`, except that a malformed
+/// file is an error, because writing defaults over it would silently lose every setting.
+fn persistent_config_for_update(path: &Path) -> Result<AppConfig> {
+    if let Some(config) = try_read_config(path)? {
+        return Ok(config);
+    }
+    if let Some(local_path) = exe_dir_config_path().filter(|local| local != path) {
+        if let Some(config) = try_read_config(&local_path)? {
+            return Ok(config);
+        }
+    }
+    Ok(AppConfig::default())
+}
+
+pub fn save_config_to_file(config: &AppConfig) -> Result<()> {
+    let _guard = CONFIG_WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    write_config(&get_config_path(), config)
+}
+
+fn write_config(path: &Path, config: &AppConfig) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write back in the same format the file was loaded from
+    let content = ConfigFormat::of(path).serialize(config)?;
+    write_atomically(path, content.as_bytes())?;
+    Ok(())
+}
+
+/// Writes `config` to `path` in the format its extension names (`.toml`, otherwise JSON).
+/// An existing file is only replaced when `overwrite` is set.
+pub fn export_config(config: &AppConfig, path: &Path, overwrite: bool) -> Result<()> {
+    if !overwrite && path.exists() {
+        anyhow::bail!("{} already exists", path.display());
+    }
+    let content = ConfigFormat::of(path).serialize(config)?;
+    write_atomically(path, content.as_bytes())
+}
+
+/// Writes to a sibling temp file and renames it over `path`, so a reader in the other
+/// binary (the server reloads per call, the GUI on refresh) never sees a half-written
+/// file. The rename stays on one filesystem because the temp file shares the directory.
+fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    // Unique per call as well as per process: session and export writes don't take
+    // the config lock, so two threads may write at once.
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = path.with_file_name(tmp_name);
+
+    fs::write(&tmp_path, content)?;
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Loads the current config, applies `f` and writes the result back while holding
+/// the write lock, so concurrent updates never lose each other's changes. A config file
+/// that doesn't parse is left alone and reported as an error.
+pub fn update_config<F: FnOnce(&mut AppConfig)>(f: F) -> Result<AppConfig> {
+    update_config_at(&get_config_path(), f)
+}
+
+fn update_config_at<F: FnOnce(&mut AppConfig)>(path: &Path, f: F) -> Result<AppConfig> {
+    let _guard = CONFIG_WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut config = persistent_config_for_update(path)?;
+    f(&mut config);
+    write_config(path, &config)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, unique to this test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("speak-common-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn engine_defaults_reach_builtins_and_engines_entries() {
+        let mut config = AppConfig::default();
+        config.engines.insert(
+            "Coeiroink".to_string(),
+            EngineConfig {
+                base_url: "http://localhost:50032".to_string(),
+                kind: EngineKind::default(),
+                default_speaker: None,
+                default_speed: None,
+            },
+        );
+
+        assert!(config.set_default_speaker("AIVIS", 888753760));
+        assert!(config.set_default_speed("voicevox", 1.2));
+        assert!(config.set_default_speaker("coeiroink", 3));
+        assert!(config.set_default_speed("coeiroink", 0.9));
+        assert!(!config.set_default_speaker("port50021", 1));
+        assert!(!config.set_default_speed("port50021", 1.0));
+
+        assert_eq!(config.aivis_default_speaker, Some(888753760));
+        assert_eq!(config.voicevox_default_speed, Some(1.2));
+        let entry = &config.engines["Coeiroink"];
+        assert_eq!(entry.default_speaker, Some(3));
+        assert_eq!(entry.default_speed, Some(0.9));
+    }
+
+    #[test]
+    fn update_refuses_to_overwrite_a_malformed_config() {
+        let path = temp_dir("malformed").join("config.json");
+        let broken = "{ \"voicevox_default_speaker\": 3, oops";
+        fs::write(&path, broken).unwrap();
+
+        let result = update_config_at(&path, |config| config.log_text = true);
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+    }
+
+    #[test]
+    fn concurrent_updates_and_reads_never_tear_or_lose_writes() {
+        const WRITERS: usize = 8;
+        const UPDATES: usize = 25;
+        let path = temp_dir("hammer").join("config.json");
+        write_config(&path, &AppConfig::default()).unwrap();
+        let done = std::sync::atomic::AtomicBool::new(false);
+        let (path, done) = (&path, &done);
+
+        std::thread::scope(|scope| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(move || {
+                        let mut reads = 0;
+                        loop {
+                            // Any torn or partial file fails to parse.
+                            let config = try_read_config(path).unwrap();
+                            assert!(config.is_some(), "config file vanished mid-write");
+                            reads += 1;
+                            if done.load(Ordering::Relaxed) {
+                                return reads;
+                            }
+                        }
+                    })
+                })
+                .collect();
+            let writers: Vec<_> = (0..WRITERS)
+                .map(|writer| {
+                    scope.spawn(move || {
+                        for update in 0..UPDATES {
+                            update_config_at(path, |config| {
+                                config.speaker_overrides.insert(
+                                    format!("{}-{}", writer, update),
+                                    SpeakerOverride::default(),
+                                );
+                            })
+                            .unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+            for reader in readers {
+                assert!(reader.join().unwrap() > 0);
+            }
+        });
+
+        let config = read_config(path).unwrap();
+        assert_eq!(config.speaker_overrides.len(), WRITERS * UPDATES);
+    }
+
+    #[test]
+    fn update_creates_a_missing_config() {
+        let path = temp_dir("missing").join("config.json");
+
+        update_config_at(&path, |config| config.log_text = true).unwrap();
+
+        assert!(read_config(&path).unwrap().log_text);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::AppConfig;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Mutex;
+
+/// API family of a registered engine. Only VOICEVOX-compatible HTTP engines exist today.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineKind {
+    #[default]
+    Voicevox,
+}
+
+/// A fully resolved engine to synthesize with: one of the built-ins or an entry from
+/// `engines` in config.
+#[derive(Debug, Clone)]
+pub struct EngineTarget {
+    pub name: String,
+    pub display_name: String,
+    pub kind: EngineKind,
+    pub base_url: String,
+    pub default_speaker: Option<u32>,
+    pub default_speed: Option<f32>,
+}
+
+/// Engines found by the server's startup port scan (`autodiscover_ports`). They are
+/// kept in memory only, for the life of the process.
+static DISCOVERED: Mutex<Vec<EngineTarget>> = Mutex::new(Vec::new());
+
+/// Adds an engine found by discovery to the registry.
+pub fn register_discovered(engine: EngineTarget) {
+    DISCOVERED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(engine);
+}
+
+/// All engines the server can use: the built-ins first, then the config entries in
+/// name order, then discovered engines. A config entry reusing a built-in name is
+/// ignored, as is a discovered engine whose URL is already registered.
+pub fn registered_engines(config: &AppConfig) -> Vec<EngineTarget> {
+    let mut engines: Vec<EngineTarget> = Engine::ALL.iter().map(|e| e.target(config)).collect();
+    for (name, entry) in &config.engines {
+        if engines.iter().any(|e| e.name == *name) {
+            continue;
+        }
+        engines.push(EngineTarget {
+            name: name.clone(),
+            display_name: name.clone(),
+            kind: entry.kind,
+            base_url: entry.base_url.trim().trim_end_matches('/').to_string(),
+            default_speaker: entry.default_speaker,
+            default_speed: entry.default_speed,
+        });
+    }
+    for engine in DISCOVERED.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        if engines
+            .iter()
+            .any(|e| e.name == engine.name || e.base_url == engine.base_url)
+        {
+            continue;
+        }
+        engines.push(engine.clone());
+    }
+    engines
+}
+
+/// Looks up a registered engine by name (case-insensitive).
+pub fn find_engine(config: &AppConfig, name: &str) -> Option<EngineTarget> {
+    registered_engines(config)
+        .into_iter()
+        .find(|e| e.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// The VOICEVOX-compatible engines speak-mcp talks to over HTTP.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    Voicevox,
+    Aivis,
+}
+
+impl Engine {
+    pub const ALL: [Engine; 2] = [Engine::Voicevox, Engine::Aivis];
+
+    /// Registry name, matching the serde representation.
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Voicevox => "voicevox",
+            Engine::Aivis => "aivis",
+        }
+    }
+
+    pub fn target(self, config: &AppConfig) -> EngineTarget {
+        EngineTarget {
+            name: self.name().to_string(),
+            display_name: self.display_name().to_string(),
+            kind: EngineKind::Voicevox,
+            base_url: self.base_url(config),
+            default_speaker: self.default_speaker(config),
+            default_speed: self.default_speed(config),
+        }
+    }
+
+    /// The engine's stock port, used when the config doesn't set one.
+    pub fn default_port(self) -> u16 {
+        match self {
+            Engine::Voicevox => 50021,
+            Engine::Aivis => 10101,
+        }
+    }
+
+    pub fn port(self, config: &AppConfig) -> u16 {
+        let configured = match self {
+            Engine::Voicevox => config.voicevox_port,
+            Engine::Aivis => config.aivis_port,
+        };
+        configured.unwrap_or_else(|| self.default_port())
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Engine::Voicevox => "VOICEVOX",
+            Engine::Aivis => "Aivis Speech",
+        }
+    }
+
+    /// Environment variable that overrides the engine URL, e.g. `SPEAK_MCP_VOICEVOX_URL`.
+    pub fn url_env_var(self) -> &'static str {
+        match self {
+            Engine::Voicevox => "SPEAK_MCP_VOICEVOX_URL",
+            Engine::Aivis => "SPEAK_MCP_AIVIS_URL",
+        }
+    }
+
+    /// Resolves the engine's base URL without a trailing slash.
+    ///
+    /// Precedence: environment variable > `*_base_url` in config > `http://localhost:<*_port>`.
+    /// Both speak-mcp and speak-config go through this so they always hit the same endpoint.
+    pub fn base_url(self, config: &AppConfig) -> String {
+        let configured = match self {
+            Engine::Voicevox => config.voicevox_base_url.as_deref(),
+            Engine::Aivis => config.aivis_base_url.as_deref(),
+        };
+        env::var(self.url_env_var())
+            .ok()
+            .filter(|url| !url.trim().is_empty())
+            .or_else(|| configured.map(str::to_string))
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .unwrap_or_else(|| format!("http://localhost:{}", self.port(config)))
+    }
+
+    pub fn default_speaker(self, config: &AppConfig) -> Option<u32> {
+        match self {
+            Engine::Voicevox => config.voicevox_default_speaker,
+            Engine::Aivis => config.aivis_default_speaker,
+        }
+    }
+
+    pub fn default_speed(self, config: &AppConfig) -> Option<f32> {
+        match self {
+            Engine::Voicevox => config.voicevox_default_speed,
+            Engine::Aivis => config.aivis_default_speed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+
+    fn entry(port: u16) -> EngineConfig {
+        EngineConfig {
+            base_url: format!("http://127.0.0.1:{}", port),
+            kind: EngineKind::Voicevox,
+            default_speaker: None,
+            default_speed: None,
+        }
+    }
+
+    #[test]
+    fn engine_list_order_is_deterministic() {
+        let mut config = AppConfig::default();
+        config.engines.insert("zeta".to_string(), entry(50100));
+        config.engines.insert("alpha".to_string(), entry(50101));
+        let names = |config: &AppConfig| -> Vec<String> {
+            registered_engines(config)
+                .into_iter()
+                .map(|e| e.name)
+                .collect()
+        };
+
+        let first = names(&config);
+        assert_eq!(first, ["voicevox", "aivis", "alpha", "zeta"]);
+        assert_eq!(names(&config), first);
+    }
+}
@@ -0,0 +1,16 @@
+//! Shared pieces used by both the `speak-mcp` server and the `speak-config` GUI.
+
+pub mod config;
+pub mod engine;
+pub mod process;
+pub mod text;
+
+pub use config::{
+    clear_session_config, export_config, get_config_path, load_config, read_config,
+    save_config_to_file, session_config_path, update_config, write_session_config, AppConfig,
+    AudioDisposition, EngineConfig, EngineTls, NamedVoice, NewlineMode, OfflineBehavior,
+    ParentheticalHandling, PlaybackMode, PortSpec, SaveFormat, SpeakerOverride, UrlHandling,
+};
+pub use engine::{
+    find_engine, register_discovered, registered_engines, Engine, EngineKind, EngineTarget,
+};
@@ -0,0 +1,21 @@
+//! Errors for external commands (say, afplay, PowerShell, ffmpeg, ...) that tell a
+//! missing executable apart from one that ran and failed.
+
+use std::process::ExitStatus;
+
+/// Wraps an error from spawning `program`. A missing executable gets its own message
+/// pointing at PATH instead of a bare "No such file or directory".
+pub fn spawn_error(program: &str, error: std::io::Error) -> anyhow::Error {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => anyhow::anyhow!(
+            "{} command not found; check that it is installed and on PATH",
+            program
+        ),
+        _ => anyhow::anyhow!("failed to run {}: {}", program, error),
+    }
+}
+
+/// Error for a command that started but exited unsuccessfully.
+pub fn exit_error(program: &str, status: ExitStatus) -> anyhow::Error {
+    anyhow::anyhow!("{} exited with {}", program, status)
+}
@@ -0,0 +1,40 @@
+/// Splits `text` into sentences after `。！？!?` and line breaks, keeping the punctuation
+/// with its sentence. ASCII `.` is deliberately not a boundary so decimals and URLs
+/// stay intact. Whitespace-only pieces are dropped.
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        current.push(c);
+        if matches!(c, '。' | '！' | '？' | '!' | '?' | '\n') {
+            push_sentence(&mut sentences, &mut current);
+        }
+    }
+    push_sentence(&mut sentences, &mut current);
+    sentences
+}
+
+/// Splits `text` at blank lines into paragraphs, each with its inner line breaks kept.
+/// Whitespace-only pieces are dropped.
+pub fn split_paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            push_sentence(&mut paragraphs, &mut current);
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+    }
+    push_sentence(&mut paragraphs, &mut current);
+    paragraphs
+}
+
+fn push_sentence(sentences: &mut Vec<String>, current: &mut String) {
+    let sentence = current.trim();
+    if !sentence.is_empty() {
+        sentences.push(sentence.to_string());
+    }
+    current.clear();
+}
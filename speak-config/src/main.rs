@@ -1,11 +1,20 @@
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use slint::{Model, SharedString, VecModel};
-use std::env;
-use std::fs;
-use std::path::PathBuf;
+use serde::Deserialize;
+use slint::{Model, ModelRc, SharedString, VecModel};
+use speak_common::process;
+use speak_common::text::split_sentences;
+use speak_common::{
+    clear_session_config, get_config_path, read_config, update_config, write_session_config,
+    AppConfig, Engine,
+};
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
 use std::rc::Rc;
+use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
 use tokio::runtime::Runtime;
 
 slint::include_modules!();
@@ -22,89 +31,142 @@ struct SpeakerInfo {
     styles: Vec<StyleInfo>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
-struct AppConfig {
-    voicevox_default_speaker: Option<u32>,
-    aivis_default_speaker: Option<u32>,
-    macos_default_voice: Option<String>,
-}
+/// A combo box entry: label and speaker ID. The separator between pinned favorites and
+/// the full list has no ID.
+type SpeakerOption = (String, Option<u32>);
+
+const FAVORITE_MARK: &str = "★ ";
+const FAVORITES_SEPARATOR: &str = "──────────";
 
 struct AppState {
-    voicevox_options: Vec<(String, u32)>, // (Display Name, ID)
-    aivis_options: Vec<(String, u32)>,
+    // Every style the engine reported, as (label, ID), before favorites are pinned
+    voicevox_speakers: Vec<(String, u32)>,
+    aivis_speakers: Vec<(String, u32)>,
+    voicevox_options: Vec<SpeakerOption>,
+    aivis_options: Vec<SpeakerOption>,
+    // Indices into *_options that are currently shown in each combo box
+    voicevox_visible: Vec<usize>,
+    aivis_visible: Vec<usize>,
     config: AppConfig,
 }
 
-fn get_config_path() -> PathBuf {
-    // Priority: ~/speak-mcp/config.json
-    if let Some(mut home) = dirs::home_dir() {
-        home.push("speak-mcp");
-        // Ensure directory exists if we are going to write (though get_path is simple getter)
-        // We will handle directory creation in save if needed, but here just return path.
-        if !home.exists() {
-            let _ = std::fs::create_dir_all(&home);
-        }
-        home.push("config.json");
-        return home;
-    }
-
-    let mut exe_path = env::current_exe()
-        .map(|p| p.parent().map(|p| p.to_path_buf()).unwrap_or_default())
-        .unwrap_or_default();
-    exe_path.push("config.json");
-    exe_path
-}
-
 fn load_config() -> AppConfig {
     let path = get_config_path();
     println!("Loading config from: {:?}", path);
 
-    if let Ok(content) = fs::read_to_string(&path) {
-        if let Ok(config) = serde_json::from_str(&content) {
-            println!("Config loaded: {:?}", config);
-            return config;
-        }
-    } else {
-        println!("Config file not found or unreadable at {:?}", path);
-        // Fallback: Try current directory
-        if let Ok(cwd_content) = fs::read_to_string("config.json") {
-            if let Ok(config) = serde_json::from_str(&cwd_content) {
-                println!("Config loaded from CWD: {:?}", config);
-                return config;
-            }
-        }
+    if let Some(config) = read_config(&path) {
+        println!("Config loaded: {:?}", config);
+        return config;
+    }
+
+    println!("Config file not found or unreadable at {:?}", path);
+    // Fallback: Try current directory
+    if let Some(config) = read_config(Path::new("config.json")) {
+        println!("Config loaded from CWD: {:?}", config);
+        return config;
     }
     println!("Using default config");
     AppConfig::default()
 }
 
-fn save_config_to_file(config: &AppConfig) -> Result<()> {
-    let path = get_config_path();
-    println!("Saving config to: {:?}", path);
+fn fetch_speakers_blocking(base_url: &str) -> Option<Vec<SpeakerInfo>> {
+    let url = format!("{}/speakers", base_url);
+    // Called from a background thread (see `refresh_speakers`), so blocking is fine.
+    match reqwest::blocking::get(&url) {
+        Ok(resp) => resp.json::<Vec<SpeakerInfo>>().ok(),
+        Err(_) => None,
+    }
+}
+
+const TEST_TEXT: &str =
+    "こんにちは。これは音声のテストです。ストリーミングでは最初の文から再生が始まります。";
+
+fn synthesize_blocking(base_url: &str, text: &str, speaker: u32) -> Result<Vec<u8>> {
+    let client = reqwest::blocking::Client::new();
+    let query: serde_json::Value = client
+        .post(format!("{}/audio_query", base_url))
+        .query(&[("text", text), ("speaker", &speaker.to_string())])
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let wav = client
+        .post(format!("{}/synthesis", base_url))
+        .query(&[("speaker", speaker.to_string())])
+        .json(&query)
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+    Ok(wav.to_vec())
+}
 
-    let content = serde_json::to_string_pretty(config)?;
-    fs::write(&path, content)?;
+fn play_wav_blocking(wav: &[u8]) -> Result<()> {
+    let mut temp_file = tempfile::Builder::new().suffix(".wav").tempfile()?;
+    temp_file.write_all(wav)?;
+    let path = temp_file.path();
+
+    let (mut cmd, program) = if cfg!(target_os = "windows") {
+        let script = format!(
+            "(New-Object System.Media.SoundPlayer '{}').PlaySync()",
+            path.display()
+        );
+        let mut cmd = Command::new("powershell");
+        cmd.arg("-Command").arg(script);
+        (cmd, "powershell")
+    } else {
+        let mut cmd = Command::new("afplay");
+        cmd.arg(path);
+        (cmd, "afplay")
+    };
+    let status = cmd.status().map_err(|e| process::spawn_error(program, e))?;
+    if !status.success() {
+        return Err(process::exit_error(program, status));
+    }
     Ok(())
 }
 
-fn fetch_speakers_blocking(port: u16) -> Option<Vec<SpeakerInfo>> {
-    let url = format!("http://localhost:{}/speakers", port);
-    // Use blocking client for simplicity in this thread or use runtime
-    // Since we are inside Slint callback usually, we might want to spawn a thread or use blocking.
-    // Let's use simple blocking reqwest here to keep it simple,
-    // though for UI responsiveness async is better.
-    // Given the simplicity, blocking might freeze UI for a fraction of a second, which is acceptable for this tool.
-    match reqwest::blocking::get(&url) {
-        Ok(resp) => resp.json::<Vec<SpeakerInfo>>().ok(),
-        Err(_) => None,
+/// Speaks `TEST_TEXT` and returns the time until audio started, in milliseconds.
+///
+/// In streaming mode the text is synthesized sentence by sentence on a separate thread
+/// so playback can start as soon as the first sentence is ready.
+fn run_test_speech(base_url: String, speaker: u32, streaming: bool) -> Result<u128> {
+    let started = Instant::now();
+
+    if !streaming {
+        let wav = synthesize_blocking(&base_url, TEST_TEXT, speaker)?;
+        let first_audio = started.elapsed().as_millis();
+        play_wav_blocking(&wav)?;
+        return Ok(first_audio);
     }
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for sentence in split_sentences(TEST_TEXT) {
+            let result = synthesize_blocking(&base_url, &sentence, speaker);
+            let failed = result.is_err();
+            if tx.send(result).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut first_audio = None;
+    for wav in rx {
+        let wav = wav?;
+        first_audio.get_or_insert_with(|| started.elapsed().as_millis());
+        play_wav_blocking(&wav)?;
+    }
+    first_audio.ok_or_else(|| anyhow::anyhow!("nothing was synthesized"))
 }
 
 fn main() -> Result<()> {
     let main_window = AppWindow::new()?;
     let state = Arc::new(Mutex::new(AppState {
+        voicevox_speakers: vec![],
+        aivis_speakers: vec![],
         voicevox_options: vec![],
         aivis_options: vec![],
+        voicevox_visible: vec![],
+        aivis_visible: vec![],
         config: load_config(),
     }));
 
@@ -126,87 +188,358 @@ fn main() -> Result<()> {
         let main_window = main_window_weak.unwrap();
         let mut state = state_weak.lock().unwrap();
 
-        let vv_id = if vv_idx >= 0 && (vv_idx as usize) < state.voicevox_options.len() {
-            Some(state.voicevox_options[vv_idx as usize].1)
-        } else {
-            None
+        // Combo box indices refer to the filtered list, so map them back to the options
+        let vv_id = selected_option(&state.voicevox_visible, vv_idx)
+            .and_then(|i| state.voicevox_options[i].1);
+        let aivis_id =
+            selected_option(&state.aivis_visible, aivis_idx).and_then(|i| state.aivis_options[i].1);
+
+        println!("Saving config to: {:?}", get_config_path());
+
+        // Merge into the file's current contents so settings changed by the server
+        // (e.g. via set_default_speed) since the GUI opened are not overwritten.
+        // The favorites separator has no ID; selecting it keeps the current default.
+        let result = update_config(|config| {
+            config.voicevox_default_speaker = vv_id.or(config.voicevox_default_speaker);
+            config.aivis_default_speaker = aivis_id.or(config.aivis_default_speaker);
+        });
+
+        // Saving commits the selection, so any earlier "Apply" preview is dropped.
+        let result = result.and_then(|config| clear_session_config().map(|_| config));
+
+        match result {
+            Ok(config) => {
+                state.config = config;
+                main_window.set_status_message("Settings saved successfully!".into());
+            }
+            Err(e) => main_window.set_status_message(format!("Error saving: {}", e).into()),
+        }
+    });
+
+    let main_window_weak = main_window.as_weak();
+    let state_weak = state.clone();
+    main_window.on_apply_temp(move |vv_idx, aivis_idx| {
+        let main_window = main_window_weak.unwrap();
+        let state = state_weak.lock().unwrap();
+
+        let vv_id = selected_option(&state.voicevox_visible, vv_idx)
+            .and_then(|i| state.voicevox_options[i].1);
+        let aivis_id =
+            selected_option(&state.aivis_visible, aivis_idx).and_then(|i| state.aivis_options[i].1);
+
+        // Only the speakers go into the session file; config.json stays untouched
+        // until "Save Settings".
+        let mut overrides = serde_json::Map::new();
+        if let Some(id) = vv_id {
+            overrides.insert("voicevox_default_speaker".into(), id.into());
+        }
+        if let Some(id) = aivis_id {
+            overrides.insert("aivis_default_speaker".into(), id.into());
+        }
+
+        let message = match write_session_config(overrides) {
+            Ok(()) if state.config.use_session_config => {
+                "Applied until the server restarts (not saved).".to_string()
+            }
+            Ok(()) => "Applied, but the server ignores it until use_session_config is enabled."
+                .to_string(),
+            Err(e) => format!("Error applying: {}", e),
         };
+        main_window.set_status_message(message.into());
+    });
+
+    let main_window_weak = main_window.as_weak();
+    let state_weak = state.clone();
+    main_window.on_filter_voicevox(move |query| {
+        let main_window = main_window_weak.unwrap();
+        let mut state = state_weak.lock().unwrap();
+        let state = &mut *state;
+        let selected = selected_option(&state.voicevox_visible, main_window.get_voicevox_index());
+        state.voicevox_visible = filter_options(&state.voicevox_options, &query);
+        let (model, index) =
+            filtered_model(&state.voicevox_options, &state.voicevox_visible, selected);
+        main_window.set_voicevox_model(model);
+        main_window.set_voicevox_index(index);
+    });
+
+    let main_window_weak = main_window.as_weak();
+    let state_weak = state.clone();
+    main_window.on_filter_aivis(move |query| {
+        let main_window = main_window_weak.unwrap();
+        let mut state = state_weak.lock().unwrap();
+        let state = &mut *state;
+        let selected = selected_option(&state.aivis_visible, main_window.get_aivis_index());
+        state.aivis_visible = filter_options(&state.aivis_options, &query);
+        let (model, index) = filtered_model(&state.aivis_options, &state.aivis_visible, selected);
+        main_window.set_aivis_model(model);
+        main_window.set_aivis_index(index);
+    });
 
-        let aivis_id = if aivis_idx >= 0 && (aivis_idx as usize) < state.aivis_options.len() {
-            Some(state.aivis_options[aivis_idx as usize].1)
-        } else {
-            None
+    let main_window_weak = main_window.as_weak();
+    let state_weak = state.clone();
+    main_window.on_test_voicevox(move |vv_idx, streaming| {
+        let main_window = main_window_weak.unwrap();
+        let (base_url, speaker) = {
+            let state = state_weak.lock().unwrap();
+            let speaker = selected_option(&state.voicevox_visible, vv_idx)
+                .and_then(|i| state.voicevox_options[i].1)
+                .unwrap_or(1);
+            (Engine::Voicevox.base_url(&state.config), speaker)
         };
 
-        state.config.voicevox_default_speaker = vv_id;
-        state.config.aivis_default_speaker = aivis_id;
+        let mode = if streaming { "streaming" } else { "sync" };
+        main_window.set_status_message(format!("Testing ({})...", mode).into());
+
+        // Synthesis and playback block, so keep them off the UI thread.
+        let weak = main_window.as_weak();
+        thread::spawn(move || {
+            let message = match run_test_speech(base_url, speaker, streaming) {
+                Ok(ms) => format!("Test finished ({}): first audio after {} ms", mode, ms),
+                Err(e) => format!("Test failed: {}", e),
+            };
+            let _ = weak.upgrade_in_event_loop(move |window| {
+                window.set_status_message(message.into());
+            });
+        });
+    });
 
-        match save_config_to_file(&state.config) {
-            Ok(_) => main_window.set_status_message("Settings saved successfully!".into()),
-            Err(e) => main_window.set_status_message(format!("Error saving: {}", e).into()),
+    let main_window_weak = main_window.as_weak();
+    let state_weak = state.clone();
+    main_window.on_toggle_favorite_voicevox(move |vv_idx| {
+        let main_window = main_window_weak.unwrap();
+        let mut state = state_weak.lock().unwrap();
+        let state = &mut *state;
+        let Some(id) = favorite_candidate(&state.voicevox_options, &state.voicevox_visible, vv_idx)
+        else {
+            return;
+        };
+        match toggle_favorite(id) {
+            Ok(config) => state.config = config,
+            Err(e) => {
+                main_window.set_status_message(format!("Error saving: {}", e).into());
+                return;
+            }
         }
+        state.voicevox_options =
+            build_options(&state.voicevox_speakers, &state.config.favorite_speakers);
+        state.voicevox_visible =
+            filter_options(&state.voicevox_options, &main_window.get_voicevox_filter());
+        let (model, index) = filtered_model(
+            &state.voicevox_options,
+            &state.voicevox_visible,
+            Some(option_index(&state.voicevox_options, Some(id))),
+        );
+        main_window.set_voicevox_model(model);
+        main_window.set_voicevox_index(index);
+    });
+
+    let main_window_weak = main_window.as_weak();
+    let state_weak = state.clone();
+    main_window.on_toggle_favorite_aivis(move |aivis_idx| {
+        let main_window = main_window_weak.unwrap();
+        let mut state = state_weak.lock().unwrap();
+        let state = &mut *state;
+        let Some(id) = favorite_candidate(&state.aivis_options, &state.aivis_visible, aivis_idx)
+        else {
+            return;
+        };
+        match toggle_favorite(id) {
+            Ok(config) => state.config = config,
+            Err(e) => {
+                main_window.set_status_message(format!("Error saving: {}", e).into());
+                return;
+            }
+        }
+        state.aivis_options = build_options(&state.aivis_speakers, &state.config.favorite_speakers);
+        state.aivis_visible = filter_options(&state.aivis_options, &main_window.get_aivis_filter());
+        let (model, index) = filtered_model(
+            &state.aivis_options,
+            &state.aivis_visible,
+            Some(option_index(&state.aivis_options, Some(id))),
+        );
+        main_window.set_aivis_model(model);
+        main_window.set_aivis_index(index);
     });
 
     main_window.run()?;
     Ok(())
 }
 
-fn refresh_speakers(window: &AppWindow, state: &Arc<Mutex<AppState>>) {
-    let mut state = state.lock().unwrap();
-    window.set_status_message("Fetching speakers...".into());
-
-    // Fetch VOICEVOX
-    let mut vv_list = Vec::new();
-    let mut vv_options = Vec::new();
-    let mut vv_default_idx = 0;
-
-    // Add "Default/Auto" option
-    vv_list.push(SharedString::from("Default / Auto (ID: 1)"));
-    vv_options.push(("Default".to_string(), 1));
-
-    if let Some(speakers) = fetch_speakers_blocking(50021) {
-        for speaker in speakers {
-            for style in speaker.styles {
-                let label = format!("{} ({})", speaker.name, style.name);
-                vv_list.push(SharedString::from(&label));
-                vv_options.push((label, style.id));
-
-                if Some(style.id) == state.config.voicevox_default_speaker {
-                    vv_default_idx = vv_options.len() as i32 - 1;
-                }
-            }
-        }
+/// Builds the combo box entries: "Default / Auto", then favorites pinned in list order,
+/// a separator, and every style. Favorites appear twice, so IDs always come from the
+/// entry itself rather than from its position.
+fn build_options(speakers: &[(String, u32)], favorites: &[u32]) -> Vec<SpeakerOption> {
+    let mut options = vec![("Default / Auto (ID: 1)".to_string(), Some(1))];
+    let pinned: Vec<SpeakerOption> = speakers
+        .iter()
+        .filter(|(_, id)| favorites.contains(id))
+        .map(|(label, id)| (format!("{}{}", FAVORITE_MARK, label), Some(*id)))
+        .collect();
+    if !pinned.is_empty() {
+        options.extend(pinned);
+        options.push((FAVORITES_SEPARATOR.to_string(), None));
     }
-    state.voicevox_options = vv_options;
-    let vv_model = Rc::new(VecModel::from(vv_list));
-    window.set_voicevox_model(vv_model.into());
-    window.set_voicevox_index(vv_default_idx);
-
-    // Fetch Aivis
-    let mut aivis_list = Vec::new();
-    let mut aivis_options = Vec::new();
-    let mut aivis_default_idx = 0;
-
-    aivis_list.push(SharedString::from("Default / Auto (ID: 1)"));
-    aivis_options.push(("Default".to_string(), 1));
-
-    if let Some(speakers) = fetch_speakers_blocking(10101) {
-        for speaker in speakers {
-            for style in speaker.styles {
-                let label = format!("{} ({})", speaker.name, style.name);
-                aivis_list.push(SharedString::from(&label));
-                aivis_options.push((label, style.id));
-
-                if Some(style.id) == state.config.aivis_default_speaker {
-                    aivis_default_idx = aivis_options.len() as i32 - 1;
-                }
+    options.extend(
+        speakers
+            .iter()
+            .map(|(label, id)| (label.clone(), Some(*id))),
+    );
+    options
+}
+
+/// First entry with speaker `id` (the pinned one for favorites), or 0 for "Default / Auto".
+fn option_index(options: &[SpeakerOption], id: Option<u32>) -> usize {
+    options
+        .iter()
+        .skip(1)
+        .position(|(_, option_id)| id.is_some() && *option_id == id)
+        .map_or(0, |i| i + 1)
+}
+
+/// The speaker ID a favorite toggle applies to. "Default / Auto" and the separator
+/// can't be favorites.
+fn favorite_candidate(
+    options: &[SpeakerOption],
+    visible: &[usize],
+    combo_index: i32,
+) -> Option<u32> {
+    selected_option(visible, combo_index)
+        .filter(|&i| i != 0)
+        .and_then(|i| options[i].1)
+}
+
+/// Adds `id` to `favorite_speakers`, or removes it if it is already there.
+fn toggle_favorite(id: u32) -> Result<AppConfig> {
+    update_config(
+        |config| match config.favorite_speakers.iter().position(|&f| f == id) {
+            Some(pos) => {
+                config.favorite_speakers.remove(pos);
             }
+            None => config.favorite_speakers.push(id),
+        },
+    )
+}
+
+/// Maps a combo box index to an index into the full options list.
+fn selected_option(visible: &[usize], combo_index: i32) -> Option<usize> {
+    usize::try_from(combo_index)
+        .ok()
+        .and_then(|i| visible.get(i).copied())
+}
+
+/// Returns the indices of the options whose label contains `query` (case-insensitive).
+/// The leading "Default / Auto" entry is always kept.
+fn filter_options(options: &[SpeakerOption], query: &str) -> Vec<usize> {
+    let query = query.trim().to_lowercase();
+    options
+        .iter()
+        .enumerate()
+        .filter(|(i, (label, _))| *i == 0 || label.to_lowercase().contains(&query))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Builds the combo box model for the visible options and the index at which
+/// `selected` now appears (or 0 if it was filtered out).
+fn filtered_model(
+    options: &[SpeakerOption],
+    visible: &[usize],
+    selected: Option<usize>,
+) -> (ModelRc<SharedString>, i32) {
+    let labels: Vec<SharedString> = visible
+        .iter()
+        .map(|&i| SharedString::from(options[i].0.as_str()))
+        .collect();
+    let index = selected
+        .and_then(|selected| visible.iter().position(|&i| i == selected))
+        .unwrap_or(0) as i32;
+    (Rc::new(VecModel::from(labels)).into(), index)
+}
+
+/// Flattens speakers into one `(label, ID)` entry per style.
+fn style_list(speakers: Vec<SpeakerInfo>) -> Vec<(String, u32)> {
+    speakers
+        .into_iter()
+        .flat_map(|speaker| {
+            let name = speaker.name;
+            speaker
+                .styles
+                .into_iter()
+                .map(move |style| (format!("{} ({})", name, style.name), style.id))
+        })
+        .collect()
+}
+
+/// Fetches both speaker lists on a background thread so a slow engine doesn't freeze
+/// the window, then fills in the lists back on the UI thread.
+fn refresh_speakers(window: &AppWindow, state: &Arc<Mutex<AppState>>) {
+    window.set_status_message("Fetching speakers...".into());
+    let (vv_url, aivis_url) = {
+        let state = state.lock().unwrap();
+        (
+            Engine::Voicevox.base_url(&state.config),
+            Engine::Aivis.base_url(&state.config),
+        )
+    };
+
+    let weak = window.as_weak();
+    let state = state.clone();
+    thread::spawn(move || {
+        let voicevox = fetch_speakers_blocking(&vv_url)
+            .map(style_list)
+            .unwrap_or_default();
+        let aivis = fetch_speakers_blocking(&aivis_url)
+            .map(style_list)
+            .unwrap_or_default();
+
+        // The window may have been closed while the engines were being probed; the
+        // result is then dropped instead of touching a UI that no longer exists.
+        let delivered = slint::invoke_from_event_loop(move || match weak.upgrade() {
+            Some(window) => show_speakers(&window, &state, voicevox, aivis),
+            None => println!("Speaker list discarded: the window was closed"),
+        });
+        if delivered.is_err() {
+            println!("Speaker list discarded: the window was closed");
         }
-    }
-    state.aivis_options = aivis_options;
-    let aivis_model = Rc::new(VecModel::from(aivis_list));
-    window.set_aivis_model(aivis_model.into());
-    window.set_aivis_index(aivis_default_idx);
+    });
+}
+
+fn show_speakers(
+    window: &AppWindow,
+    state: &Arc<Mutex<AppState>>,
+    voicevox: Vec<(String, u32)>,
+    aivis: Vec<(String, u32)>,
+) {
+    let mut state = state.lock().unwrap();
+    let state = &mut *state;
+
+    state.voicevox_speakers = voicevox;
+    state.voicevox_options =
+        build_options(&state.voicevox_speakers, &state.config.favorite_speakers);
+    state.voicevox_visible = filter_options(&state.voicevox_options, &window.get_voicevox_filter());
+    let vv_default_idx = option_index(
+        &state.voicevox_options,
+        state.config.voicevox_default_speaker,
+    );
+    let (vv_model, vv_index) = filtered_model(
+        &state.voicevox_options,
+        &state.voicevox_visible,
+        Some(vv_default_idx),
+    );
+    window.set_voicevox_model(vv_model);
+    window.set_voicevox_index(vv_index);
+
+    state.aivis_speakers = aivis;
+    state.aivis_options = build_options(&state.aivis_speakers, &state.config.favorite_speakers);
+    state.aivis_visible = filter_options(&state.aivis_options, &window.get_aivis_filter());
+    let aivis_default_idx = option_index(&state.aivis_options, state.config.aivis_default_speaker);
+    let (aivis_model, aivis_index) = filtered_model(
+        &state.aivis_options,
+        &state.aivis_visible,
+        Some(aivis_default_idx),
+    );
+    window.set_aivis_model(aivis_model);
+    window.set_aivis_index(aivis_index);
 
     window.set_status_message("Ready".into());
 }
@@ -0,0 +1,108 @@
+//! In-memory LRU cache of synthesized audio.
+//!
+//! Keys include every input that changes the result (engine URL, speaker, text and the
+//! final prosody values), so changing a default never serves stale audio; it only
+//! makes the old entries unreachable until they are evicted.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// Used when `audio_cache_max_bytes` is unset.
+pub const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+struct Entry {
+    audio: Vec<u8>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, Entry>,
+    total_bytes: u64,
+    clock: u64,
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+fn lru() -> &'static Mutex<Lru> {
+    static LRU: OnceLock<Mutex<Lru>> = OnceLock::new();
+    LRU.get_or_init(|| Mutex::new(Lru::default()))
+}
+
+pub fn get(key: &str) -> Option<Vec<u8>> {
+    let mut lru = lru().lock().unwrap();
+    lru.clock += 1;
+    let clock = lru.clock;
+    match lru.entries.get_mut(key) {
+        Some(entry) => {
+            entry.last_used = clock;
+            HITS.fetch_add(1, Ordering::Relaxed);
+            Some(entry.audio.clone())
+        }
+        None => {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// Stores `audio`, evicting the least recently used entries to stay under `max_bytes`.
+/// Items larger than the whole budget are not cached.
+pub fn insert(key: String, audio: Vec<u8>, max_bytes: u64) {
+    let size = audio.len() as u64;
+    if size > max_bytes {
+        return;
+    }
+    let mut lru = lru().lock().unwrap();
+    lru.clock += 1;
+    let entry = Entry {
+        audio,
+        last_used: lru.clock,
+    };
+    if let Some(old) = lru.entries.insert(key, entry) {
+        lru.total_bytes -= old.audio.len() as u64;
+    }
+    lru.total_bytes += size;
+
+    while lru.total_bytes > max_bytes {
+        let Some(oldest) = lru
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone())
+        else {
+            break;
+        };
+        if let Some(evicted) = lru.entries.remove(&oldest) {
+            lru.total_bytes -= evicted.audio.len() as u64;
+        }
+    }
+}
+
+pub struct Stats {
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+pub fn stats() -> Stats {
+    let lru = lru().lock().unwrap();
+    Stats {
+        entries: lru.entries.len(),
+        total_bytes: lru.total_bytes,
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
+
+/// Empties the cache and returns how many entries were dropped. Counters are kept.
+pub fn clear() -> usize {
+    let mut lru = lru().lock().unwrap();
+    let dropped = lru.entries.len();
+    lru.entries.clear();
+    lru.total_bytes = 0;
+    dropped
+}
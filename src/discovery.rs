@@ -0,0 +1,116 @@
+//! Opt-in startup scan for VOICEVOX-compatible engines on localhost.
+//!
+//! Each port in `autodiscover_ports` that isn't already a registered engine is asked
+//! for `/speakers`; any port answering with a JSON array is registered as `port<N>`.
+//! The scan is capped in both ports and time so a wide range can't stall startup.
+
+use serde_json::Value;
+use speak_common::{AppConfig, EngineKind, EngineTarget, register_discovered, registered_engines};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// Ports probed at most; the rest of an oversized list is ignored with a warning.
+const MAX_PORTS: usize = 64;
+/// Per-port limit. All ports are probed at once, so this also bounds the whole scan.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Probes the configured ports and registers the engines found. Does nothing unless
+/// `autodiscover_ports` is set.
+pub async fn run(config: &AppConfig) {
+    let known: Vec<u16> = registered_engines(config)
+        .iter()
+        .filter_map(|e| local_port(&e.base_url))
+        .collect();
+    let mut ports: Vec<u16> = config
+        .autodiscover_ports
+        .iter()
+        .flat_map(|spec| spec.ports())
+        .filter(|port| !known.contains(port))
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    if ports.is_empty() {
+        return;
+    }
+    if ports.len() > MAX_PORTS {
+        eprintln!(
+            "⚠️ autodiscover_ports が多すぎるため先頭 {} 個だけ調べます ({} 個指定)",
+            MAX_PORTS,
+            ports.len()
+        );
+        ports.truncate(MAX_PORTS);
+    }
+
+    // Local engines only, so proxies and per-engine headers don't apply.
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new());
+    let mut probes = JoinSet::new();
+    for port in ports {
+        let client = client.clone();
+        probes.spawn(async move { probe(&client, port).await.then_some(port) });
+    }
+    let mut found = Vec::new();
+    while let Some(result) = probes.join_next().await {
+        if let Ok(Some(port)) = result {
+            found.push(port);
+        }
+    }
+    found.sort_unstable();
+
+    for port in found {
+        let name = format!("port{}", port);
+        eprintln!("🔎 エンジンを検出しました: {} ({})", name, base_url(port));
+        register_discovered(EngineTarget {
+            display_name: name.clone(),
+            name,
+            kind: EngineKind::Voicevox,
+            base_url: base_url(port),
+            default_speaker: None,
+            default_speed: None,
+        });
+    }
+}
+
+fn base_url(port: u16) -> String {
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// The port of an engine URL on this machine, however the host is spelled: the
+/// built-ins default to `localhost` while discovered engines use `127.0.0.1`.
+fn local_port(base_url: &str) -> Option<u16> {
+    let url = reqwest::Url::parse(base_url).ok()?;
+    match url.host_str()? {
+        "localhost" | "127.0.0.1" | "[::1]" => url.port_or_known_default(),
+        _ => None,
+    }
+}
+
+/// True when the port serves a VOICEVOX-style `/speakers` list.
+async fn probe(client: &reqwest::Client, port: u16) -> bool {
+    let Ok(resp) = client
+        .get(format!("{}/speakers", base_url(port)))
+        .send()
+        .await
+    else {
+        return false;
+    };
+    resp.status().is_success() && resp.json::<Value>().await.is_ok_and(|body| body.is_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_engines_match_by_port_whatever_the_host_spelling() {
+        assert_eq!(local_port("http://localhost:50021"), Some(50021));
+        assert_eq!(local_port(&base_url(50021)), Some(50021));
+        assert_eq!(local_port("http://[::1]:10101"), Some(10101));
+        assert_eq!(local_port("http://localhost"), Some(80));
+        assert_eq!(local_port("http://192.168.1.5:50021"), None);
+        assert_eq!(local_port("not a url"), None);
+    }
+}
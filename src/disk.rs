@@ -0,0 +1,50 @@
+//! Free-space check before audio is written to disk.
+//!
+//! A write that fills the disk leaves a truncated file behind and makes the player or
+//! the next write fail with a confusing error, so writers check up front instead.
+
+use crate::messages::Lang;
+use anyhow::Result;
+use std::path::Path;
+
+/// Kept free on top of the file itself so the write never fills the disk completely.
+const MARGIN_BYTES: u64 = 16 * 1024 * 1024;
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// Fails if `dir` can't hold `bytes` plus a safety margin. If the free space can't be
+/// determined the write is allowed, so unusual file systems keep working.
+pub fn ensure_space(dir: &Path, bytes: u64, lang: Lang) -> Result<()> {
+    let Ok(available) = fs2::available_space(dir) else {
+        return Ok(());
+    };
+    let needed = bytes + MARGIN_BYTES;
+    if available >= needed {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(lang.pick(
+        format!(
+            "{} の空き容量が足りません (必要: {:.1} MB / 空き: {:.1} MB)",
+            dir.display(),
+            megabytes(needed),
+            megabytes(available)
+        ),
+        format!(
+            "Not enough free space in {} (needed {:.1} MB, available {:.1} MB)",
+            dir.display(),
+            megabytes(needed),
+            megabytes(available)
+        ),
+    )))
+}
+
+/// `ensure_space` for the directory that will contain `path`.
+pub fn ensure_space_for_file(path: &Path, bytes: u64, lang: Lang) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    ensure_space(dir, bytes, lang)
+}
@@ -0,0 +1,129 @@
+//! Shows how a speak call's speaker, speed and pitch (or the macOS `say` voice and
+//! rate) are chosen, layer by layer, for `describe_config` and verbose `speak_auto`
+//! responses. Nothing is synthesized.
+
+use crate::{PITCH_SCALE_RANGE, SPEED_SCALE_RANGE};
+use serde::Serialize;
+use serde_json::{Value, json};
+use speak_common::{AppConfig, EngineTarget};
+
+/// What the call itself asked for, before `named_voices` filled in the gaps.
+pub struct Requested {
+    pub voice: Option<String>,
+    pub speaker: Option<u32>,
+    pub speed: Option<f32>,
+    pub pitch: Option<f32>,
+}
+
+/// `{value, source, layers}` where `source` names the first layer that had a value,
+/// listed from highest to lowest precedence.
+fn chain<T: Serialize + Clone>(layers: &[(&str, Option<T>)]) -> (Option<T>, Value) {
+    let winner = layers.iter().find(|(_, value)| value.is_some());
+    let value = winner.and_then(|(_, value)| value.clone());
+    let layers: Vec<Value> = layers
+        .iter()
+        .map(|(layer, value)| json!({ "layer": layer, "value": value }))
+        .collect();
+    let explanation = json!({
+        "value": value,
+        "source": winner.map(|(layer, _)| *layer),
+        "layers": layers,
+    });
+    (value, explanation)
+}
+
+/// Resolution of speaker, speed and pitch on `target`, in the same order `synthesize`
+/// applies them.
+pub fn voice(config: &AppConfig, target: &EngineTarget, requested: &Requested) -> Value {
+    let named = requested
+        .voice
+        .as_deref()
+        .and_then(|name| config.named_voices.get(name));
+
+    let (speaker, speaker_explanation) = chain(&[
+        ("call", requested.speaker),
+        ("named_voice", named.and_then(|v| v.speaker)),
+        ("engine_default", target.default_speaker),
+        ("builtin", Some(1)),
+    ]);
+    let speaker_override = config.speaker_override(speaker.unwrap_or(1));
+
+    let (speed, mut speed_explanation) = chain(&[
+        ("call", requested.speed),
+        ("named_voice", named.and_then(|v| v.speed)),
+        ("speaker_override", speaker_override.and_then(|o| o.speed)),
+        ("engine_default", target.default_speed),
+        ("builtin", Some(1.0)),
+    ]);
+    let multiplier = config.global_speed_multiplier.unwrap_or(1.0);
+    speed_explanation["global_multiplier"] = json!(config.global_speed_multiplier);
+    speed_explanation["effective"] =
+        json!((speed.unwrap_or(1.0) * multiplier).clamp(SPEED_SCALE_RANGE.0, SPEED_SCALE_RANGE.1));
+
+    // Without any layer the engine's own audio_query value is kept, which is only known
+    // after asking the engine.
+    let (pitch, mut pitch_explanation) = chain(&[
+        ("call", requested.pitch),
+        ("named_voice", named.and_then(|v| v.pitch)),
+        ("speaker_override", speaker_override.and_then(|o| o.pitch)),
+    ]);
+    if pitch.is_none() {
+        pitch_explanation["source"] = json!("engine");
+    }
+    pitch_explanation["global_offset"] = json!(config.global_pitch_offset);
+    if let Some(pitch) = pitch {
+        pitch_explanation["effective"] = json!(
+            (pitch + config.global_pitch_offset.unwrap_or(0.0))
+                .clamp(PITCH_SCALE_RANGE.0, PITCH_SCALE_RANGE.1)
+        );
+    }
+
+    json!({
+        "speaker": speaker_explanation,
+        "speed": speed_explanation,
+        "pitch": pitch_explanation,
+        "intonation": speaker_override.and_then(|o| o.intonation),
+        "fallback_speaker": config.fallback_speaker(&target.name),
+    })
+}
+
+/// Resolution of the `say` voice and rate from layers listed highest first, as built by
+/// `say` itself. With no layer set, `say` uses the system's own voice and rate.
+#[cfg(target_os = "macos")]
+pub fn say(voice: &[(&str, Option<String>)], rate: &[(&str, Option<u32>)]) -> Value {
+    let (voice, mut voice_explanation) = chain(voice);
+    if voice.is_none() {
+        voice_explanation["source"] = json!("system");
+    }
+    let (rate, mut rate_explanation) = chain(rate);
+    if rate.is_none() {
+        rate_explanation["source"] = json!("system");
+    }
+    json!({
+        "voice": voice_explanation,
+        "rate": rate_explanation,
+    })
+}
+
+#[cfg(all(test, target_os = "macos"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn say_reports_the_winning_layer_or_the_system_default() {
+        let explanation = say(
+            &[
+                ("call", None),
+                ("locale", None),
+                ("SPEAK_MCP_SAY_VOICE", Some("Kyoko".to_string())),
+                ("macos_default_voice", Some("Otoya".to_string())),
+            ],
+            &[("call", None), ("SPEAK_MCP_SAY_RATE", None)],
+        );
+        assert_eq!(explanation["voice"]["value"], "Kyoko");
+        assert_eq!(explanation["voice"]["source"], "SPEAK_MCP_SAY_VOICE");
+        assert_eq!(explanation["voice"]["layers"].as_array().unwrap().len(), 4);
+        assert_eq!(explanation["rate"]["value"], Value::Null);
+        assert_eq!(explanation["rate"]["source"], "system");
+    }
+}
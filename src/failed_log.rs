@@ -0,0 +1,147 @@
+//! Opt-in "dead letter" file of speech lost to an unreachable or failing engine, so
+//! `retry_failed` can speak it once the engine is back.
+//!
+//! Entries are JSON Lines in `failed_log_path`. The file is kept under
+//! `failed_log_max_bytes` by dropping the oldest entries; rotating it away like the
+//! usage log would throw out exactly the text it exists to keep.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use speak_common::AppConfig;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MAX_BYTES: u64 = 256 * 1024;
+
+/// Serializes rewrites between concurrent tool calls.
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FailedSpeech {
+    pub timestamp: u64,
+    pub engine: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f32>,
+    pub text: String,
+    pub error: String,
+}
+
+/// Appends one entry if `failed_log_path` is configured. Failures are only reported on
+/// stderr.
+pub fn record(
+    config: &AppConfig,
+    engine: &str,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+    text: &str,
+    error: &anyhow::Error,
+) {
+    let Some(path) = config.failed_log_path.as_deref() else {
+        return;
+    };
+    let entry = FailedSpeech {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        engine: engine.to_string(),
+        speaker,
+        speed,
+        pitch,
+        text: text.to_string(),
+        error: error.to_string(),
+    };
+    let max_bytes = config.failed_log_max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+    match append(Path::new(path), entry, max_bytes) {
+        Ok(()) => eprintln!("Saved failed speech to {}", path),
+        Err(e) => eprintln!("Failed to write failed-speech log {}: {}", path, e),
+    }
+}
+
+fn append(path: &Path, entry: FailedSpeech, max_bytes: u64) -> Result<()> {
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut entries = read(path)?;
+    entries.push(entry);
+    write(path, &entries, max_bytes)
+}
+
+/// Every saved entry, oldest first. Empty when the option is off or nothing failed.
+pub fn entries(config: &AppConfig) -> Result<Vec<FailedSpeech>> {
+    let Some(path) = config.failed_log_path.as_deref() else {
+        return Ok(Vec::new());
+    };
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    read(Path::new(path))
+}
+
+/// Removes `done` from the file, keeping anything recorded since it was read.
+pub fn remove(config: &AppConfig, done: &[FailedSpeech]) -> Result<()> {
+    let Some(path) = config.failed_log_path.as_deref() else {
+        return Ok(());
+    };
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut entries = read(Path::new(path))?;
+    entries.retain(|entry| !done.contains(entry));
+    let max_bytes = config.failed_log_max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+    write(Path::new(path), &entries, max_bytes)
+}
+
+/// Unparsable lines are skipped with a warning rather than failing the whole file.
+fn read(path: &Path) -> Result<Vec<FailedSpeech>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("Skipping malformed entry in {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Writes `entries`, dropping the oldest until the file fits in `max_bytes`. The newest
+/// entry is always kept.
+fn write(path: &Path, entries: &[FailedSpeech], max_bytes: u64) -> Result<()> {
+    let mut lines = entries
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    let mut total: u64 = lines.iter().map(|line| line.len() as u64 + 1).sum();
+    let mut dropped = 0;
+    while total > max_bytes && lines.len() - dropped > 1 {
+        total -= lines[dropped].len() as u64 + 1;
+        dropped += 1;
+    }
+    if dropped > 0 {
+        eprintln!(
+            "⚠️ {} が上限を超えたため古い {} 件を削除しました",
+            path.display(),
+            dropped
+        );
+    }
+    lines.drain(..dropped);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! Short-lived cache of engine reachability.
+//!
+//! Probing an engine costs a round trip, so results are reused for
+//! `health_cache_ttl_secs` (5 seconds by default) before probing again.
+//!
+//! With `sticky_working_engine` it also remembers the engine that answered after the
+//! preferred one failed, so an outage costs one failed probe rather than one per call.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TTL: Duration = Duration::from_secs(5);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct Health {
+    pub reachable: bool,
+    /// The engine's `/version`, when it answered.
+    pub version: Option<String>,
+    /// How long ago the probe behind this result ran.
+    pub age: Duration,
+}
+
+struct Probe {
+    version: Option<String>,
+    checked_at: Instant,
+}
+
+fn cache() -> &'static Mutex<HashMap<String, Probe>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Probe>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+type ManifestCache = Mutex<HashMap<String, (Option<serde_json::Value>, Instant)>>;
+
+/// `/engine_manifest` per base URL, cached with the same TTL as reachability.
+fn manifests() -> &'static ManifestCache {
+    static MANIFESTS: OnceLock<ManifestCache> = OnceLock::new();
+    MANIFESTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The engine picked by fallback and when, for `sticky_working_engine`.
+static STICKY: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+/// Remembers `name` as the engine that worked.
+pub fn stick(name: &str) {
+    *STICKY.lock().unwrap() = Some((name.to_string(), Instant::now()));
+}
+
+/// The remembered engine and how long it stays remembered, unless `duration` has passed.
+pub fn sticky(duration: Duration) -> Option<(String, Duration)> {
+    let mut sticky = STICKY.lock().unwrap();
+    match sticky.as_ref() {
+        Some((name, since)) if since.elapsed() < duration => {
+            Some((name.clone(), duration.saturating_sub(since.elapsed())))
+        }
+        _ => {
+            *sticky = None;
+            None
+        }
+    }
+}
+
+/// Forgets the remembered engine so the configured priority applies again.
+pub fn unstick() {
+    *STICKY.lock().unwrap() = None;
+}
+
+pub fn ttl(secs: Option<u64>) -> Duration {
+    secs.map(Duration::from_secs).unwrap_or(DEFAULT_TTL)
+}
+
+/// Returns the cached result for `base_url` if it is younger than `ttl`, otherwise
+/// probes `/version` and caches the new result.
+pub async fn check(base_url: &str, ttl: Duration) -> Health {
+    let cached = cache()
+        .lock()
+        .unwrap()
+        .get(base_url)
+        .map(|probe| (probe.version.clone(), probe.checked_at.elapsed()));
+    if let Some((version, age)) = cached.filter(|(_, age)| *age < ttl) {
+        return Health {
+            reachable: version.is_some(),
+            version,
+            age,
+        };
+    }
+
+    let version = probe(base_url).await;
+    cache().lock().unwrap().insert(
+        base_url.to_string(),
+        Probe {
+            version: version.clone(),
+            checked_at: Instant::now(),
+        },
+    );
+    Health {
+        reachable: version.is_some(),
+        version,
+        age: Duration::ZERO,
+    }
+}
+
+/// The engine's version, or `None` if it didn't answer successfully. Engines return it
+/// as a JSON string; anything else is kept as plain text.
+async fn probe(base_url: &str) -> Option<String> {
+    let client = crate::http::client(&speak_common::load_config(), base_url);
+    let resp = client
+        .get(format!("{}/version", base_url))
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .ok()
+        .filter(|resp| resp.status().is_success())?;
+    let body = resp.text().await.unwrap_or_default();
+    let version = serde_json::from_str::<String>(&body).unwrap_or(body);
+    Some(version.trim().to_string())
+}
+
+/// The engine's `/engine_manifest` (features, name, ...), cached like `check`. `None` if
+/// the engine is down or doesn't provide one.
+pub async fn manifest(base_url: &str, ttl: Duration) -> Option<serde_json::Value> {
+    let cached = manifests().lock().unwrap().get(base_url).cloned();
+    if let Some((manifest, _)) = cached.filter(|(_, fetched_at)| fetched_at.elapsed() < ttl) {
+        return manifest;
+    }
+
+    let client = crate::http::client(&speak_common::load_config(), base_url);
+    let manifest = match client
+        .get(format!("{}/engine_manifest", base_url))
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+    {
+        Ok(resp) if resp.status().is_success() => resp.json().await.ok(),
+        _ => None,
+    };
+    manifests()
+        .lock()
+        .unwrap()
+        .insert(base_url.to_string(), (manifest.clone(), Instant::now()));
+    manifest
+}
+
+/// Forgets every cached result, and the sticky engine, so the next check probes again.
+pub fn invalidate() {
+    cache().lock().unwrap().clear();
+    manifests().lock().unwrap().clear();
+    unstick();
+}
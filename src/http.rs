@@ -0,0 +1,107 @@
+//! HTTP client construction shared by every engine call.
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use speak_common::{AppConfig, find_engine};
+
+/// Builds a client for requests to the engine at `base_url`.
+///
+/// Proxies follow `HTTP_PROXY` / `HTTPS_PROXY` / `NO_PROXY` (reqwest's default) or, when
+/// set, the explicit `proxy_url` from config. `NO_PROXY` is applied to the explicit proxy
+/// too, so localhost engines stay direct; in practice a proxy only matters for cloud
+/// engines. Headers from `engine_headers` for that engine are sent with every request,
+/// and its `engine_tls` entry adjusts certificate verification.
+pub fn client(config: &AppConfig, base_url: &str) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy_url) = config.proxy_url.as_deref().filter(|url| !url.is_empty()) {
+        match reqwest::Proxy::all(proxy_url) {
+            Ok(proxy) => builder = builder.proxy(proxy.no_proxy(reqwest::NoProxy::from_env())),
+            Err(e) => eprintln!("⚠️ proxy_url を使えません ({}): {}", proxy_url, e),
+        }
+    }
+    let headers = engine_headers(config, base_url);
+    if !headers.is_empty() {
+        builder = builder.default_headers(headers);
+    }
+    builder = apply_tls(builder, config, base_url);
+    builder.build().unwrap_or_else(|e| {
+        eprintln!("⚠️ HTTP クライアントの設定に失敗しました: {}", e);
+        reqwest::Client::new()
+    })
+}
+
+/// Applies `engine_tls` for whichever registered engine lives at `base_url`. A CA file
+/// that can't be loaded is skipped with a warning, leaving verification strict.
+fn apply_tls(
+    mut builder: reqwest::ClientBuilder,
+    config: &AppConfig,
+    base_url: &str,
+) -> reqwest::ClientBuilder {
+    for (engine_name, tls) in &config.engine_tls {
+        if find_engine(config, engine_name).is_none_or(|e| e.base_url != base_url) {
+            continue;
+        }
+        if tls.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Some(path) = tls.ca_cert_path.as_deref().filter(|p| !p.is_empty()) {
+            let cert = std::fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|pem| reqwest::Certificate::from_pem(&pem).map_err(Into::into));
+            match cert {
+                Ok(cert) => builder = builder.add_root_certificate(cert),
+                Err(e) => eprintln!(
+                    "⚠️ engine_tls.{} の ca_cert_path を読み込めません ({}): {}",
+                    engine_name, path, e
+                ),
+            }
+        }
+    }
+    builder
+}
+
+/// Headers configured for whichever registered engine lives at `base_url`.
+fn engine_headers(config: &AppConfig, base_url: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (engine_name, values) in &config.engine_headers {
+        if find_engine(config, engine_name).is_none_or(|e| e.base_url != base_url) {
+            continue;
+        }
+        for (name, value) in values {
+            let value = expand_env(value);
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                (Ok(name), Ok(value)) => {
+                    headers.insert(name, value);
+                }
+                _ => eprintln!(
+                    "⚠️ engine_headers.{} のヘッダー {} が不正です",
+                    engine_name, name
+                ),
+            }
+        }
+    }
+    headers
+}
+
+/// Replaces `${VAR}` with the environment variable's value, so secrets like API keys
+/// don't have to be written into the config file. Unset variables become empty.
+pub fn expand_env(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let var = &rest[start + 2..start + 2 + len];
+        match std::env::var(var) {
+            Ok(val) => out.push_str(&val),
+            Err(_) => eprintln!("⚠️ 環境変数 {} が設定されていません", var),
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    out.push_str(rest);
+    out
+}
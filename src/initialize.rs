@@ -0,0 +1,60 @@
+//! Loading a speaker's model ahead of its first synthesis through VOICEVOX's
+//! `/is_initialized_speaker` and `/initialize_speaker`.
+//!
+//! An engine loads a model lazily on the first request for that speaker, which makes
+//! the first call noticeably slower. Initializing ahead of time moves that cost to
+//! startup (`warm_up_speakers`) or to an explicit `initialize_speaker` call.
+
+use crate::{ensure_success, http};
+use anyhow::Result;
+use speak_common::{AppConfig, EngineTarget, registered_engines};
+
+/// Whether the engine has already loaded `speaker`'s model.
+pub async fn is_initialized(config: &AppConfig, base_url: &str, speaker: u32) -> Result<bool> {
+    let resp = http::client(config, base_url)
+        .get(format!("{}/is_initialized_speaker", base_url))
+        .query(&[("speaker", speaker)])
+        .send()
+        .await?;
+    let resp = ensure_success(resp, "is_initialized_speaker").await?;
+    Ok(resp.json().await?)
+}
+
+/// Loads `speaker`'s model unless it is already loaded. Returns `true` if it was
+/// already initialized.
+pub async fn ensure(config: &AppConfig, base_url: &str, speaker: u32) -> Result<bool> {
+    if is_initialized(config, base_url, speaker).await? {
+        return Ok(true);
+    }
+    let resp = http::client(config, base_url)
+        .post(format!("{}/initialize_speaker", base_url))
+        .query(&[
+            ("speaker", speaker.to_string()),
+            ("skip_reinit", "true".to_string()),
+        ])
+        .send()
+        .await?;
+    ensure_success(resp, "initialize_speaker").await?;
+    Ok(false)
+}
+
+/// Initializes every engine's default speaker in the background, logging the result.
+pub fn warm_up(config: &AppConfig) {
+    let targets: Vec<(EngineTarget, u32)> = registered_engines(config)
+        .into_iter()
+        .filter_map(|engine| engine.default_speaker.map(|speaker| (engine, speaker)))
+        .collect();
+    for (engine, speaker) in targets {
+        let config = config.clone();
+        tokio::spawn(async move {
+            match ensure(&config, &engine.base_url, speaker).await {
+                Ok(true) => {}
+                Ok(false) => eprintln!("Initialized speaker {} on {}", speaker, engine.name),
+                Err(e) => eprintln!(
+                    "⚠️ {} の話者 {} を初期化できませんでした: {}",
+                    engine.name, speaker, e
+                ),
+            }
+        });
+    }
+}
@@ -0,0 +1,251 @@
+//! Readings the engine chose, taken from the `kana` field of the audio query, so
+//! mis-read names and jargon can be caught without listening, and laid out for study
+//! by `speak_reading`.
+//!
+//! The field uses AquesTalk-style notation (`コンニチワ'/セ'カイ`): `'` marks the accent,
+//! `/` and `、` separate phrases, `_` devoices the next mora and `？` ends a question.
+//! Comparisons ignore all of those and treat hiragana as katakana.
+
+use serde_json::{Value, json};
+use speak_common::AppConfig;
+
+/// The engine's reading from an audio query.
+pub fn reading(query: &Value) -> Option<String> {
+    query
+        .get("kana")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Katakana only, with hiragana converted, for comparing readings.
+fn normalize(kana: &str) -> String {
+    kana.chars()
+        .filter_map(|c| match c {
+            '\u{3041}'..='\u{3096}' => char::from_u32(c as u32 + 0x60),
+            '\u{30A1}'..='\u{30FA}' | 'ー' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `kana_overrides` entries whose word appears in `text` but whose expected reading is
+/// missing from the engine's `kana` for it.
+pub fn mismatches(config: &AppConfig, text: &str, kana: &str) -> Vec<Value> {
+    let kana = normalize(kana);
+    config
+        .kana_overrides
+        .iter()
+        .filter(|(word, expected)| {
+            text.contains(word.as_str()) && !kana.contains(&normalize(expected))
+        })
+        .map(|(word, expected)| json!({ "word": word, "expected": expected }))
+        .collect()
+}
+
+/// A query's reading laid out for study: the whole reading, its romaji and each accent
+/// phrase with the mora that carries the accent (1-based, as VOICEVOX counts it).
+pub fn study(text: &str, query: &Value) -> Value {
+    let empty = Vec::new();
+    let phrases: Vec<Value> = query
+        .get("accent_phrases")
+        .and_then(Value::as_array)
+        .unwrap_or(&empty)
+        .iter()
+        .map(|phrase| {
+            let reading: String = phrase
+                .get("moras")
+                .and_then(Value::as_array)
+                .unwrap_or(&empty)
+                .iter()
+                .filter_map(|mora| mora.get("text").and_then(Value::as_str))
+                .collect();
+            json!({
+                "reading": reading,
+                "romaji": romaji(&reading),
+                "accent": phrase.get("accent"),
+            })
+        })
+        .collect();
+    let reading = phrases
+        .iter()
+        .filter_map(|phrase| phrase["reading"].as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    json!({
+        "original": text,
+        "kana": reading(query).unwrap_or_default(),
+        "reading": reading,
+        "romaji": romaji(&reading),
+        "phrases": phrases,
+    })
+}
+
+/// Hepburn romaji for a katakana (or hiragana) reading. Characters that aren't kana are
+/// kept as they are.
+pub fn romaji(kana: &str) -> String {
+    let chars: Vec<char> = kana
+        .chars()
+        .map(|c| match c {
+            '\u{3041}'..='\u{3096}' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .collect();
+    let mut out = String::new();
+    let mut geminate = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == 'ッ' {
+            geminate = true;
+            i += 1;
+            continue;
+        }
+        if c == 'ー' {
+            // A long vowel repeats the previous one.
+            if let Some(vowel) = out.chars().next_back().filter(|v| "aiueo".contains(*v)) {
+                out.push(vowel);
+            }
+            i += 1;
+            continue;
+        }
+        let (syllable, len) = syllable(c, chars.get(i + 1).copied());
+        if geminate {
+            match syllable.chars().next() {
+                Some('c') => out.push('t'),
+                Some(first) if !"aiueon".contains(first) && first.is_ascii_lowercase() => {
+                    out.push(first)
+                }
+                _ => {}
+            }
+            geminate = false;
+        }
+        out.push_str(&syllable);
+        i += len;
+    }
+    out
+}
+
+/// The romaji for the mora starting with `c`, and how many characters it used (2 when
+/// `next` is a small kana that combines with it).
+fn syllable(c: char, next: Option<char>) -> (String, usize) {
+    let Some(base) = base(c) else {
+        let syllable = small(c).map_or_else(|| c.to_string(), str::to_string);
+        return (syllable, 1);
+    };
+    match next {
+        // キャ → kya, シャ → sha, ジョ → jo
+        Some(n @ ('ャ' | 'ュ' | 'ョ')) if base.len() > 1 && base.ends_with('i') => {
+            let stem = &base[..base.len() - 1];
+            let vowel = &small(n).unwrap_or("ya")[1..];
+            let syllable = if matches!(stem, "sh" | "ch" | "j") {
+                format!("{}{}", stem, vowel)
+            } else {
+                format!("{}y{}", stem, vowel)
+            };
+            (syllable, 2)
+        }
+        // ファ → fa, ティ → ti, ウィ → wi, イェ → ye
+        Some(n @ ('ァ' | 'ィ' | 'ゥ' | 'ェ' | 'ォ')) => {
+            let stem = match base {
+                "u" => "w",
+                "i" => "y",
+                _ => base.trim_end_matches(['a', 'i', 'u', 'e', 'o']),
+            };
+            (format!("{}{}", stem, small(n).unwrap_or_default()), 2)
+        }
+        _ => (base.to_string(), 1),
+    }
+}
+
+fn small(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ァ' => "a",
+        'ィ' => "i",
+        'ゥ' => "u",
+        'ェ' => "e",
+        'ォ' => "o",
+        'ャ' => "ya",
+        'ュ' => "yu",
+        'ョ' => "yo",
+        'ヮ' => "wa",
+        _ => return None,
+    })
+}
+
+fn base(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ア' => "a",
+        'イ' => "i",
+        'ウ' => "u",
+        'エ' => "e",
+        'オ' => "o",
+        'カ' => "ka",
+        'キ' => "ki",
+        'ク' => "ku",
+        'ケ' => "ke",
+        'コ' => "ko",
+        'ガ' => "ga",
+        'ギ' => "gi",
+        'グ' => "gu",
+        'ゲ' => "ge",
+        'ゴ' => "go",
+        'サ' => "sa",
+        'シ' => "shi",
+        'ス' => "su",
+        'セ' => "se",
+        'ソ' => "so",
+        'ザ' => "za",
+        'ジ' => "ji",
+        'ズ' => "zu",
+        'ゼ' => "ze",
+        'ゾ' => "zo",
+        'タ' => "ta",
+        'チ' => "chi",
+        'ツ' => "tsu",
+        'テ' => "te",
+        'ト' => "to",
+        'ダ' => "da",
+        'ヂ' => "ji",
+        'ヅ' => "zu",
+        'デ' => "de",
+        'ド' => "do",
+        'ナ' => "na",
+        'ニ' => "ni",
+        'ヌ' => "nu",
+        'ネ' => "ne",
+        'ノ' => "no",
+        'ハ' => "ha",
+        'ヒ' => "hi",
+        'フ' => "fu",
+        'ヘ' => "he",
+        'ホ' => "ho",
+        'バ' => "ba",
+        'ビ' => "bi",
+        'ブ' => "bu",
+        'ベ' => "be",
+        'ボ' => "bo",
+        'パ' => "pa",
+        'ピ' => "pi",
+        'プ' => "pu",
+        'ペ' => "pe",
+        'ポ' => "po",
+        'マ' => "ma",
+        'ミ' => "mi",
+        'ム' => "mu",
+        'メ' => "me",
+        'モ' => "mo",
+        'ヤ' => "ya",
+        'ユ' => "yu",
+        'ヨ' => "yo",
+        'ラ' => "ra",
+        'リ' => "ri",
+        'ル' => "ru",
+        'レ' => "re",
+        'ロ' => "ro",
+        'ワ' => "wa",
+        'ヲ' => "o",
+        'ン' => "n",
+        'ヴ' => "vu",
+        _ => return None,
+    })
+}
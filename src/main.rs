@@ -1,347 +1,5266 @@
 use anyhow::Result;
 use async_mcp::server::Server;
 use async_mcp::transport::ServerStdioTransport;
-use async_mcp::types::{CallToolRequest, CallToolResponse, Tool, ToolResponseContent};
+use async_mcp::types::{
+    CallToolRequest, CallToolResponse, ServerCapabilities, Tool, ToolResponseContent,
+};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::env;
-use std::fs;
-use std::io::Write;
+use speak_common::process;
+use speak_common::{
+    AppConfig, AudioDisposition, Engine, EngineTarget, NewlineMode, OfflineBehavior, PlaybackMode,
+    SaveFormat, find_engine, get_config_path, load_config, registered_engines, update_config,
+};
+use std::collections::HashMap;
 use std::process::Command;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+mod cache;
+mod discovery;
+mod disk;
+mod explain;
+mod failed_log;
+mod health;
+mod http;
+mod initialize;
+mod kana;
+mod messages;
+mod on_complete;
+mod playback;
+mod post_process;
+mod prepared;
+mod resource;
+mod save_index;
+mod speakers;
+mod stream;
+mod text;
+mod timing;
+mod usage_log;
+mod wav;
+#[cfg(feature = "http")]
+mod web;
+
+use messages::Lang;
+use playback::{Outcome, Priority};
+use speakers::{SpeakerInfo, fetch_speakers};
+use text::Language;
 
 #[derive(Debug, Deserialize, Serialize)]
 struct SpeakArgs {
+    /// May be omitted when `resource_uri` is given.
+    #[serde(default)]
     text: String,
+    /// `file://` or `http(s)://` URI whose text is read instead of `text`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resource_uri: Option<String>,
     voice: Option<String>,
+    /// e.g. "ja_JP" or "en"; picks the first installed voice for it when `voice` is unset.
+    locale: Option<String>,
     speed: Option<u32>,
+    /// Also report which voice was used.
+    #[serde(default)]
+    verbose: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize)]
 struct VoiceEngineArgs {
+    /// May be omitted when `resource_uri` is given.
+    #[serde(default)]
     text: String,
+    /// `file://` or `http(s)://` URI whose text is read instead of `text`.
+    resource_uri: Option<String>,
     speaker: Option<u32>,
     speed: Option<f32>,
+    /// Absolute `pitchScale`; overrides `speaker_overrides`.
+    pitch: Option<f32>,
+    #[serde(default)]
+    priority: Priority,
+    resume_queue: Option<bool>,
+    /// Overrides the `playback_mode` config for this call.
+    playback_mode: Option<PlaybackMode>,
+    async_playback: Option<bool>,
+    /// Read digit runs one digit at a time; overrides the `spell_numbers` config.
+    spell_numbers: Option<bool>,
+    /// Report the engine's kana reading; overrides the `verify_kana` config.
+    verify_kana: Option<bool>,
+    /// Return the audio instead of playing it (`true`) or play it only (`false`);
+    /// overrides the engine's `default_audio_disposition`.
+    return_audio: Option<bool>,
+    /// Speak only the characters from `start` (0-based) up to `end` (exclusive).
+    start: Option<usize>,
+    end: Option<usize>,
+    /// Speak only sentences `[from, to]` (1-based, inclusive).
+    sentences: Option<[usize; 2]>,
+    /// Also save `speaker` as the engine's default in the config file. Without it the
+    /// choice applies to this call only. A per-call `speaker` still wins over the saved
+    /// default, and with `use_session_config` a default speaker in `config.session.json`
+    /// shadows it until the session overrides are cleared.
+    #[serde(default)]
+    remember: bool,
 }
 
 #[derive(Debug, Deserialize)]
-struct StyleInfo {
-    name: String,
-    id: u32,
+struct SpeakAutoArgs {
+    /// Registered engine name. When omitted, English text goes to `english_engine` and
+    /// everything else to VOICEVOX.
+    engine: Option<String>,
+    /// Name from `named_voices`; picks the engine and fills in speaker/speed/pitch
+    /// that the call doesn't set itself.
+    voice: Option<String>,
+    /// Also report the detected language and the engine that was used.
+    #[serde(default)]
+    verbose: bool,
+    #[serde(flatten)]
+    speak: VoiceEngineArgs,
 }
 
 #[derive(Debug, Deserialize)]
-struct SpeakerInfo {
-    name: String,
-    styles: Vec<StyleInfo>,
+struct SpeakPhraseArgs {
+    /// Key of `quick_phrases`.
+    phrase: String,
+    async_playback: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
-struct AppConfig {
-    voicevox_default_speaker: Option<u32>,
-    aivis_default_speaker: Option<u32>,
-    macos_default_voice: Option<String>,
+#[derive(Debug, Deserialize)]
+struct RetryFailedArgs {
+    /// Retry at most this many entries, oldest first.
+    limit: Option<usize>,
 }
 
-fn get_config_path() -> std::path::PathBuf {
-    if let Some(mut home) = dirs::home_dir() {
-        home.push("speak-mcp");
-        home.push("config.json");
-        return home;
-    }
-    // Fallback
-    let mut config_path = env::current_exe()
-        .map(|p| p.parent().map(|p| p.to_path_buf()).unwrap_or_default())
-        .unwrap_or_default();
-    config_path.push("config.json");
-    config_path
+#[derive(Debug, Deserialize)]
+struct SpeakStreamArgs {
+    session_id: String,
+    #[serde(default)]
+    text: String,
+    /// The voice is taken from a session's first call; later values are ignored.
+    engine: Option<String>,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
 }
 
-fn load_config() -> AppConfig {
-    let path = get_config_path();
-    if let Ok(content) = fs::read_to_string(&path) {
-        if let Ok(config) = serde_json::from_str(&content) {
-            return config;
-        }
-    }
+#[derive(Debug, Deserialize)]
+struct SpeakLinesArgs {
+    engine: String,
+    lines: Vec<String>,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pause_ms: Option<u64>,
+    #[serde(default)]
+    numbered: bool,
+}
 
-    // Fallback check for local config if home one failed or didn't exist
-    let mut local_path = env::current_exe()
-        .map(|p| p.parent().map(|p| p.to_path_buf()).unwrap_or_default())
-        .unwrap_or_default();
-    local_path.push("config.json");
+#[derive(Debug, Deserialize)]
+struct DialogueSegment {
+    text: String,
+    /// Registered engine name; defaults to the first working engine.
+    engine: Option<String>,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+    /// Stereo position from -1.0 (left) to 1.0 (right); center when omitted.
+    pan: Option<f32>,
+}
 
-    if path != local_path {
-        if let Ok(content) = fs::read_to_string(&local_path) {
-            if let Ok(config) = serde_json::from_str(&content) {
-                return config;
-            }
-        }
-    }
+#[derive(Debug, Deserialize)]
+struct SpeakDialogueArgs {
+    segments: Vec<DialogueSegment>,
+    pause_ms: Option<u64>,
+}
 
-    AppConfig::default()
+#[derive(Debug, Deserialize)]
+struct SingNote {
+    /// Syllable to sing; ignored for rests.
+    #[serde(default)]
+    lyric: String,
+    /// MIDI note number (60 = C4). Omit or null for a rest.
+    key: Option<u8>,
+    duration_ms: u32,
 }
 
-async fn fetch_speakers(port: u16) -> Option<Vec<SpeakerInfo>> {
-    let client = reqwest::Client::new();
-    let url = format!("http://localhost:{}/speakers", port);
-    match client.get(&url).send().await {
-        Ok(resp) => resp.json::<Vec<SpeakerInfo>>().await.ok(),
-        Err(_) => None,
-    }
+#[derive(Debug, Deserialize)]
+struct SingArgs {
+    speaker: u32,
+    notes: Vec<SingNote>,
+    /// Style used to build the frame query; VOICEVOX ships its singing teacher as 6000.
+    teacher: Option<u32>,
+}
+
+/// VOICEVOX singing works in frames of 256 samples at 24 kHz.
+const SING_FRAMES_PER_SECOND: f64 = 24000.0 / 256.0;
+const DEFAULT_SING_TEACHER: u32 = 6000;
+
+#[derive(Debug, Deserialize)]
+struct BenchmarkArgs {
+    engine: String,
+    speaker: Option<u32>,
+    iterations: Option<u32>,
+}
+
+/// Valid `speedScale` / `pitchScale` ranges (the same limits the VOICEVOX editor uses).
+const SPEED_SCALE_RANGE: (f32, f32) = (0.5, 2.0);
+const PITCH_SCALE_RANGE: (f32, f32) = (-0.15, 0.15);
+
+const BENCHMARK_TEXT: &str = "これは音声合成の速度を測るためのテストです。";
+
+/// Output sampling rates VOICEVOX-compatible engines accept for `outputSamplingRate`.
+const EXPORT_SAMPLE_RATE_RANGE: (u32, u32) = (8000, 48000);
+
+/// Upper bound for `waveform_points`; a sparkline never needs more.
+const MAX_WAVEFORM_POINTS: usize = 1000;
+
+/// Upper bound for `benchmark` iterations so a single call can't keep an engine busy.
+const MAX_BENCHMARK_ITERATIONS: u32 = 20;
+
+#[derive(Debug, Deserialize)]
+struct SpeakWithTimingArgs {
+    engine: Option<String>,
+    text: String,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+    /// `false` returns the timing without playing. Defaults to `true`.
+    play: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpeakReadingArgs {
+    text: String,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    play: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetAudioQueryArgs {
+    engine: Option<String>,
+    text: String,
+    speaker: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SynthesizeQueryArgs {
+    engine: Option<String>,
+    query: serde_json::Value,
+    speaker: Option<u32>,
+    /// Write the WAV here instead of playing it.
+    output_path: Option<String>,
+    /// Return a min/max waveform with this many buckets alongside the result.
+    waveform_points: Option<usize>,
+    #[serde(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Channels {
+    Mono,
+    Stereo,
+}
+
+/// Output adjustments applied to saved files. Unset fields keep the engine's output.
+#[derive(Debug, Deserialize, Default)]
+struct ExportOptions {
+    sample_rate: Option<u32>,
+    channels: Option<Channels>,
+    /// Scale the audio so its loudest sample hits this level, e.g. -1.0.
+    normalize_peak_dbfs: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveSpeechArgs {
+    engine: Option<String>,
+    text: String,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    output_path: String,
+    /// Falls back to the engine's `default_save_formats` entry, then WAV.
+    format: Option<SaveFormat>,
+    /// Keep `output_path` if it already holds the result of this exact request.
+    #[serde(default)]
+    skip_if_exists: bool,
+    #[serde(flatten)]
+    export: ExportOptions,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchRecord {
+    text: String,
+    output_path: String,
+    engine: Option<String>,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchSynthesizeArgs {
+    records: Option<Vec<BatchRecord>>,
+    /// JSON Lines file with one record per line, as an alternative to `records`.
+    jsonl_path: Option<String>,
+    /// Keep output files that already hold the result of the same record.
+    #[serde(default)]
+    skip_if_exists: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrepareSpeechArgs {
+    engine: Option<String>,
+    text: String,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayPreparedArgs {
+    token: String,
+    #[serde(default)]
+    priority: Priority,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListVoicesArgs {
+    engine: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct DiagnosticsArgs {
+    engine: Option<String>,
+    speaker: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportConfigArgs {
+    path: String,
+    #[serde(default)]
+    include_secrets: bool,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct InitializeSpeakerArgs {
+    /// Registered engine name; the default engine when omitted.
+    engine: Option<String>,
+    /// The engine's default speaker when omitted.
+    speaker: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetDefaultSpeakerArgs {
+    /// Registered engine name.
+    engine: String,
+    speaker: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetEnginePriorityArgs {
+    engines: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SetDefaultSpeedArgs {
+    /// Registered engine name.
+    engine: String,
+    speed: f32,
+}
+
+#[cfg(target_os = "macos")]
+#[derive(Debug, Deserialize)]
+struct SetDefaultVoiceArgs {
+    voice: String,
 }
 
+/// `max_one_of` caps how many styles are listed as `oneOf` choices; above it the plain
+/// integer schema is used, since some clients can't handle very long lists.
 fn build_speaker_choice_schema(
     speakers: Option<Vec<SpeakerInfo>>,
     default_id: Option<u32>,
+    max_one_of: Option<usize>,
 ) -> serde_json::Value {
     // Default to 1 if no config and no speakers found, but if config exists use it.
     let default_val = default_id.unwrap_or(1);
 
-    if let Some(speakers) = speakers {
-        let mut one_of = Vec::new();
+    let speaker_schema = if let Some(speakers) = speakers {
+        // Sort by style ID rather than by name or engine order: IDs are stable across
+        // engine updates, so clients caching the schema (or keying off option position)
+        // keep seeing the same voice at the same place.
+        let mut choices: Vec<(u32, String, String)> = speakers
+            .into_iter()
+            .flat_map(|speaker| {
+                let name = speaker.name;
+                speaker
+                    .styles
+                    .into_iter()
+                    .map(move |style| (style.id, name.clone(), style.name))
+            })
+            .collect();
+        choices.sort();
 
-        for speaker in speakers {
-            for style in speaker.styles {
-                one_of.push(json!({
-                    "const": style.id,
-                    "title": format!("{} ({})", speaker.name, style.name)
-                }));
-            }
+        if max_one_of.is_some_and(|max| choices.len() > max) {
+            return with_speaker_schema(json!({
+                "type": "integer",
+                "default": default_val,
+                "description": format!(
+                    "話者のスタイル ID。選択肢が多いため ({} 件) 一覧は省略しています。list_voices で確認してください",
+                    choices.len()
+                )
+            }));
         }
 
+        let one_of: Vec<serde_json::Value> = choices
+            .into_iter()
+            .map(|(id, speaker, style)| {
+                json!({
+                    "const": id,
+                    "title": format!("{} ({}) [ID: {}]", speaker, style, id)
+                })
+            })
+            .collect();
+
         // Ensure default value is in the list if possible, or add a fallback option
         // In a perfect world we check validation, but for now we trust the config or list.
 
         json!({
-            "type": "object",
-            "properties": {
-                "text": { "type": "string" },
-                "speaker": {
-                    "oneOf": one_of,
-                    "default": default_val
-                },
-                "speed": { "type": "number", "default": 1.0 }
-            },
-            "required": ["text"]
+            "oneOf": one_of,
+            "default": default_val
         })
     } else {
         // Fallback schema if engine is offline
-        json!({
-            "type": "object",
-            "properties": {
-                "text": { "type": "string" },
-                "speaker": { "type": "integer", "default": default_val },
-                "speed": { "type": "number", "default": 1.0 }
+        json!({ "type": "integer", "default": default_val })
+    };
+
+    with_speaker_schema(speaker_schema)
+}
+
+/// The speak tools' input schema around the given `speaker` property.
+fn with_speaker_schema(speaker_schema: serde_json::Value) -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "text": { "type": "string", "description": "resource_uri を指定する場合は省略します" },
+            "resource_uri": {
+                "type": "string",
+                "description": "text の代わりに読み上げるテキストの場所 (file:// または http(s)://、1 MiB まで)"
             },
-            "required": ["text"]
-        })
-    }
+            "speaker": speaker_schema,
+            "speed": { "type": "number", "default": 1.0 },
+            "pitch": { "type": "number", "description": "音高 (pitchScale, -0.15〜0.15)" },
+            "priority": {
+                "type": "string",
+                "enum": ["normal", "high"],
+                "default": "normal",
+                "description": "high にすると再生中の音声を止めて割り込みます"
+            },
+            "resume_queue": {
+                "type": "boolean",
+                "default": true,
+                "description": "priority=high の後、待機中の読み上げを続けるかどうか"
+            },
+            "start": {
+                "type": "integer",
+                "minimum": 0,
+                "description": "text の何文字目から読むか (0 始まり)"
+            },
+            "end": {
+                "type": "integer",
+                "minimum": 1,
+                "description": "text の何文字目の手前まで読むか (この文字は含みません)"
+            },
+            "sentences": {
+                "type": "array",
+                "items": { "type": "integer", "minimum": 1 },
+                "minItems": 2,
+                "maxItems": 2,
+                "description": "[3, 5] のように、読む文の範囲 (1 始まり、両端を含む)。start / end とは併用できません"
+            },
+            "return_audio": {
+                "type": "boolean",
+                "description": "true にすると再生せずに音声データ (base64) を _meta.audio で返します。false にすると再生だけします。省略時は config の default_audio_disposition に従います"
+            },
+            "playback_mode": {
+                "type": "string",
+                "enum": ["queue", "interrupt", "drop"],
+                "description": "再生中の読み上げがあるときの扱い (config の playback_mode より優先)。queue: 順番待ち、interrupt: 止めて割り込む、drop: 読み上げない"
+            },
+            "async_playback": {
+                "type": "boolean",
+                "default": false,
+                "description": "true にすると再生の完了を待たずにすぐ応答します"
+            },
+            "spell_numbers": {
+                "type": "boolean",
+                "description": "true にすると数字を 1 桁ずつ読み上げます (例: 402 → よん、ゼロ、に)"
+            },
+            "verify_kana": {
+                "type": "boolean",
+                "description": "true にするとエンジンが推定した読み (カナ) を応答の _meta.kana に含め、kana_overrides と違う読みを警告します"
+            },
+            "remember": {
+                "type": "boolean",
+                "default": false,
+                "description": "true にすると speaker をこのエンジンのデフォルト話者として設定ファイル (config.json / config.toml) に保存します。優先順位は呼び出しの speaker > config.session.json (use_session_config 有効時) > 保存したデフォルトです"
+            }
+        }
+    })
 }
 
-async fn play_wav(wav_data: &[u8]) -> Result<()> {
-    let mut temp_file = tempfile::NamedTempFile::new()?;
-    temp_file.write_all(wav_data)?;
-    let path = temp_file
-        .path()
-        .to_str()
-        .ok_or_else(|| anyhow::anyhow!("Invalid path"))?;
+#[cfg(test)]
+mod speaker_schema_tests {
+    use super::*;
+    use speakers::StyleInfo;
 
-    #[cfg(target_os = "macos")]
-    {
-        let status = Command::new("afplay").arg(path).status()?;
-        if !status.success() {
-            return Err(anyhow::anyhow!("afplay failed"));
+    fn speaker(name: &str, styles: &[(&str, u32)]) -> SpeakerInfo {
+        SpeakerInfo {
+            name: name.to_string(),
+            styles: styles
+                .iter()
+                .map(|(style, id)| StyleInfo {
+                    name: style.to_string(),
+                    id: *id,
+                })
+                .collect(),
         }
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        let script = format!(
-            "(New-Object System.Media.SoundPlayer '{}').PlaySync()",
-            path
+    fn choice_ids(schema: &serde_json::Value) -> Vec<u64> {
+        schema["properties"]["speaker"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|choice| choice["const"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn choices_are_ordered_by_style_id_whatever_the_engine_order() {
+        let engine_order = vec![
+            speaker("ずんだもん", &[("ノーマル", 3), ("あまあま", 1)]),
+            speaker("四国めたん", &[("ノーマル", 2), ("ツンツン", 6)]),
+        ];
+        let reordered = vec![
+            speaker("四国めたん", &[("ツンツン", 6), ("ノーマル", 2)]),
+            speaker("ずんだもん", &[("あまあま", 1), ("ノーマル", 3)]),
+        ];
+
+        let schema = build_speaker_choice_schema(Some(engine_order.clone()), Some(3), None);
+
+        assert_eq!(choice_ids(&schema), vec![1, 2, 3, 6]);
+        assert_eq!(
+            schema["properties"]["speaker"]["oneOf"][0]["title"],
+            "ずんだもん (あまあま) [ID: 1]"
+        );
+        assert_eq!(
+            build_speaker_choice_schema(Some(reordered), Some(3), None),
+            schema
+        );
+        assert_eq!(
+            build_speaker_choice_schema(Some(engine_order), Some(3), None),
+            schema
         );
-        let status = Command::new("powershell")
-            .arg("-Command")
-            .arg(script)
-            .status()?;
-        if !status.success() {
-            return Err(anyhow::anyhow!("PowerShell playback failed"));
-        }
     }
 
-    Ok(())
+    #[test]
+    fn one_of_is_kept_up_to_max_schema_oneof() {
+        let speakers = vec![
+            speaker("ずんだもん", &[("ノーマル", 3), ("あまあま", 1)]),
+            speaker("四国めたん", &[("ノーマル", 2)]),
+        ];
+
+        let at_limit = build_speaker_choice_schema(Some(speakers.clone()), Some(3), Some(3));
+        assert_eq!(choice_ids(&at_limit), vec![1, 2, 3]);
+
+        let over_limit = build_speaker_choice_schema(Some(speakers), Some(3), Some(2));
+        let speaker = &over_limit["properties"]["speaker"];
+        assert!(speaker.get("oneOf").is_none());
+        assert_eq!(speaker["type"], "integer");
+        assert_eq!(speaker["default"], 3);
+        assert!(
+            speaker["description"]
+                .as_str()
+                .unwrap()
+                .contains("list_voices")
+        );
+    }
 }
 
-async fn call_voicevox_compatible(
-    port: u16,
-    req: CallToolRequest,
-    default_speaker: Option<u32>,
-) -> Result<CallToolResponse> {
-    let args_val = req
-        .arguments
-        .ok_or_else(|| anyhow::anyhow!("Arguments missing"))?;
-    let args: VoiceEngineArgs = serde_json::from_value(json!(args_val))?;
+/// A non-success response from an engine endpoint.
+#[derive(Debug)]
+struct EngineError {
+    endpoint: String,
+    status: reqwest::StatusCode,
+    body: String,
+}
 
-    // Use argument speaker if provided, otherwise config default, otherwise 1
-    let speaker_id = args.speaker.or(default_speaker).unwrap_or(1);
+impl std::fmt::Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "/{} returned {}: {}",
+            self.endpoint, self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl EngineError {
+    /// A 4xx that blames the speaker or style, e.g. a style removed by an engine update.
+    /// Other client errors (bad text, bad query) don't qualify.
+    fn is_unknown_speaker(&self) -> bool {
+        let body = self.body.to_lowercase();
+        self.status.is_client_error()
+            && ["speaker", "style", "話者", "スタイル"]
+                .iter()
+                .any(|word| body.contains(word))
+    }
+}
 
-    let speed_scale = args.speed.unwrap_or(1.0);
-    let client = reqwest::Client::new();
-    let base_url = format!("http://localhost:{}", port);
+/// Turns a non-2xx engine response into an error that includes the response body,
+/// which usually explains the problem (e.g. an unknown speaker ID).
+async fn ensure_success(resp: reqwest::Response, endpoint: &str) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let body: String = body.chars().take(500).collect();
+    Err(EngineError {
+        endpoint: endpoint.to_string(),
+        status,
+        body,
+    }
+    .into())
+}
 
+async fn audio_query(
+    client: &reqwest::Client,
+    base_url: &str,
+    text: &str,
+    speaker_id: u32,
+) -> Result<serde_json::Value> {
     let query_res = client
         .post(format!("{}/audio_query", base_url))
-        .query(&[("text", &args.text), ("speaker", &speaker_id.to_string())])
+        .query(&[("text", text), ("speaker", &speaker_id.to_string())])
         .send()
         .await?;
-    let mut query_json: serde_json::Value = query_res.json().await?;
-    query_json["speedScale"] = json!(speed_scale);
+    let query_res = ensure_success(query_res, "audio_query").await?;
+    Ok(query_res.json().await?)
+}
 
+async fn synthesis(
+    client: &reqwest::Client,
+    base_url: &str,
+    query_json: &serde_json::Value,
+    speaker_id: u32,
+) -> Result<Vec<u8>> {
     let synthesis_res = client
         .post(format!("{}/synthesis", base_url))
         .query(&[("speaker", &speaker_id.to_string())])
-        .json(&query_json)
+        .json(query_json)
         .send()
         .await?;
-    let wav_data = synthesis_res.bytes().await?;
-
-    play_wav(&wav_data).await?;
+    let synthesis_res = ensure_success(synthesis_res, "synthesis").await?;
+    Ok(synthesis_res.bytes().await?.to_vec())
+}
 
-    Ok(CallToolResponse {
-        content: vec![ToolResponseContent::Text {
-            text: "読み上げ完了！✨".to_string(),
-        }],
-        is_error: Some(false),
-        meta: None,
+/// Resolves a registered engine by name, listing the valid names on failure.
+fn resolve_engine(config: &AppConfig, name: &str) -> Result<EngineTarget> {
+    find_engine(config, name).ok_or_else(|| {
+        let names: Vec<String> = registered_engines(config)
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        let names = names.join(", ");
+        anyhow::anyhow!(Lang::of(config).pick(
+            format!(
+                "エンジン \"{}\" は登録されていません (利用可能: {})",
+                name, names
+            ),
+            format!(
+                "Engine \"{}\" is not registered (available: {})",
+                name, names
+            ),
+        ))
     })
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let transport = ServerStdioTransport;
-    let mut builder = Server::builder(transport)
-        .name("speak-mcp")
-        .version("0.1.0");
+/// Used when `max_concurrent_synthesis` is unset.
+const DEFAULT_MAX_CONCURRENT_SYNTHESIS: usize = 2;
 
-    let config = load_config();
+/// Bounds how many synthesis requests run at once on each engine, so a slow engine can't
+/// hold up calls to the others. Each engine's limit is read from the config the first
+/// time it is used; changing it takes effect after a restart.
+fn synthesis_permits(config: &AppConfig, engine: &EngineTarget) -> Arc<Semaphore> {
+    static PERMITS: OnceLock<Mutex<HashMap<String, Arc<Semaphore>>>> = OnceLock::new();
+    let mut permits = PERMITS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    permits
+        .entry(engine.name.to_ascii_lowercase())
+        .or_insert_with(|| {
+            let limit = config
+                .max_concurrent_synthesis_for(&engine.name)
+                .unwrap_or(DEFAULT_MAX_CONCURRENT_SYNTHESIS)
+                .max(1);
+            Arc::new(Semaphore::new(limit))
+        })
+        .clone()
+}
 
-    // Fetch speakers at startup
-    // Note: We intentionally ignore errors here and fallback to default schema
-    // to ensure the server starts even if TTS engines are down.
-    let voicevox_speakers = fetch_speakers(50021).await;
-    let aivis_speakers = fetch_speakers(10101).await;
+#[cfg(test)]
+mod synthesis_permit_tests {
+    use super::*;
+    use std::time::Duration;
 
-    // VOICEVOX Engine with Dynamic Schema and Config Default
-    let vv_default = config.voicevox_default_speaker;
-    builder.register_tool(
-        Tool {
-            name: "speak_voicevox".to_string(),
-            description: Some("VOICEVOXを使用して読み上げます。(Port: 50021)".to_string()),
-            input_schema: build_speaker_choice_schema(voicevox_speakers, vv_default),
-            output_schema: None,
-        },
-        move |req| {
-            let default = vv_default;
-            Box::pin(async move { call_voicevox_compatible(50021, req, default).await })
-        },
-    );
+    fn engine(name: &str, base_url: &str) -> EngineTarget {
+        EngineTarget {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            kind: Default::default(),
+            base_url: base_url.to_string(),
+            default_speaker: None,
+            default_speed: None,
+        }
+    }
 
-    // Aivis Speech Engine with Dynamic Schema and Config Default
-    let aivis_default = config.aivis_default_speaker;
-    builder.register_tool(
-        Tool {
-            name: "speak_aivis".to_string(),
-            description: Some("Aivis Speechを使用して読み上げます。(Port: 10101)".to_string()),
-            input_schema: build_speaker_choice_schema(aivis_speakers, aivis_default),
-            output_schema: None,
-        },
-        move |req| {
-            let default = aivis_default;
-            Box::pin(async move { call_voicevox_compatible(10101, req, default).await })
-        },
-    );
+    #[tokio::test]
+    async fn a_saturated_engine_does_not_block_another() {
+        let config = AppConfig {
+            max_concurrent_synthesis: Some(1),
+            ..Default::default()
+        };
+        let slow = engine("isolation-saturated", "http://127.0.0.1:1");
+        let other = engine("isolation-neighbour", "http://127.0.0.1:1");
 
-    #[cfg(target_os = "macos")]
-    {
-        builder.register_tool(
-            Tool {
-                name: "speak".to_string(),
-                description: Some("Mac標準のsayコマンドで読み上げます。".to_string()),
-                input_schema: json!({
-                    "type": "object",
-                    "properties": {
-                        "text": { "type": "string" },
-                        "voice": { "type": "string" },
-                        "speed": { "type": "integer" }
-                    },
-                    "required": ["text"]
-                }),
-                output_schema: None,
-            },
-            |req| {
-                // Cloning string again inside closure if needed, but here simple clone for move is enough if we structured it differently.
-                // However, since we can't easily move captured variable into Fn callback multiple times if it's not Copy,
-                // we might need a slight adjustment but for this SDK style:
-                // Actually the closure is `Fn(CallToolRequest) -> Pin<Box<...>>`.
-                // We need to be careful about borrowing.
-                // Let's rely on simple `move` for now assuming `builder.register_tool` takes `impl Fn ... + Send + Sync + 'static`.
-                // String is Clone but not Copy. We can use Arc if needed, or clone inside the closure if logic permits.
-                // Here, we'll try simple move and clone if necessary.
-                // Wait, `builder.register_tool` usually takes a closure that is called multiple times.
-                // So the closure must capture variables.
-                // For `speak_voicevox` `move` works because `u32` is Copy.
-                // For `speak` with `String`, we need to wrap in Arc or clone efficiently.
-                // Let's use a static/const default or Arc for now to be safe.
-                // Actually let's just create a local clone for the closure scope.
+        let _held = synthesis_permits(&config, &slow)
+            .acquire_owned()
+            .await
+            .unwrap();
+        assert!(
+            synthesis_permits(&config, &slow)
+                .try_acquire_owned()
+                .is_err()
+        );
+        let acquired = tokio::time::timeout(
+            Duration::from_secs(1),
+            synthesis_permits(&config, &other).acquire_owned(),
+        )
+        .await;
+        assert!(acquired.is_ok());
+    }
 
-                // Workaround: We can't move non-Copy types into Fn closure easily if we want to use them repeatedly without Clone inside.
-                // But `String` needs to be cloned *per call* if we want to pass it? No, we just need access.
-                // Let's keep it simple: We won't support default voice for `say` in schema yet, just logic.
+    #[tokio::test]
+    async fn a_misconfigured_limit_stays_on_its_own_engine() {
+        let mut config = AppConfig::default();
+        config
+            .engine_max_concurrent_synthesis
+            .insert("isolation-zero".to_string(), 0);
+        config
+            .engine_max_concurrent_synthesis
+            .insert("isolation-three".to_string(), 3);
 
-                Box::pin(async move {
-                    let args_val = req
-                        .arguments
-                        .clone()
-                        .ok_or_else(|| anyhow::anyhow!("Arguments missing"))?;
-                    let args: SpeakArgs = serde_json::from_value(json!(args_val))?;
-
-                    // We need to access configuration here.
-                    // Since passing dynamic config around in this closure structure is tricky without Arc,
-                    // we will re-load config or just not support default voice for Mac in this iteration
-                    // UNLESS we use lazy_static or similar.
-                    // For simplicity, let's just load config again efficiently or use a simple logic.
-                    // Actually, loading config every time is robust for updates!
-                    let current_config = load_config();
-
-                    let mut cmd = Command::new("say");
-                    cmd.arg(&args.text);
-
-                    // Use arg voice, or config default, or system default
-                    if let Some(v) = args.voice.or(current_config.macos_default_voice) {
-                        cmd.arg("-v").arg(v);
-                    }
-                    if let Some(s) = args.speed {
-                        cmd.arg("-r").arg(s.to_string());
-                    }
-                    let status = cmd.status()?;
-                    if status.success() {
-                        Ok(CallToolResponse {
-                            content: vec![ToolResponseContent::Text {
-                                text: "Macのsayで読み上げたよ！🎵".to_string(),
-                            }],
-                            is_error: Some(false),
-                            meta: None,
-                        })
-                    } else {
-                        Err(anyhow::anyhow!("sayコマンド失敗💦"))
-                    }
-                })
-            },
+        let zero = synthesis_permits(&config, &engine("isolation-zero", "http://127.0.0.1:1"));
+        let three = synthesis_permits(&config, &engine("isolation-three", "http://127.0.0.1:1"));
+        assert_eq!(zero.available_permits(), 1);
+        assert_eq!(three.available_permits(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_failing_engine_releases_its_permit() {
+        let config = AppConfig {
+            max_concurrent_synthesis: Some(1),
+            ..Default::default()
+        };
+        // Nothing listens on port 1, so the connection is refused straight away.
+        let down = engine("isolation-down", "http://127.0.0.1:1");
+        let other = engine("isolation-up", "http://127.0.0.1:1");
+
+        assert!(
+            synthesize(&down, &config, "テスト", Some(1), None, None)
+                .await
+                .is_err()
         );
+        assert_eq!(synthesis_permits(&config, &down).available_permits(), 1);
+        assert_eq!(synthesis_permits(&config, &other).available_permits(), 1);
     }
+}
 
-    let server = builder.build();
-    eprintln!("Speak MCP Server (Multi-Engine) 起動中...🌟");
+/// Preprocesses `text` and synthesizes it, returning the WAV bytes.
+async fn synthesize(
+    engine: &EngineTarget,
+    config: &AppConfig,
+    text: &str,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+) -> Result<Vec<u8>> {
+    synthesize_with_query(engine, config, text, speaker, speed, pitch, false)
+        .await
+        .map(|synthesized| synthesized.wav)
+}
+
+struct Synthesized {
+    wav: Vec<u8>,
+    /// The audio query as sent to `/synthesis`, when asked for.
+    query: Option<serde_json::Value>,
+    /// The `fallback_speakers` entry used because the requested speaker was rejected.
+    substitute: Option<u32>,
+}
+
+/// `synthesize`, also returning the audio query when `keep_query` is set. Such calls
+/// skip the cache lookup since cached audio has no query.
+async fn synthesize_with_query(
+    engine: &EngineTarget,
+    config: &AppConfig,
+    text: &str,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+    keep_query: bool,
+) -> Result<Synthesized> {
+    // Use argument speaker if provided, otherwise config default, otherwise 1
+    let speaker_id = speaker.or(engine.default_speaker).unwrap_or(1);
+
+    // Precedence: per-call > speaker_overrides > engine default > engine's own value.
+    // The global multiplier/offset then layer on top of whichever value won, and the
+    // result is clamped to the range the engines accept.
+    let speaker_override = config.speaker_override(speaker_id);
+    let speed_scale = speed
+        .or(speaker_override.and_then(|o| o.speed))
+        .or(engine.default_speed)
+        .unwrap_or(1.0)
+        * config.global_speed_multiplier.unwrap_or(1.0);
+    let speed_scale = speed_scale.clamp(SPEED_SCALE_RANGE.0, SPEED_SCALE_RANGE.1);
+    let text = text::prepare(text, config);
+    let base_url = &engine.base_url;
+    let client = http::client(config, base_url);
+
+    let cache_max_bytes = config
+        .audio_cache_max_bytes
+        .unwrap_or(cache::DEFAULT_MAX_BYTES);
+    let cache_key = format!(
+        "{}|{}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{}",
+        base_url,
+        speaker_id,
+        speed_scale,
+        pitch.or(speaker_override.and_then(|o| o.pitch)),
+        speaker_override.and_then(|o| o.intonation),
+        config.global_pitch_offset,
+        config.target_sample_rate,
+        config.post_process_command,
+        text
+    );
+    if cache_max_bytes > 0 && !keep_query {
+        if let Some(wav_data) = cache::get(&cache_key) {
+            usage_log::record(config, &engine.display_name, json!(speaker_id), &text);
+            return Ok(Synthesized {
+                wav: wav_data,
+                query: None,
+                substitute: None,
+            });
+        }
+    }
+    let _permit = synthesis_permits(config, engine).acquire_owned().await?;
+
+    // A speaker the engine doesn't know (e.g. a style removed by an update) is retried
+    // once with the engine's fallback speaker; the caller reports the substitution.
+    let fallback = config
+        .fallback_speaker(&engine.name)
+        .filter(|fallback| *fallback != speaker_id);
+    let (mut query_json, speaker_id, substitute) = match (
+        audio_query(&client, base_url, &text, speaker_id).await,
+        fallback,
+    ) {
+        (Err(e), Some(fallback))
+            if e.downcast_ref::<EngineError>()
+                .is_some_and(EngineError::is_unknown_speaker) =>
+        {
+            eprintln!(
+                "⚠️ 話者 {} が使えないため {} で読み上げます: {}",
+                speaker_id, fallback, e
+            );
+            let query = audio_query(&client, base_url, &text, fallback).await?;
+            (query, fallback, Some(fallback))
+        }
+        (result, _) => (result?, speaker_id, None),
+    };
+    query_json["speedScale"] = json!(speed_scale);
+    if let Some(intonation) = speaker_override.and_then(|o| o.intonation) {
+        query_json["intonationScale"] = json!(intonation);
+    }
+    let pitch_override = pitch.or(speaker_override.and_then(|o| o.pitch));
+    if pitch_override.is_some() || config.global_pitch_offset.is_some() {
+        let pitch_scale = pitch_override
+            .unwrap_or_else(|| query_json["pitchScale"].as_f64().unwrap_or(0.0) as f32)
+            + config.global_pitch_offset.unwrap_or(0.0);
+        query_json["pitchScale"] =
+            json!(pitch_scale.clamp(PITCH_SCALE_RANGE.0, PITCH_SCALE_RANGE.1));
+    }
+    let wav_data = synthesis(&client, base_url, &query_json, speaker_id).await?;
+    let wav_data = finish_audio(config, wav_data).await;
+    // Substituted audio isn't cached under the requested speaker, so every call keeps
+    // reporting the substitution.
+    if cache_max_bytes > 0 && substitute.is_none() {
+        cache::insert(cache_key, wav_data.clone(), cache_max_bytes);
+    }
+
+    usage_log::record(config, &engine.display_name, json!(speaker_id), &text);
+    Ok(Synthesized {
+        wav: wav_data,
+        query: keep_query.then_some(query_json),
+        substitute,
+    })
+}
+
+/// Applies `target_sample_rate` and then `post_process_command` to audio straight from
+/// an engine, before it is cached, played or saved.
+async fn finish_audio(config: &AppConfig, mut wav_data: Vec<u8>) -> Vec<u8> {
+    if let Some(rate) = config.target_sample_rate.filter(|rate| *rate > 0) {
+        wav_data = wav::resample_to(wav_data, rate);
+    }
+    if let Some(command) = config
+        .post_process_command
+        .as_deref()
+        .filter(|c| !c.is_empty())
+    {
+        wav_data = post_process::apply(command, wav_data).await;
+    }
+    wav_data
+}
+
+/// Used when `emphasis_intonation_scale` / `emphasis_pitch_offset` are unset.
+const DEFAULT_EMPHASIS_INTONATION: f32 = 1.5;
+const DEFAULT_EMPHASIS_PITCH: f32 = 0.03;
+
+/// `synthesize_with_query`, except that with `emphasis_prosody` each markdown-emphasized
+/// span is synthesized on its own with boosted intonation and pitch, and the pieces are
+/// joined. Joined audio has no single audio query, so none is returned for it.
+async fn synthesize_emphasized(
+    engine: &EngineTarget,
+    config: &AppConfig,
+    text: &str,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    pitch: Option<f32>,
+    keep_query: bool,
+) -> Result<Synthesized> {
+    let spans = text::split_emphasis(text);
+    if !config.emphasis_prosody || !spans.iter().any(|(_, emphasized)| *emphasized) {
+        return synthesize_with_query(engine, config, text, speaker, speed, pitch, keep_query)
+            .await;
+    }
+
+    // The boost layers on the speaker's own override, the same way the global offset
+    // does, so an explicit per-call pitch is still raised.
+    let speaker_id = speaker.or(engine.default_speaker).unwrap_or(1);
+    let mut emphasis = config.clone();
+    let mut boosted = config
+        .speaker_override(speaker_id)
+        .cloned()
+        .unwrap_or_default();
+    boosted.intonation = Some(
+        boosted.intonation.unwrap_or(1.0)
+            * config
+                .emphasis_intonation_scale
+                .unwrap_or(DEFAULT_EMPHASIS_INTONATION),
+    );
+    emphasis
+        .speaker_overrides
+        .insert(speaker_id.to_string(), boosted);
+    emphasis.global_pitch_offset = Some(
+        config.global_pitch_offset.unwrap_or(0.0)
+            + config
+                .emphasis_pitch_offset
+                .unwrap_or(DEFAULT_EMPHASIS_PITCH),
+    );
+
+    let last = spans.len() - 1;
+    let mut clips = Vec::with_capacity(spans.len());
+    let mut substitute = None;
+    for (i, (span, emphasized)) in spans.iter().enumerate() {
+        let mut config = if *emphasized {
+            emphasis.clone()
+        } else {
+            config.clone()
+        };
+        if i > 0 {
+            config.text_prefix.clear();
+        }
+        if i < last {
+            config.text_suffix.clear();
+        }
+        let synthesized =
+            synthesize_with_query(engine, &config, span, speaker, speed, pitch, false).await?;
+        substitute = substitute.or(synthesized.substitute);
+        clips.push(synthesized.wav);
+    }
+    Ok(Synthesized {
+        wav: wav::concat(&clips)?,
+        query: None,
+        substitute,
+    })
+}
+
+/// Returns the raw `/audio_query` result so a client can edit moras before synthesis.
+async fn get_audio_query(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: GetAudioQueryArgs = parse_args(req)?;
+
+    let config = load_config();
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => Engine::Voicevox.target(&config),
+    };
+    let speaker_id = args.speaker.or(target.default_speaker).unwrap_or(1);
+    let text = text::prepare(&args.text, &config);
+    if let Some(response) = reject_empty_text(&args.text, &config) {
+        return Ok(response);
+    }
+    let client = http::client(&config, &target.base_url);
+    let query = audio_query(&client, &target.base_url, &text, speaker_id).await?;
+    Ok(text_response(query.to_string()))
+}
+
+/// Plays `text` in the background and returns per-mora timing for it right away, so a
+/// client can highlight the text in sync with playback.
+async fn speak_with_timing(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SpeakWithTimingArgs = parse_args(req)?;
+
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if let Some(response) = reject_empty_text(&args.text, &config) {
+        return Ok(response);
+    }
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => Engine::Voicevox.target(&config),
+    };
+    let synthesized = match synthesize_with_query(
+        &target,
+        &config,
+        &args.text,
+        args.speaker,
+        args.speed,
+        args.pitch,
+        true,
+    )
+    .await
+    {
+        Ok(synthesized) => synthesized,
+        Err(e) => return offline_or(e, &config, &target),
+    };
+    let timing = synthesized
+        .query
+        .as_ref()
+        .map(timing::from_query)
+        .unwrap_or_default();
+    let wav = synthesized.wav;
+
+    let message = if args.play.unwrap_or(true) {
+        let pending = match playback::enqueue(wav, Priority::Normal, true) {
+            Ok(pending) => pending,
+            Err(e) => return Ok(error_response(messages::playback_failed(lang, e))),
+        };
+        tokio::spawn(async move {
+            if let Err(e) = pending.wait().await {
+                eprintln!("Background playback failed: {}", e);
+            }
+        });
+        lang.pick(
+            "音声をバックグラウンドで再生中です🎶",
+            "Playing in the background 🎶",
+        )
+    } else {
+        lang.pick(
+            "再生せずにタイミングだけを返します",
+            "Timing only; nothing was played",
+        )
+    };
+    Ok(text_response(
+        json!({ "message": message, "timing": timing }).to_string(),
+    ))
+}
+
+/// Speaks `text` with VOICEVOX and returns the reading it used, phrase by phrase with
+/// romaji and accents, for learners checking how something is read.
+async fn speak_reading(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SpeakReadingArgs = parse_args(req)?;
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if let Some(response) = reject_empty_text(&args.text, &config) {
+        return Ok(response);
+    }
+    // The reading comes from VOICEVOX's own text analysis, so other engines aren't offered.
+    let target = Engine::Voicevox.target(&config);
+    let synthesized = match synthesize_with_query(
+        &target,
+        &config,
+        &args.text,
+        args.speaker,
+        args.speed,
+        None,
+        true,
+    )
+    .await
+    {
+        Ok(synthesized) => synthesized,
+        Err(e) => return offline_or(e, &config, &target),
+    };
+    let mut reading = synthesized
+        .query
+        .as_ref()
+        .map(|query| kana::study(&args.text, query))
+        .unwrap_or_default();
+
+    if args.play.unwrap_or(true) {
+        let pending = match playback::enqueue(synthesized.wav, Priority::Normal, true) {
+            Ok(pending) => pending,
+            Err(e) => return Ok(error_response(messages::playback_failed(lang, e))),
+        };
+        if let Err(e) = pending.wait().await {
+            return Ok(error_response(messages::playback_failed(lang, e)));
+        }
+    }
+    reading["played"] = json!(args.play.unwrap_or(true));
+    Ok(text_response(reading.to_string()))
+}
+
+/// Checks the parts of an audio query that `/synthesis` needs, so a malformed edit is
+/// reported with the offending field instead of as an opaque 422 from the engine.
+fn validate_audio_query(query: &serde_json::Value, lang: Lang) -> Result<()> {
+    let phrases = query
+        .get("accent_phrases")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| {
+            anyhow::anyhow!(lang.pick(
+                "query.accent_phrases が配列ではありません",
+                "query.accent_phrases is not an array",
+            ))
+        })?;
+    for (i, phrase) in phrases.iter().enumerate() {
+        let moras = phrase
+            .get("moras")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| {
+                anyhow::anyhow!(lang.pick(
+                    format!("accent_phrases[{}].moras が配列ではありません", i),
+                    format!("accent_phrases[{}].moras is not an array", i),
+                ))
+            })?;
+        for (j, mora) in moras.iter().enumerate() {
+            for key in ["vowel_length", "pitch"] {
+                if !mora.get(key).is_some_and(|v| v.is_number()) {
+                    let field = format!("accent_phrases[{}].moras[{}].{}", i, j, key);
+                    return Err(anyhow::anyhow!(lang.pick(
+                        format!("{} が数値ではありません", field),
+                        format!("{} is not a number", field),
+                    )));
+                }
+            }
+        }
+        if !phrase.get("accent").is_some_and(|v| v.is_u64()) {
+            return Err(anyhow::anyhow!(lang.pick(
+                format!("accent_phrases[{}].accent が整数ではありません", i),
+                format!("accent_phrases[{}].accent is not an integer", i),
+            )));
+        }
+    }
+    for key in [
+        "speedScale",
+        "pitchScale",
+        "intonationScale",
+        "volumeScale",
+        "prePhonemeLength",
+        "postPhonemeLength",
+        "outputSamplingRate",
+    ] {
+        if !query.get(key).is_some_and(|v| v.is_number()) {
+            return Err(anyhow::anyhow!(lang.pick(
+                format!("query.{} が数値ではありません", key),
+                format!("query.{} is not a number", key),
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Synthesizes a (possibly edited) audio query and plays it, or saves it to a file.
+async fn synthesize_query(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SynthesizeQueryArgs = parse_args(req)?;
+
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if let Err(e) = validate_audio_query(&args.query, lang) {
+        return Ok(error_response(e.to_string()));
+    }
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => Engine::Voicevox.target(&config),
+    };
+    let speaker_id = args.speaker.or(target.default_speaker).unwrap_or(1);
+    let client = http::client(&config, &target.base_url);
+    let permit = synthesis_permits(&config, &target).acquire_owned().await?;
+    let wav = synthesis(&client, &target.base_url, &args.query, speaker_id).await?;
+    drop(permit);
+    let wav = finish_audio(&config, wav).await;
+
+    // Computed up front since playback consumes the buffer.
+    let waveform = match args.waveform_points {
+        Some(points) => Some(wav::Wav::parse(&wav)?.waveform(points.min(MAX_WAVEFORM_POINTS))),
+        None => None,
+    };
+
+    let message = if let Some(path) = args.output_path {
+        let wav = match export_wav(wav, &args.export, lang) {
+            Ok(wav) => wav,
+            Err(e) => return Ok(error_response(e.to_string())),
+        };
+        if let Err(e) = disk::ensure_space_for_file(path.as_ref(), wav.len() as u64, lang) {
+            return Ok(error_response(e.to_string()));
+        }
+        std::fs::write(&path, &wav)?;
+        messages::saved(lang, &path)
+    } else {
+        let outcome = match playback::enqueue(wav, Priority::Normal, true) {
+            Ok(pending) => pending.wait().await,
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(_) => messages::played(lang).to_string(),
+            Err(e) => return Ok(error_response(messages::playback_failed(lang, e))),
+        }
+    };
+
+    Ok(text_response(match waveform {
+        Some(waveform) => json!({ "message": message, "waveform": waveform }).to_string(),
+        None => message,
+    }))
+}
+
+/// Applies `options` to a WAV before it is written to disk.
+fn export_wav(wav: Vec<u8>, options: &ExportOptions, lang: Lang) -> Result<Vec<u8>> {
+    if options.sample_rate.is_none()
+        && options.channels.is_none()
+        && options.normalize_peak_dbfs.is_none()
+    {
+        return Ok(wav);
+    }
+    if let Some(rate) = options.sample_rate {
+        if !(EXPORT_SAMPLE_RATE_RANGE.0..=EXPORT_SAMPLE_RATE_RANGE.1).contains(&rate) {
+            let (min, max) = EXPORT_SAMPLE_RATE_RANGE;
+            return Err(anyhow::anyhow!(lang.pick(
+                format!(
+                    "sample_rate は {} 〜 {} Hz の範囲で指定してください (指定値: {})",
+                    min, max, rate
+                ),
+                format!(
+                    "sample_rate must be between {} and {} Hz (got {})",
+                    min, max, rate
+                ),
+            )));
+        }
+    }
+    if let Some(peak) = options.normalize_peak_dbfs {
+        if !(peak <= 0.0 && peak.is_finite()) {
+            return Err(anyhow::anyhow!(lang.pick(
+                format!(
+                    "normalize_peak_dbfs は 0 以下で指定してください (指定値: {})",
+                    peak
+                ),
+                format!("normalize_peak_dbfs must be 0 or less (got {})", peak),
+            )));
+        }
+    }
+
+    let mut audio = wav::Wav::parse(&wav)?;
+    if let Some(channels) = options.channels {
+        audio = audio.with_channels(match channels {
+            Channels::Mono => 1,
+            Channels::Stereo => 2,
+        });
+    }
+    if let Some(rate) = options.sample_rate {
+        if rate != audio.sample_rate {
+            audio = audio.resample(rate);
+        }
+    }
+    if let Some(peak) = options.normalize_peak_dbfs {
+        audio = audio.normalize_peak(peak);
+    }
+    Ok(audio.encode())
+}
+
+/// Converts WAV bytes to MP3 with ffmpeg.
+async fn encode_mp3(wav: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut input = tempfile::Builder::new().suffix(".wav").tempfile()?;
+    input.write_all(wav)?;
+    let output = tempfile::Builder::new().suffix(".mp3").tempfile()?;
+    let status = tokio::process::Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(input.path())
+        .arg(output.path())
+        .status()
+        .await
+        .map_err(|e| process::spawn_error("ffmpeg", e))?;
+    if !status.success() {
+        return Err(process::exit_error("ffmpeg", status));
+    }
+    Ok(tokio::fs::read(output.path()).await?)
+}
+
+/// Synthesizes `text` into a WAV file instead of playing it.
+async fn save_speech(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SaveSpeechArgs = parse_args(req)?;
+
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if let Some(response) = reject_empty_text(&args.text, &config) {
+        return Ok(response);
+    }
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => Engine::Voicevox.target(&config),
+    };
+    let format = args
+        .format
+        .or_else(|| config.default_save_format(&target.name))
+        .unwrap_or_default();
+    let request = save_request_key(
+        &target,
+        &config,
+        &args.text,
+        args.speaker,
+        args.speed,
+        &format!("{:?}|{:?}", format, args.export),
+    );
+    if args.skip_if_exists && save_index::is_current(&config, &args.output_path, &request) {
+        return Ok(text_response(lang.pick(
+            format!(
+                "{} は同じ内容で保存済みのため再利用しました",
+                args.output_path
+            ),
+            format!("Reused {}; it already holds this audio", args.output_path),
+        )));
+    }
+    let wav = synthesize(&target, &config, &args.text, args.speaker, args.speed, None).await?;
+    let wav = match export_wav(wav, &args.export, lang) {
+        Ok(wav) => wav,
+        Err(e) => return Ok(error_response(e.to_string())),
+    };
+    let seconds = wav::Wav::parse(&wav)
+        .map(|w| w.duration().as_secs_f64())
+        .unwrap_or(0.0);
+    let audio = match format {
+        SaveFormat::Wav => wav,
+        // VOICEVOX-compatible engines only produce WAV, so MP3 goes through ffmpeg.
+        SaveFormat::Mp3 => match encode_mp3(&wav).await {
+            Ok(mp3) => mp3,
+            Err(e) => {
+                return Ok(error_response(lang.pick(
+                    format!(
+                        "{} の音声を mp3 で保存できません: {}",
+                        target.display_name, e
+                    ),
+                    format!("Cannot save {} audio as mp3: {}", target.display_name, e),
+                )));
+            }
+        },
+    };
+    if let Err(e) = disk::ensure_space_for_file(args.output_path.as_ref(), audio.len() as u64, lang)
+    {
+        return Ok(error_response(e.to_string()));
+    }
+    std::fs::write(&args.output_path, &audio)?;
+    if let Err(e) = save_index::record(&config, &args.output_path, &request, &audio) {
+        eprintln!("⚠️ 保存済みファイルの索引を更新できませんでした: {}", e);
+    }
+    Ok(text_response(lang.pick(
+        format!("{} に保存しました！ ({:.2} 秒)", args.output_path, seconds),
+        format!("Saved to {} ({:.2} s)!", args.output_path, seconds),
+    )))
+}
+
+/// SHA-256 of everything that shapes a saved file, for `skip_if_exists`. `output`
+/// describes the file format and export options.
+fn save_request_key(
+    target: &EngineTarget,
+    config: &AppConfig,
+    text: &str,
+    speaker: Option<u32>,
+    speed: Option<f32>,
+    output: &str,
+) -> String {
+    let speaker_id = speaker.or(target.default_speaker).unwrap_or(1);
+    let key = format!(
+        "{}|{}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{}|{}",
+        target.base_url,
+        speaker_id,
+        speed,
+        target.default_speed,
+        config.global_speed_multiplier,
+        config.global_pitch_offset,
+        config.speaker_override(speaker_id),
+        config.target_sample_rate,
+        config.post_process_command,
+        output,
+        text::prepare(text, config)
+    );
+    save_index::sha256(key.as_bytes())
+}
+
+/// Synthesizes one batch record to its file. Returns `true` if an existing file was
+/// reused because of `skip_if_exists`.
+async fn synthesize_record(
+    config: &AppConfig,
+    record: &BatchRecord,
+    skip_if_exists: bool,
+) -> Result<bool> {
+    if record.text.trim().is_empty() {
+        return Err(anyhow::anyhow!(messages::empty_text(Lang::of(config))));
+    }
+    let target = match &record.engine {
+        Some(name) => resolve_engine(config, name)?,
+        None => default_engine(config).await,
+    };
+    let request = save_request_key(
+        &target,
+        config,
+        &record.text,
+        record.speaker,
+        record.speed,
+        "wav",
+    );
+    if skip_if_exists && save_index::is_current(config, &record.output_path, &request) {
+        return Ok(true);
+    }
+    let wav = synthesize(
+        &target,
+        config,
+        &record.text,
+        record.speaker,
+        record.speed,
+        None,
+    )
+    .await?;
+    disk::ensure_space_for_file(
+        record.output_path.as_ref(),
+        wav.len() as u64,
+        Lang::of(config),
+    )?;
+    tokio::fs::write(&record.output_path, &wav).await?;
+    if let Err(e) = save_index::record(config, &record.output_path, &request, &wav) {
+        eprintln!("⚠️ 保存済みファイルの索引を更新できませんでした: {}", e);
+    }
+    Ok(false)
+}
+
+/// Synthesizes many records to files. Concurrency is bounded by the synthesis
+/// semaphore; failures are collected per record instead of aborting the batch.
+async fn batch_synthesize(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: BatchSynthesizeArgs = parse_args(req)?;
+
+    let mut records = args.records.unwrap_or_default();
+    let mut failures = Vec::new();
+    if let Some(path) = &args.jsonl_path {
+        let content = tokio::fs::read_to_string(path).await?;
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<BatchRecord>(line) {
+                Ok(record) => records.push(record),
+                Err(e) => failures.push(json!({ "line": i + 1, "error": e.to_string() })),
+            }
+        }
+    }
+    let config = std::sync::Arc::new(load_config());
+    if records.is_empty() && failures.is_empty() {
+        return Ok(error_response(Lang::of(&config).pick(
+            "records か jsonl_path を指定してください",
+            "Specify records or jsonl_path",
+        )));
+    }
+
+    let tasks: Vec<_> = records
+        .into_iter()
+        .map(|record| {
+            let config = config.clone();
+            tokio::spawn(async move {
+                let result = synthesize_record(&config, &record, args.skip_if_exists).await;
+                (record.output_path, result)
+            })
+        })
+        .collect();
+
+    let mut succeeded = 0;
+    let mut reused = 0;
+    for task in tasks {
+        let (output_path, result) = task.await?;
+        match result {
+            Ok(false) => succeeded += 1,
+            Ok(true) => reused += 1,
+            Err(e) => failures.push(json!({ "output_path": output_path, "error": e.to_string() })),
+        }
+    }
+
+    Ok(text_response(
+        json!({
+            "succeeded": succeeded,
+            "reused": reused,
+            "failed": failures.len(),
+            "failures": failures,
+        })
+        .to_string(),
+    ))
+}
+
+/// Synthesizes now and keeps the audio under a token for `play_prepared`.
+async fn prepare_speech(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: PrepareSpeechArgs = parse_args(req)?;
+
+    let config = load_config();
+    if let Some(response) = reject_empty_text(&args.text, &config) {
+        return Ok(response);
+    }
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => default_engine(&config).await,
+    };
+    let wav = synthesize(
+        &target,
+        &config,
+        &args.text,
+        args.speaker,
+        args.speed,
+        args.pitch,
+    )
+    .await?;
+
+    let ttl = config
+        .prepared_ttl_secs
+        .map(Duration::from_secs)
+        .unwrap_or(prepared::DEFAULT_TTL);
+    let token = prepared::insert(wav, ttl);
+    Ok(text_response(
+        json!({ "token": token, "expires_in_secs": ttl.as_secs() }).to_string(),
+    ))
+}
+
+async fn play_prepared(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: PlayPreparedArgs = parse_args(req)?;
+
+    let lang = Lang::of(&load_config());
+    let Some(wav) = prepared::get(&args.token) else {
+        return Ok(error_response(lang.pick(
+            format!(
+                "トークン {} の音声はありません (期限切れの可能性があります)",
+                args.token
+            ),
+            format!("No audio for token {} (it may have expired)", args.token),
+        )));
+    };
+    let outcome = match playback::enqueue(wav, args.priority, true) {
+        Ok(pending) => pending.wait().await,
+        Err(e) => Err(e),
+    };
+    match outcome {
+        Ok(_) => Ok(text_response(messages::played(lang))),
+        Err(e) => Ok(error_response(messages::playback_failed(lang, e))),
+    }
+}
+
+async fn call_voicevox_compatible(
+    engine: Engine,
+    req: CallToolRequest,
+) -> Result<CallToolResponse> {
+    let mut args: VoiceEngineArgs = parse_args(req)?;
+
+    // Config is re-read per call so defaults changed at runtime take effect immediately.
+    let config = load_config();
+    if let Err(e) = read_resource_text(&config, &mut args.text, args.resource_uri.as_deref()).await
+    {
+        return Ok(error_response(e.to_string()));
+    }
+    speak_on(&engine.target(&config), &config, args).await
+}
+
+/// Unified entry point: speaks with any registered engine.
+async fn speak_auto(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SpeakAutoArgs = parse_args(req)?;
+    speak_auto_with(args).await
+}
+
+/// Replaces `text` with the content of `resource_uri`, if one was given. Passing both
+/// is rejected since it's unclear which one the caller meant.
+async fn read_resource_text(
+    config: &AppConfig,
+    text: &mut String,
+    resource_uri: Option<&str>,
+) -> Result<()> {
+    let Some(uri) = resource_uri else {
+        return Ok(());
+    };
+    if !text.trim().is_empty() {
+        return Err(anyhow::anyhow!(Lang::of(config).pick(
+            "text と resource_uri はどちらか一方だけを指定してください",
+            "Pass either text or resource_uri, not both",
+        )));
+    }
+    *text = resource::read_text(uri, config).await?;
+    Ok(())
+}
+
+/// Fills in engine and prosody from the `named_voices` entry picked by `voice`.
+fn apply_named_voice(config: &AppConfig, args: &mut SpeakAutoArgs) -> Result<()> {
+    if let Some(voice_name) = &args.voice {
+        let voice = config.named_voices.get(voice_name).ok_or_else(|| {
+            let lang = Lang::of(&config);
+            let names: Vec<&str> = config.named_voices.keys().map(String::as_str).collect();
+            let names = if names.is_empty() {
+                lang.pick("なし", "none").to_string()
+            } else {
+                names.join(", ")
+            };
+            anyhow::anyhow!(lang.pick(
+                format!(
+                    "ボイス \"{}\" は named_voices に定義されていません (定義済み: {})",
+                    voice_name, names
+                ),
+                format!(
+                    "Voice \"{}\" is not defined in named_voices (defined: {})",
+                    voice_name, names
+                ),
+            ))
+        })?;
+        args.engine = Some(voice.engine.clone());
+        args.speak.speaker = args.speak.speaker.or(voice.speaker);
+        args.speak.speed = args.speak.speed.or(voice.speed);
+        args.speak.pitch = args.speak.pitch.or(voice.pitch);
+    }
+    Ok(())
+}
+
+async fn speak_auto_with(mut args: SpeakAutoArgs) -> Result<CallToolResponse> {
+    let config = load_config();
+    if let Err(e) = read_resource_text(
+        &config,
+        &mut args.speak.text,
+        args.speak.resource_uri.as_deref(),
+    )
+    .await
+    {
+        return Ok(error_response(e.to_string()));
+    }
+    let language = text::detect_language(&args.speak.text);
+    let requested = requested_voice(&args);
+    apply_named_voice(&config, &mut args)?;
+    let (target, engine_source) = auto_target(&config, &args, language).await?;
+    let Some(target) = target else {
+        #[cfg(target_os = "macos")]
+        {
+            let say_args = SpeakArgs {
+                text: args.speak.text,
+                resource_uri: None,
+                voice: None,
+                locale: None,
+                speed: None,
+                verbose: false,
+            };
+            let response = say(say_args, &config).await?;
+            let explain = say_description(&config, engine_source);
+            return Ok(with_verbose_note(
+                response,
+                &config,
+                args.verbose.then_some(explain),
+                language,
+                "say",
+            ));
+        }
+        #[cfg(not(target_os = "macos"))]
+        unreachable!("auto_target only picks say on macOS");
+    };
+    let explain = args.verbose.then(|| {
+        let mut explain = explain::voice(&config, &target, &requested);
+        explain["engine"] = json!({ "name": target.name, "source": engine_source });
+        explain
+    });
+    let response = speak_on(&target, &config, args.speak).await?;
+    // The part of the text that was selected, known only once `speak_on` has sliced it.
+    let explain = explain.map(|mut explain| {
+        if let Some(range) = response.meta.as_ref().and_then(|meta| meta.get("range")) {
+            explain["range"] = range.clone();
+        }
+        explain
+    });
+    Ok(with_verbose_note(
+        response,
+        &config,
+        explain,
+        language,
+        &target.name,
+    ))
+}
+
+/// The call's own voice settings, captured before `apply_named_voice` merges the named
+/// voice into them.
+fn requested_voice(args: &SpeakAutoArgs) -> explain::Requested {
+    explain::Requested {
+        voice: args.voice.clone(),
+        speaker: args.speak.speaker,
+        speed: args.speak.speed,
+        pitch: args.speak.pitch,
+    }
+}
+
+/// Picks the engine for a `speak_auto` call (after `apply_named_voice`) and names the
+/// layer that decided it. `None` means macOS `say`.
+async fn auto_target(
+    config: &AppConfig,
+    args: &SpeakAutoArgs,
+    language: Language,
+) -> Result<(Option<EngineTarget>, &'static str)> {
+    if let Some(name) = &args.engine {
+        let source = if args.voice.is_some() {
+            "named_voice"
+        } else {
+            "call"
+        };
+        return Ok((Some(resolve_engine(config, name)?), source));
+    }
+    // Only English text is rerouted, and only when an English engine is configured.
+    if let (Language::English, Some(name)) = (language, config.english_engine.as_deref()) {
+        if cfg!(target_os = "macos") && name == "say" {
+            return Ok((None, "english_engine"));
+        }
+        return Ok((Some(resolve_engine(config, name)?), "english_engine"));
+    }
+    let source = if config.engine_priority.is_empty() {
+        "builtin"
+    } else {
+        "engine_priority"
+    };
+    Ok((Some(default_engine(config).await), source))
+}
+
+/// `explain::say` for a `speak_auto` call routed to `say`, which passes no voice, locale
+/// or rate of its own.
+fn say_description(config: &AppConfig, engine_source: &str) -> serde_json::Value {
+    #[cfg(target_os = "macos")]
+    let mut description = explain::say(
+        &say_voice_layers(None, None, config),
+        &say_rate_layers(None),
+    );
+    // Only reached on macOS; elsewhere auto_target never picks say.
+    #[cfg(not(target_os = "macos"))]
+    let mut description = {
+        let _ = config;
+        json!({})
+    };
+    description["engine"] = json!({ "name": "say", "source": engine_source });
+    description
+}
+
+/// Explains which engine, speaker, speed and pitch a `speak_auto` call with these
+/// arguments would use, without synthesizing.
+async fn describe_config(req: CallToolRequest) -> Result<CallToolResponse> {
+    let mut args: SpeakAutoArgs = parse_args(req)?;
+    let config = load_config();
+    let language = text::detect_language(&args.speak.text);
+    let requested = requested_voice(&args);
+    apply_named_voice(&config, &mut args)?;
+    let (target, engine_source) = auto_target(&config, &args, language).await?;
+    let mut description = match &target {
+        Some(target) => {
+            let mut description = explain::voice(&config, target, &requested);
+            description["engine"] = json!({ "name": target.name, "source": engine_source });
+            description
+        }
+        None => say_description(&config, engine_source),
+    };
+    let session_path = speak_common::session_config_path();
+    description["config_path"] = json!(get_config_path().display().to_string());
+    description["session_config"] = json!({
+        "path": session_path.display().to_string(),
+        "active": config.use_session_config && session_path.exists(),
+    });
+    if !args.speak.text.trim().is_empty() {
+        description["language"] = json!(language.name());
+    }
+    Ok(text_response(serde_json::to_string_pretty(&description)?))
+}
+
+/// Writes the effective config (session overrides and `SPEAK_MCP_*_URL` included) to a
+/// file that can be loaded as a config later.
+///
+/// Without `include_secrets`, `engine_headers` values are kept only when they read from
+/// the environment (`${VAR}`), and the `proxy_url` credentials are removed. With it,
+/// `${VAR}` references are expanded too, so the file works without those variables.
+async fn export_config(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: ExportConfigArgs = parse_args(req)?;
+    let lang = Lang::of(&load_config());
+    let path = std::path::PathBuf::from(args.path.trim());
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let invalid = if !path.is_absolute() {
+        Some(lang.pick("path は絶対パスで指定してください", "path must be absolute"))
+    } else if !matches!(extension.as_deref(), Some("json" | "toml")) {
+        Some(lang.pick(
+            "path の拡張子は .json か .toml にしてください",
+            "path must end in .json or .toml",
+        ))
+    } else if path.is_dir() {
+        Some(lang.pick("path はフォルダです", "path is a directory"))
+    } else if path.parent().is_none_or(|parent| !parent.is_dir()) {
+        Some(lang.pick(
+            "path のフォルダが存在しません",
+            "path's directory doesn't exist",
+        ))
+    } else if std::path::absolute(get_config_path()).is_ok_and(|config_path| config_path == path) {
+        Some(lang.pick(
+            "使用中の設定ファイルには書き出せません",
+            "Can't export over the config file in use",
+        ))
+    } else {
+        None
+    };
+    if let Some(reason) = invalid {
+        return Ok(error_response(reason));
+    }
+    if path.exists() && !args.overwrite {
+        return Ok(error_response(lang.pick(
+            format!(
+                "{} は既に存在します。上書きするには overwrite: true を指定してください",
+                path.display()
+            ),
+            format!(
+                "{} already exists; pass overwrite: true to replace it",
+                path.display()
+            ),
+        )));
+    }
+
+    let mut config = load_config();
+    for engine in Engine::ALL {
+        if std::env::var(engine.url_env_var()).is_ok_and(|url| !url.trim().is_empty()) {
+            let url = Some(engine.base_url(&config));
+            match engine {
+                Engine::Voicevox => config.voicevox_base_url = url,
+                Engine::Aivis => config.aivis_base_url = url,
+            }
+        }
+    }
+    let mut omitted = 0;
+    if args.include_secrets {
+        for value in config
+            .engine_headers
+            .values_mut()
+            .flat_map(|h| h.values_mut())
+        {
+            *value = http::expand_env(value);
+        }
+    } else {
+        for headers in config.engine_headers.values_mut() {
+            let before = headers.len();
+            headers.retain(|_, value| value.contains("${"));
+            omitted += before - headers.len();
+        }
+        config
+            .engine_headers
+            .retain(|_, headers| !headers.is_empty());
+        let proxy = config
+            .proxy_url
+            .as_deref()
+            .and_then(|url| reqwest::Url::parse(url).ok())
+            .filter(|url| !url.username().is_empty() || url.password().is_some());
+        if let Some(mut url) = proxy {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            config.proxy_url = Some(url.to_string());
+            omitted += 1;
+        }
+    }
+
+    speak_common::export_config(&config, &path, args.overwrite)?;
+    let mut message = lang.pick(
+        format!("設定を {} に書き出しました", path.display()),
+        format!("Exported the config to {}", path.display()),
+    );
+    if omitted > 0 {
+        message.push_str(&lang.pick(
+            format!(
+                " (秘密の値 {} 件を除きました。含めるには include_secrets: true)",
+                omitted
+            ),
+            format!(
+                " ({} secret value(s) left out; pass include_secrets: true to keep them)",
+                omitted
+            ),
+        ));
+    }
+    Ok(text_response(message))
+}
+
+/// Resolves a `speak_auto` request like `speak_auto_with` but returns the WAV instead of
+/// playing it. English text is not rerouted to `say`, which can't produce audio data.
+#[cfg(feature = "http")]
+async fn synthesize_auto(mut args: SpeakAutoArgs) -> Result<Vec<u8>> {
+    let mut config = load_config();
+    read_resource_text(
+        &config,
+        &mut args.speak.text,
+        args.speak.resource_uri.as_deref(),
+    )
+    .await?;
+    if args.speak.text.trim().is_empty() {
+        return Err(anyhow::anyhow!(messages::empty_text(Lang::of(&config))));
+    }
+    apply_named_voice(&config, &mut args)?;
+    if let Some(spell) = args.speak.spell_numbers {
+        config.spell_numbers = spell;
+    }
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => match (
+            text::detect_language(&args.speak.text),
+            config.english_engine.as_deref(),
+        ) {
+            (Language::English, Some(name)) if name != "say" => resolve_engine(&config, name)?,
+            _ => default_engine(&config).await,
+        },
+    };
+    let speak = args.speak;
+    synthesize(
+        &target,
+        &config,
+        &speak.text,
+        speak.speaker,
+        speak.speed,
+        speak.pitch,
+    )
+    .await
+}
+
+/// Used when `sticky_engine_secs` is unset.
+const DEFAULT_STICKY_ENGINE_SECS: u64 = 300;
+
+/// How long `sticky_working_engine` keeps a fallback engine in front.
+fn sticky_duration(config: &AppConfig) -> Duration {
+    Duration::from_secs(
+        config
+            .sticky_engine_secs
+            .unwrap_or(DEFAULT_STICKY_ENGINE_SECS),
+    )
+}
+
+/// First reachable engine in `engine_priority`, or VOICEVOX when none is configured.
+/// If every listed engine is down, the first one is used so the error names it.
+///
+/// With `sticky_working_engine`, an engine reached only after an earlier one failed is
+/// tried first on later calls until `sticky_engine_secs` pass.
+async fn default_engine(config: &AppConfig) -> EngineTarget {
+    let ttl = health::ttl(config.health_cache_ttl_secs);
+    let candidates: Vec<EngineTarget> = config
+        .engine_priority
+        .iter()
+        .filter_map(|name| find_engine(config, name))
+        .collect();
+    if config.sticky_working_engine {
+        let sticky = health::sticky(sticky_duration(config))
+            .and_then(|(name, _)| candidates.iter().find(|e| e.name == name));
+        if let Some(engine) = sticky {
+            if health::check(&engine.base_url, ttl).await.reachable {
+                return engine.clone();
+            }
+            health::unstick();
+        }
+    }
+    for (i, engine) in candidates.iter().enumerate() {
+        if health::check(&engine.base_url, ttl).await.reachable {
+            if config.sticky_working_engine && i > 0 {
+                eprintln!(
+                    "Using {} until {} is back (sticky_working_engine)",
+                    engine.name, candidates[0].name
+                );
+                health::stick(&engine.name);
+            }
+            return engine.clone();
+        }
+    }
+    candidates
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| Engine::Voicevox.target(config))
+}
+
+async fn set_engine_priority(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SetEnginePriorityArgs = parse_args(req)?;
+
+    let config = load_config();
+    let lang = Lang::of(&config);
+    let mut names = Vec::with_capacity(args.engines.len());
+    for name in &args.engines {
+        // Stored by canonical name so lookups don't depend on the caller's casing.
+        let engine = resolve_engine(&config, name)?;
+        if names.contains(&engine.name) {
+            return Err(anyhow::anyhow!(lang.pick(
+                format!("エンジン \"{}\" が重複しています", engine.name),
+                format!("Engine \"{}\" is listed more than once", engine.name),
+            )));
+        }
+        names.push(engine.name);
+    }
+
+    let order = names.join(" > ");
+    update_config(|config| config.engine_priority = names)?;
+    health::unstick();
+    Ok(text_response(if order.is_empty() {
+        lang.pick(
+            "エンジンの優先順位を解除しました (VOICEVOX を使います)",
+            "Cleared the engine priority (VOICEVOX will be used)",
+        )
+        .to_string()
+    } else {
+        lang.pick(
+            format!("エンジンの優先順位を {} に設定しました！", order),
+            format!("Set the engine priority to {}!", order),
+        )
+    }))
+}
+
+/// Used when `verbose_max_chars` is unset.
+const DEFAULT_VERBOSE_MAX_CHARS: usize = 2000;
+
+/// Adds verbose `detail` to `response`: as its own content block, or appended to the
+/// message with `verbose_inline`. Detail longer than `verbose_max_chars` is cut off
+/// with a note, since clients show it all in the transcript.
+fn push_verbose(response: &mut CallToolResponse, config: &AppConfig, detail: serde_json::Value) {
+    let mut text = detail.to_string();
+    let limit = config
+        .verbose_max_chars
+        .unwrap_or(DEFAULT_VERBOSE_MAX_CHARS);
+    let chars = text.chars().count();
+    if chars > limit {
+        text = text.chars().take(limit).collect();
+        text.push_str(&Lang::of(config).pick(
+            format!(
+                "… (詳細が長いため {} 文字中 {} 文字で省略しました。verbose_max_chars で変更できます)",
+                chars, limit
+            ),
+            format!(
+                "… (detail cut at {} of {} characters; see verbose_max_chars)",
+                limit, chars
+            ),
+        ));
+    }
+    let inline = response
+        .content
+        .iter_mut()
+        .find_map(|content| match content {
+            ToolResponseContent::Text { text } => Some(text),
+            _ => None,
+        });
+    match inline.filter(|_| config.verbose_inline) {
+        Some(message) => {
+            message.push('\n');
+            message.push_str(&text);
+        }
+        None => response.content.push(ToolResponseContent::Text { text }),
+    }
+}
+
+/// Adds the detected language, the engine and, under `explain`, how the voice was
+/// resolved. `explain` is only set for verbose calls.
+fn with_verbose_note(
+    mut response: CallToolResponse,
+    config: &AppConfig,
+    explain: Option<serde_json::Value>,
+    language: Language,
+    engine: &str,
+) -> CallToolResponse {
+    if let Some(explain) = explain {
+        push_verbose(
+            &mut response,
+            config,
+            json!({ "language": language.name(), "engine": engine, "explain": explain }),
+        );
+    }
+    response
+}
+
+/// Cuts `text` down to the `start`/`end` or `sentences` selection, returning what was
+/// selected for `_meta.range`. `Err` holds a message for the caller when the selection
+/// is out of range.
+fn select_range(
+    args: &mut VoiceEngineArgs,
+    lang: Lang,
+) -> Result<Option<serde_json::Value>, String> {
+    if let Some([from, to]) = args.sentences {
+        if args.start.is_some() || args.end.is_some() {
+            return Err(lang
+                .pick(
+                    "sentences と start / end は同時に指定できません",
+                    "sentences can't be combined with start / end",
+                )
+                .to_string());
+        }
+        let all = speak_common::text::split_sentences(&args.text);
+        if from == 0 || from > to || to > all.len() {
+            return Err(lang.pick(
+                format!(
+                    "sentences [{}, {}] は範囲外です (1 〜 {} 文目を指定してください)",
+                    from,
+                    to,
+                    all.len()
+                ),
+                format!(
+                    "sentences [{}, {}] is out of range (the text has sentences 1 to {})",
+                    from,
+                    to,
+                    all.len()
+                ),
+            ));
+        }
+        args.text = all[from - 1..to].join("\n");
+        return Ok(Some(
+            json!({ "sentences": [from, to], "total_sentences": all.len() }),
+        ));
+    }
+    if args.start.is_none() && args.end.is_none() {
+        return Ok(None);
+    }
+    let total = args.text.chars().count();
+    let start = args.start.unwrap_or(0);
+    let end = args.end.unwrap_or(total);
+    if start >= end || end > total {
+        return Err(lang.pick(
+            format!(
+                "start {} / end {} は範囲外です (0 ≦ start < end ≦ {})",
+                start, end, total
+            ),
+            format!(
+                "start {} / end {} is out of range (0 <= start < end <= {})",
+                start, end, total
+            ),
+        ));
+    }
+    args.text = args.text.chars().skip(start).take(end - start).collect();
+    Ok(Some(
+        json!({ "start": start, "end": end, "total_chars": total }),
+    ))
+}
+
+/// Aborts the task when dropped. When one chunk fails, `speak_on` returns early and the
+/// chunks after it are dropped with it; without this they would keep holding engine
+/// permits and sending requests whose audio nobody plays.
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Splits long text into the chunks `speak_on` synthesizes separately, or `None` when it
+/// is spoken as one piece. High priority keeps a single item: each chunk would jump the
+/// queue ahead of the previous one and reverse the order. In paragraphs mode a line
+/// break is only wrapping, so whole paragraphs are the chunks.
+///
+/// Markdown and URLs are handled before splitting (`text::prepare_whole`), since a
+/// table spans lines and a URL can contain `?`.
+fn speech_chunks(text: &str, config: &AppConfig, priority: Priority) -> Option<Vec<String>> {
+    let min = config.chunk_min_chars?;
+    if priority != Priority::Normal || text.chars().count() <= min {
+        return None;
+    }
+    let text = text::prepare_whole(text, config);
+    Some(match config.newline_mode {
+        NewlineMode::Paragraphs => speak_common::text::split_paragraphs(&text),
+        _ => speak_common::text::split_sentences(&text),
+    })
+}
+
+#[cfg(test)]
+mod speech_chunk_tests {
+    use super::*;
+    use speak_common::UrlHandling;
+
+    #[test]
+    fn tables_and_urls_are_handled_before_splitting() {
+        let config = AppConfig {
+            chunk_min_chars: Some(1),
+            linearize_markdown: true,
+            url_handling: UrlHandling::Domain,
+            ..Default::default()
+        };
+        let text = "結果です。\n| 名前 | 年齢 |\n|---|---|\n| Alice | 30 |\n\
+                    詳細は https://example.com/search?q=1 を参照。";
+        assert_eq!(
+            speech_chunks(text, &config, Priority::Normal).unwrap(),
+            [
+                "結果です。",
+                "1行目、名前 Alice、年齢 30。",
+                "詳細は example.com を参照。",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dropping_a_chunk_task_aborts_it() {
+        let finished = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = finished.clone();
+        let task = AbortOnDrop(tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+        }));
+        drop(task);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!finished.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn short_or_high_priority_text_is_not_split() {
+        let config = AppConfig {
+            chunk_min_chars: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(speech_chunks("短い。文。", &config, Priority::Normal), None);
+        let long = "一つ目の文です。二つ目の文です。";
+        assert_eq!(speech_chunks(long, &config, Priority::High), None);
+        assert_eq!(
+            speech_chunks(long, &config, Priority::Normal)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
+
+async fn speak_on(
+    engine: &EngineTarget,
+    config: &AppConfig,
+    mut args: VoiceEngineArgs,
+) -> Result<CallToolResponse> {
+    let lang = Lang::of(config);
+    let range = match select_range(&mut args, lang) {
+        Ok(range) => range,
+        Err(message) => return Ok(error_response(message)),
+    };
+    if let Some(response) = reject_empty_text(&args.text, config) {
+        return Ok(response);
+    }
+    let disposition = match args.return_audio {
+        Some(true) => AudioDisposition::Return,
+        Some(false) => AudioDisposition::Play,
+        None => config.audio_disposition(&engine.name),
+    };
+    // High priority already says what to do with the current utterance, so it wins.
+    let mode = match args.priority {
+        Priority::High => PlaybackMode::Queue,
+        Priority::Normal => args.playback_mode.unwrap_or(config.playback_mode),
+    };
+    let status = playback::status();
+    if disposition.plays() && mode == PlaybackMode::Drop && (status.playing || status.pending > 0) {
+        let mut response = text_response(lang.pick(
+            "再生中の読み上げがあるため、読み上げませんでした",
+            "Not spoken because something else is playing",
+        ));
+        response.meta = Some(json!({ "skipped": true, "reason": "busy" }));
+        return Ok(response);
+    }
+    let mut meta = range.map(|range| json!({ "range": range }));
+    if let Some(limit) = config.max_text_chars {
+        let chars = args.text.chars().count();
+        if chars > limit {
+            if !config.truncate_over_limit {
+                return Ok(error_response(lang.pick(
+                    format!("text が長すぎます ({} 文字、上限 {} 文字)", chars, limit),
+                    format!("text is too long ({} characters, limit {})", chars, limit),
+                )));
+            }
+            let kept = text::truncate_at_sentence(&args.text, limit);
+            let spoken_chars = kept.chars().count();
+            args.text = format!(
+                "{}{}",
+                kept,
+                text::omission_note(text::detect_language(kept))
+            );
+            let meta = meta.get_or_insert_with(|| json!({}));
+            meta["truncated"] = json!(true);
+            meta["original_chars"] = json!(chars);
+            meta["spoken_chars"] = json!(spoken_chars);
+        }
+    }
+    let mut call_config;
+    let config = match args.spell_numbers {
+        Some(spell) if spell != config.spell_numbers => {
+            call_config = config.clone();
+            call_config.spell_numbers = spell;
+            &call_config
+        }
+        _ => config,
+    };
+    let mut note = if args.speaker.is_none() && engine.default_speaker.is_none() {
+        default_speaker_note(engine, lang).await
+    } else {
+        String::new()
+    };
+
+    // Long text is synthesized in chunks, concurrently but queued in order.
+    let split = speech_chunks(&args.text, config, args.priority);
+    let prepared_whole = split.is_some();
+    let chunks = split.unwrap_or_else(|| vec![args.text.clone()]);
+
+    // Playback failures are reported as a tool error rather than a protocol error, so
+    // the client sees that nothing was heard instead of a generic failure.
+    let resume_queue = args.resume_queue.unwrap_or(true);
+    let verify_kana = args.verify_kana.unwrap_or(config.verify_kana);
+    let last = chunks.len().saturating_sub(1);
+    let tasks: Vec<_> = chunks
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let engine = engine.clone();
+            let mut config = config.clone();
+            if prepared_whole {
+                text::skip_whole_text_steps(&mut config);
+            }
+            // Prefix and suffix belong to the whole utterance, not to every sentence.
+            if i > 0 {
+                config.text_prefix.clear();
+            }
+            if i < last {
+                config.text_suffix.clear();
+            }
+            let (speaker, speed, pitch) = (args.speaker, args.speed, args.pitch);
+            AbortOnDrop(tokio::spawn(async move {
+                synthesize_emphasized(&engine, &config, &chunk, speaker, speed, pitch, verify_kana)
+                    .await
+            }))
+        })
+        .collect();
+    let gap_ms = config.inter_sentence_ms.unwrap_or(0);
+    let mut pending = Vec::with_capacity(tasks.len());
+    let mut substitute = None;
+    let mut readings = Vec::new();
+    let mut mismatches = Vec::new();
+    let mut duration = Duration::ZERO;
+    let mut audio = Vec::new();
+    for (i, mut task) in tasks.into_iter().enumerate() {
+        let synthesized = match (&mut task.0).await? {
+            Ok(synthesized) => synthesized,
+            Err(e) => {
+                // Earlier chunks are already queued, so only the rest is saved.
+                if is_retryable(&e) {
+                    failed_log::record(
+                        config,
+                        &engine.name,
+                        args.speaker,
+                        args.speed,
+                        args.pitch,
+                        &chunks[i..].join("\n"),
+                        &e,
+                    );
+                }
+                return offline_or(e, config, engine);
+            }
+        };
+        substitute = substitute.or(synthesized.substitute);
+        if let Some(reading) = synthesized.query.as_ref().and_then(kana::reading) {
+            mismatches.extend(kana::mismatches(config, &chunks[i], &reading));
+            readings.push(json!({ "text": chunks[i], "kana": reading }));
+        }
+        let mut wav_data = synthesized.wav;
+        if gap_ms > 0 && i < last {
+            // Padding the audio keeps the gap exact without holding the queue open.
+            wav_data = wav::append_silence(wav_data, gap_ms);
+        }
+        duration += wav::Wav::parse(&wav_data)
+            .map(|wav| wav.duration())
+            .unwrap_or_default();
+        if disposition.returns() {
+            audio.push(json!({
+                "mime_type": playback::mime_type(&wav_data),
+                "data": BASE64.encode(&wav_data),
+            }));
+        }
+        if !disposition.plays() {
+            continue;
+        }
+        if i == 0 && mode == PlaybackMode::Interrupt {
+            // Cut off only once the new audio is ready, so there is no silent gap.
+            playback::stop_all();
+        }
+        match playback::enqueue(wav_data, args.priority, resume_queue) {
+            Ok(item) => pending.push(item),
+            Err(e) => return Ok(error_response(messages::playback_failed(lang, e))),
+        }
+    }
+
+    if verify_kana {
+        for mismatch in &mismatches {
+            note.push_str(&lang.pick(
+                format!(
+                    "\n(⚠️ 「{}」が kana_overrides の読み「{}」と違う読みになっています)",
+                    mismatch["word"].as_str().unwrap_or_default(),
+                    mismatch["expected"].as_str().unwrap_or_default()
+                ),
+                format!(
+                    "\n(⚠️ \"{}\" was not read as \"{}\" from kana_overrides)",
+                    mismatch["word"].as_str().unwrap_or_default(),
+                    mismatch["expected"].as_str().unwrap_or_default()
+                ),
+            ));
+        }
+        let meta = meta.get_or_insert_with(|| json!({}));
+        meta["kana"] = json!({ "readings": readings, "mismatches": mismatches });
+    }
+
+    if let Some(fallback) = substitute {
+        note.push_str(&lang.pick(
+            format!(
+                "\n(指定の話者が{}で使えなかったため、代わりに話者 ID {} で読み上げました)",
+                engine.display_name, fallback
+            ),
+            format!(
+                "\n(The requested speaker isn't available on {}; used speaker ID {} instead)",
+                engine.display_name, fallback
+            ),
+        ));
+    }
+
+    // Saved only once synthesis has succeeded, so an ID the engine rejects never
+    // becomes the default.
+    if let Some(speaker) = args
+        .speaker
+        .filter(|_| args.remember && substitute.is_none())
+    {
+        let mut saved = false;
+        update_config(|config| saved = config.set_default_speaker(&engine.name, speaker))?;
+        if saved {
+            note.push_str(&lang.pick(
+                format!(
+                    "\n(話者 ID {} を{}のデフォルトに設定しました)",
+                    speaker, engine.display_name
+                ),
+                format!(
+                    "\n(Speaker ID {} is now the {} default)",
+                    speaker, engine.display_name
+                ),
+            ));
+        } else {
+            // Discovered engines live only in memory, so there is nothing to save to.
+            note.push_str(&lang.pick(
+                format!(
+                    "\n({}は設定ファイルに登録されていないため、話者 ID {} をデフォルトとして保存できませんでした)",
+                    engine.display_name, speaker
+                ),
+                format!(
+                    "\n({} isn't in the config file, so speaker ID {} couldn't be saved as its default)",
+                    engine.display_name, speaker
+                ),
+            ));
+        }
+    }
+
+    if disposition.returns() {
+        // One entry per chunk when long text was split, in playing order.
+        let meta = meta.get_or_insert_with(|| json!({}));
+        meta["audio"] = json!(audio);
+    }
+    if !disposition.plays() {
+        let mut response = text_response(format!(
+            "{}{}",
+            lang.pick(
+                "音声データを _meta.audio で返しました (再生はしていません)",
+                "Returned the audio in _meta.audio without playing it",
+            ),
+            note
+        ));
+        response.meta = meta;
+        return Ok(response);
+    }
+
+    // Fires only when the whole call was heard, not for stopped, skipped or muted speech.
+    let on_complete = config
+        .on_complete_command
+        .clone()
+        .filter(|command| !command.trim().is_empty())
+        .map(|command| {
+            let speaker =
+                substitute.unwrap_or(args.speaker.or(engine.default_speaker).unwrap_or(1));
+            (command, engine.name.clone(), speaker, duration)
+        });
+
+    if args.async_playback.unwrap_or(false) {
+        // Nobody is left to receive a failure, so the detached task only logs it.
+        tokio::spawn(async move {
+            let mut played = true;
+            for item in pending {
+                match item.wait().await {
+                    Ok(Outcome::Played) => {}
+                    Ok(_) => played = false,
+                    Err(e) => {
+                        eprintln!("Background playback failed: {}", e);
+                        played = false;
+                    }
+                }
+            }
+            if let Some((command, engine, speaker, duration)) = on_complete.filter(|_| played) {
+                on_complete::run(&command, &engine, speaker, duration);
+            }
+        });
+        let mut response = text_response(lang.pick(
+            format!("音声をバックグラウンドで再生中です🎶{}", note),
+            format!("Playing in the background 🎶{}", note),
+        ));
+        response.meta = meta;
+        return Ok(response);
+    }
+
+    // The first chunk that didn't play decides the message.
+    let mut outcome = Outcome::Played;
+    for item in pending {
+        match item.wait().await {
+            Ok(Outcome::Played) => {}
+            Ok(other) => {
+                if outcome == Outcome::Played {
+                    outcome = other;
+                }
+            }
+            Err(e) => return Ok(error_response(messages::playback_failed(lang, e))),
+        }
+    }
+    if let Some((command, engine, speaker, duration)) =
+        on_complete.filter(|_| outcome == Outcome::Played)
+    {
+        on_complete::run(&command, &engine, speaker, duration);
+    }
+
+    let message = match outcome {
+        Outcome::Played => messages::played(lang),
+        Outcome::Interrupted => lang.pick("読み上げを途中で止めました", "Speech was stopped"),
+        Outcome::Discarded => lang.pick(
+            "割り込みにより読み上げをスキップしました",
+            "Skipped because another message interrupted",
+        ),
+        Outcome::Muted => lang.pick(
+            "一時停止中のため再生しませんでした🔇",
+            "Not played because speech is paused 🔇",
+        ),
+    };
+    let mut response = text_response(format!("{}{}", message, note));
+    response.meta = meta;
+    Ok(response)
+}
+
+/// True when `error` comes from not reaching the engine at all (connection refused,
+/// unresolvable host, timeout) rather than from the engine rejecting the request.
+fn is_engine_offline(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_connect() || e.is_timeout())
+    })
+}
+
+/// Failures worth trying again later: the engine was unreachable or broke on its side.
+/// Client errors (bad text, unknown speaker) would fail the same way again.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    is_engine_offline(error)
+        || error.chain().any(|cause| {
+            cause
+                .downcast_ref::<EngineError>()
+                .is_some_and(|e| e.status.is_server_error())
+        })
+}
+
+/// Speaks the entries saved in `failed_log_path` again and removes the ones that worked.
+/// Once an engine fails again its remaining entries are left for a later retry instead
+/// of each waiting for the same timeout.
+async fn retry_failed(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: RetryFailedArgs = parse_args(req)?;
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if config.failed_log_path.is_none() {
+        return Ok(error_response(lang.pick(
+            "failed_log_path が設定されていません",
+            "failed_log_path is not configured",
+        )));
+    }
+    let mut entries = failed_log::entries(&config)?;
+    entries.truncate(args.limit.unwrap_or(usize::MAX));
+
+    // A retry that fails again must not be saved a second time.
+    let mut retry_config = config.clone();
+    retry_config.failed_log_path = None;
+    let mut spoken = Vec::new();
+    let mut failed_engines: Vec<String> = Vec::new();
+    for entry in &entries {
+        if failed_engines.contains(&entry.engine) {
+            continue;
+        }
+        let target = match resolve_engine(&config, &entry.engine) {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("Cannot retry failed speech: {}", e);
+                failed_engines.push(entry.engine.clone());
+                continue;
+            }
+        };
+        let speak = VoiceEngineArgs {
+            text: entry.text.clone(),
+            resource_uri: None,
+            speaker: entry.speaker,
+            speed: entry.speed,
+            pitch: entry.pitch,
+            priority: Priority::Normal,
+            resume_queue: None,
+            playback_mode: None,
+            async_playback: None,
+            spell_numbers: None,
+            verify_kana: None,
+            return_audio: None,
+            start: None,
+            end: None,
+            sentences: None,
+            remember: false,
+        };
+        let succeeded = match speak_on(&target, &retry_config, speak).await {
+            Ok(response) => {
+                response.is_error != Some(true)
+                    && !response
+                        .meta
+                        .as_ref()
+                        .is_some_and(|meta| meta["skipped"] == json!(true))
+            }
+            Err(e) => {
+                eprintln!("Retry on {} failed: {}", entry.engine, e);
+                false
+            }
+        };
+        if succeeded {
+            spoken.push(entry.clone());
+        } else {
+            failed_engines.push(entry.engine.clone());
+        }
+    }
+    failed_log::remove(&config, &spoken)?;
+
+    let remaining = failed_log::entries(&config)?.len();
+    let mut response = text_response(lang.pick(
+        format!(
+            "{} 件を読み上げ直しました (残り {} 件)",
+            spoken.len(),
+            remaining
+        ),
+        format!(
+            "Re-spoke {} saved failures ({} remaining)",
+            spoken.len(),
+            remaining
+        ),
+    ));
+    response.meta = Some(json!({
+        "attempted": entries.len(),
+        "spoken": spoken.len(),
+        "remaining": remaining,
+    }));
+    Ok(response)
+}
+
+/// Passes `error` on, unless the engine is unreachable and `offline_behavior` is `skip`;
+/// then the call succeeds with a note saying nothing was spoken.
+fn offline_or(
+    error: anyhow::Error,
+    config: &AppConfig,
+    engine: &EngineTarget,
+) -> Result<CallToolResponse> {
+    if config.offline_behavior != OfflineBehavior::Skip || !is_engine_offline(&error) {
+        return Err(error);
+    }
+    eprintln!("Skipped speech: {} is offline ({})", engine.name, error);
+    let mut response = text_response(Lang::of(config).pick(
+        format!(
+            "{}に接続できないため読み上げをスキップしました",
+            engine.display_name
+        ),
+        format!("{} is offline; skipped speaking", engine.display_name),
+    ));
+    response.meta = Some(json!({
+        "skipped": true,
+        "reason": "engine_offline",
+        "engine": engine.name,
+    }));
+    Ok(response)
+}
+
+/// Number of voices listed when nudging the user to pick a default speaker.
+const SPEAKER_NOTE_LIMIT: usize = 10;
+
+/// Explains that ID 1 was used because no speaker is configured, and lists a few
+/// alternatives.
+///
+/// async-mcp has no elicitation support, so the server can't ask the client to choose
+/// before speaking; this note is the fallback.
+async fn default_speaker_note(engine: &EngineTarget, lang: Lang) -> String {
+    let mut note = String::from(lang.pick(
+        "\n(話者が未設定のため ID 1 を使いました。set_default_speaker で変更できます",
+        "\n(No speaker is configured, so ID 1 was used. Change it with set_default_speaker",
+    ));
+    if let Some(speakers) = speakers::speakers(&engine.base_url).await {
+        let choices: Vec<String> = speakers
+            .iter()
+            .flat_map(|speaker| {
+                speaker.styles.iter().map(move |style| {
+                    format!("{} ({}) [ID: {}]", speaker.name, style.name, style.id)
+                })
+            })
+            .take(SPEAKER_NOTE_LIMIT)
+            .collect();
+        let choices = choices.join(", ");
+        note.push_str(&lang.pick(format!("。例: {}", choices), format!(". e.g. {}", choices)));
+    }
+    note.push(')');
+    note
+}
+
+/// Adds streamed text to a session and queues the sentences it completed. With `finish`
+/// the rest of the text is queued too and the session ends.
+///
+/// Synthesis happens in the background so the caller can keep streaming; failures are
+/// logged since nobody is waiting for them.
+async fn speak_stream(req: CallToolRequest, finish: bool) -> Result<CallToolResponse> {
+    let args: SpeakStreamArgs = parse_args(req)?;
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if !playback::is_supported(&config) {
+        return Ok(error_response(messages::playback_failed(
+            lang,
+            format!(
+                "no audio backend available on this platform ({})",
+                std::env::consts::OS
+            ),
+        )));
+    }
+    let engine = match args.engine.as_deref() {
+        Some(name) => resolve_engine(&config, name)?,
+        None => default_engine(&config).await,
+    };
+    let voice = stream::Voice {
+        engine,
+        speaker: args.speaker,
+        speed: args.speed,
+        pitch: args.pitch,
+    };
+    let ttl = config
+        .stream_session_ttl_secs
+        .map(Duration::from_secs)
+        .unwrap_or(stream::DEFAULT_TTL);
+    let batch = stream::push(&args.session_id, &args.text, finish, voice, ttl);
+    let queued = batch.sentences.len();
+    let buffered = batch.buffered_chars;
+
+    tokio::spawn(async move {
+        let last = batch.sentences.len().saturating_sub(1);
+        let tasks: Vec<_> = batch
+            .sentences
+            .into_iter()
+            .enumerate()
+            .map(|(i, sentence)| {
+                let voice = batch.voice.clone();
+                let mut config = config.clone();
+                // Prefix and suffix belong to the whole stream, like chunks in `speak_on`.
+                if i > 0 || !batch.first {
+                    config.text_prefix.clear();
+                }
+                if i < last || !batch.last {
+                    config.text_suffix.clear();
+                }
+                tokio::spawn(async move {
+                    synthesize(
+                        &voice.engine,
+                        &config,
+                        &sentence,
+                        voice.speaker,
+                        voice.speed,
+                        voice.pitch,
+                    )
+                    .await
+                })
+            })
+            .collect();
+        let mut wavs = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            match task.await {
+                Ok(Ok(wav)) => wavs.push(wav),
+                Ok(Err(e)) => eprintln!("Stream synthesis failed: {}", e),
+                Err(e) => eprintln!("Stream synthesis failed: {}", e),
+            }
+        }
+        if let Some(previous) = batch.after {
+            let _ = previous.await;
+        }
+        for wav in wavs {
+            if let Err(e) = playback::enqueue(wav, Priority::Normal, true) {
+                eprintln!("Background playback failed: {}", e);
+                break;
+            }
+        }
+        let _ = batch.done.send(());
+    });
+
+    let message = if finish {
+        lang.pick(
+            format!(
+                "ストリームを終了し、残り {} 文を読み上げキューに追加しました",
+                queued
+            ),
+            format!("Stream finished; queued the last {} sentence(s)", queued),
+        )
+    } else {
+        lang.pick(
+            format!(
+                "{} 文を読み上げキューに追加しました (文の途中の {} 文字は保留中)",
+                queued, buffered
+            ),
+            format!(
+                "Queued {} sentence(s) ({} characters waiting for the sentence to end)",
+                queued, buffered
+            ),
+        )
+    };
+    Ok(text_response(message))
+}
+
+/// Speaks a preset from `quick_phrases` with the default engine and speaker.
+async fn speak_phrase(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SpeakPhraseArgs = parse_args(req)?;
+    let config = load_config();
+    let Some(text) = config.quick_phrases.get(&args.phrase) else {
+        let lang = Lang::of(&config);
+        let keys: Vec<&str> = config.quick_phrases.keys().map(String::as_str).collect();
+        let keys = if keys.is_empty() {
+            lang.pick("なし", "none").to_string()
+        } else {
+            keys.join(", ")
+        };
+        return Ok(error_response(lang.pick(
+            format!(
+                "フレーズ \"{}\" は quick_phrases にありません (利用可能: {})",
+                args.phrase, keys
+            ),
+            format!(
+                "Phrase \"{}\" is not in quick_phrases (available: {})",
+                args.phrase, keys
+            ),
+        )));
+    };
+    let speak = VoiceEngineArgs {
+        text: text.clone(),
+        resource_uri: None,
+        speaker: None,
+        speed: None,
+        pitch: None,
+        priority: Priority::Normal,
+        resume_queue: None,
+        playback_mode: None,
+        async_playback: args.async_playback,
+        spell_numbers: None,
+        verify_kana: None,
+        return_audio: None,
+        start: None,
+        end: None,
+        sentences: None,
+        remember: false,
+    };
+    let target = default_engine(&config).await;
+    speak_on(&target, &config, speak).await
+}
+
+async fn speak_lines(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SpeakLinesArgs = parse_args(req)?;
+
+    let config = load_config();
+    let engine = resolve_engine(&config, &args.engine)?;
+    let pause = Duration::from_millis(args.pause_ms.unwrap_or(500));
+    let lines: Vec<&String> = args.lines.iter().filter(|l| !l.trim().is_empty()).collect();
+    if lines.is_empty() {
+        return Ok(reject_empty_text("", &config).unwrap());
+    }
+
+    let mut spoken = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let line = if args.numbered {
+            format!("{}つ目、{}", i + 1, line)
+        } else {
+            line.to_string()
+        };
+        let wav = match synthesize(&engine, &config, &line, args.speaker, args.speed, None).await {
+            Ok(wav) => wav,
+            Err(e) => return offline_or(e, &config, &engine),
+        };
+        let outcome = playback::enqueue(wav, Priority::Normal, true)?
+            .wait()
+            .await?;
+        if outcome == Outcome::Discarded {
+            // Flushed by a high-priority interruption; drop the rest of the list too.
+            break;
+        }
+        spoken += 1;
+        if i + 1 < lines.len() {
+            tokio::time::sleep(pause).await;
+        }
+    }
+
+    Ok(text_response(Lang::of(&config).pick(
+        format!("{} 行中 {} 行を読み上げました！✨", lines.len(), spoken),
+        format!("Spoke {} of {} lines! ✨", spoken, lines.len()),
+    )))
+}
+
+/// Speaks a conversation as one stereo clip: every segment is synthesized with its own
+/// voice, placed at its `pan` position and joined with `pause_ms` of silence, so
+/// speakers can be told apart by where they sound from.
+async fn speak_dialogue(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SpeakDialogueArgs = parse_args(req)?;
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if args.segments.iter().all(|s| s.text.trim().is_empty()) {
+        return Ok(reject_empty_text("", &config).unwrap());
+    }
+    if let Some((i, pan)) = args
+        .segments
+        .iter()
+        .enumerate()
+        .find_map(|(i, s)| s.pan.filter(|p| !(-1.0..=1.0).contains(p)).map(|p| (i, p)))
+    {
+        return Ok(error_response(lang.pick(
+            format!(
+                "segments[{}] の pan ({}) は -1.0 から 1.0 の範囲で指定してください",
+                i, pan
+            ),
+            format!("segments[{}].pan ({}) must be between -1.0 and 1.0", i, pan),
+        )));
+    }
+
+    let pause_ms = args.pause_ms.unwrap_or(300);
+    let mut joined: Option<wav::Wav> = None;
+    let mut spoken = 0;
+    for segment in args.segments.iter().filter(|s| !s.text.trim().is_empty()) {
+        let engine = match &segment.engine {
+            Some(name) => resolve_engine(&config, name)?,
+            None => default_engine(&config).await,
+        };
+        let data = match synthesize(
+            &engine,
+            &config,
+            &segment.text,
+            segment.speaker,
+            segment.speed,
+            segment.pitch,
+        )
+        .await
+        {
+            Ok(data) => data,
+            Err(e) => return offline_or(e, &config, &engine),
+        };
+        let clip = wav::Wav::parse(&data)?;
+        // Engines differ in sample rate, so everything follows the first segment.
+        let clip = match &joined {
+            Some(joined) if joined.sample_rate != clip.sample_rate => {
+                clip.resample(joined.sample_rate)
+            }
+            _ => clip,
+        };
+        let clip = clip.pan(segment.pan.unwrap_or(0.0));
+        match &mut joined {
+            Some(joined) => {
+                let frames = joined.sample_rate as u64 * pause_ms / 1000;
+                joined
+                    .samples
+                    .extend(std::iter::repeat_n(0, frames as usize * 2));
+                joined.samples.extend(clip.samples);
+            }
+            None => joined = Some(clip),
+        }
+        spoken += 1;
+    }
+    let Some(joined) = joined else {
+        return Ok(reject_empty_text("", &config).unwrap());
+    };
+
+    let duration = joined.duration();
+    let outcome = playback::enqueue(joined.encode(), Priority::Normal, true)?
+        .wait()
+        .await?;
+    let mut response = text_response(match outcome {
+        Outcome::Played => lang.pick(
+            format!("{} 個のセリフを読み上げました！✨", spoken),
+            format!("Spoke {} dialogue lines! ✨", spoken),
+        ),
+        _ => lang.pick(
+            "会話の読み上げは中断されました".to_string(),
+            "The dialogue was interrupted".to_string(),
+        ),
+    });
+    response.meta = Some(json!({
+        "segments": spoken,
+        "duration_secs": duration.as_secs_f64(),
+    }));
+    Ok(response)
+}
+
+/// Sings a simple score with VOICEVOX's `/sing_frame_audio_query` + `/frame_synthesis`.
+async fn sing_voicevox(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SingArgs = parse_args(req)?;
+    let config = load_config();
+    let lang = Lang::of(&config);
+    if args.notes.is_empty() {
+        return Ok(error_response(
+            lang.pick("notes が空です", "notes is empty"),
+        ));
+    }
+
+    let engine = Engine::Voicevox.target(&config);
+    let base_url = engine.base_url.clone();
+    let client = http::client(&config, &base_url);
+
+    // Checked against /singers rather than /speaker_info: speaker_info needs a speaker
+    // UUID and only describes portraits and samples, while /singers lists exactly the
+    // styles that /sing_frame_audio_query and /frame_synthesis accept.
+    let singers_res = client.get(format!("{}/singers", base_url)).send().await?;
+    let singers: Vec<SpeakerInfo> = ensure_success(singers_res, "singers").await?.json().await?;
+    let is_singer = singers
+        .iter()
+        .any(|s| s.styles.iter().any(|style| style.id == args.speaker));
+    if !is_singer {
+        return Ok(error_response(lang.pick(
+            format!("話者ID {} は歌唱に対応していません", args.speaker),
+            format!("Speaker ID {} cannot sing", args.speaker),
+        )));
+    }
+
+    let mut notes: Vec<serde_json::Value> = args
+        .notes
+        .iter()
+        .map(|note| {
+            let frames = (note.duration_ms as f64 * SING_FRAMES_PER_SECOND / 1000.0).round();
+            json!({
+                "key": note.key,
+                "frame_length": (frames as u32).max(1),
+                "lyric": if note.key.is_some() { note.lyric.as_str() } else { "" },
+            })
+        })
+        .collect();
+    // The engine requires the score to start with a rest.
+    if args.notes[0].key.is_some() {
+        notes.insert(0, json!({ "key": null, "frame_length": 15, "lyric": "" }));
+    }
+
+    let teacher = args.teacher.unwrap_or(DEFAULT_SING_TEACHER);
+    let permit = synthesis_permits(&config, &engine).acquire_owned().await?;
+    let query_res = client
+        .post(format!("{}/sing_frame_audio_query", base_url))
+        .query(&[("speaker", teacher.to_string())])
+        .json(&json!({ "notes": notes }))
+        .send()
+        .await?;
+    let query_json: serde_json::Value = ensure_success(query_res, "sing_frame_audio_query")
+        .await?
+        .json()
+        .await?;
+
+    let synthesis_res = client
+        .post(format!("{}/frame_synthesis", base_url))
+        .query(&[("speaker", args.speaker.to_string())])
+        .json(&query_json)
+        .send()
+        .await?;
+    let wav_data = ensure_success(synthesis_res, "frame_synthesis")
+        .await?
+        .bytes()
+        .await?
+        .to_vec();
+    drop(permit);
+    let wav_data = finish_audio(&config, wav_data).await;
+
+    let outcome = match playback::enqueue(wav_data, Priority::Normal, true) {
+        Ok(pending) => pending.wait().await,
+        Err(e) => Err(e),
+    };
+    match outcome {
+        Ok(Outcome::Played) => Ok(text_response(
+            lang.pick("歌い終わりました🎤", "Finished singing 🎤"),
+        )),
+        Ok(_) => Ok(text_response(
+            lang.pick("歌を途中で止めました", "Singing was stopped"),
+        )),
+        Err(e) => Ok(error_response(messages::playback_failed(lang, e))),
+    }
+}
+
+/// Summarizes latencies as min/median/max milliseconds.
+fn latency_summary(mut samples: Vec<f64>) -> serde_json::Value {
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let median = match samples.len() {
+        0 => 0.0,
+        n if n % 2 == 0 => (samples[n / 2 - 1] + samples[n / 2]) / 2.0,
+        n => samples[n / 2],
+    };
+    json!({
+        "min_ms": samples.first().copied().unwrap_or(0.0),
+        "median_ms": median,
+        "max_ms": samples.last().copied().unwrap_or(0.0),
+    })
+}
+
+async fn benchmark(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: BenchmarkArgs = parse_args(req)?;
+
+    let iterations = args
+        .iterations
+        .unwrap_or(5)
+        .clamp(1, MAX_BENCHMARK_ITERATIONS);
+    let config = load_config();
+    let engine = resolve_engine(&config, &args.engine)?;
+    let speaker_id = args.speaker.or(engine.default_speaker).unwrap_or(1);
+    let base_url = engine.base_url.clone();
+    let client = http::client(&config, &base_url);
+
+    // Audio is synthesized but never played.
+    let mut query_ms = Vec::new();
+    let mut synthesis_ms = Vec::new();
+    let mut total_ms = Vec::new();
+    for _ in 0..iterations {
+        // Taken before the clock starts, so waiting behind other calls isn't measured.
+        let _permit = synthesis_permits(&config, &engine).acquire_owned().await?;
+        let started = Instant::now();
+        let query_json = audio_query(&client, &base_url, BENCHMARK_TEXT, speaker_id).await?;
+        let queried = Instant::now();
+        synthesis(&client, &base_url, &query_json, speaker_id).await?;
+        let finished = Instant::now();
+
+        query_ms.push((queried - started).as_secs_f64() * 1000.0);
+        synthesis_ms.push((finished - queried).as_secs_f64() * 1000.0);
+        total_ms.push((finished - started).as_secs_f64() * 1000.0);
+    }
+
+    Ok(text_response(
+        json!({
+            "engine": engine.display_name,
+            "base_url": base_url,
+            "speaker": speaker_id,
+            "iterations": iterations,
+            "audio_query": latency_summary(query_ms),
+            "synthesis": latency_summary(synthesis_ms),
+            "total": latency_summary(total_ms),
+        })
+        .to_string(),
+    ))
+}
+
+async fn list_engines() -> Result<CallToolResponse> {
+    let config = load_config();
+    let engines: Vec<serde_json::Value> = registered_engines(&config)
+        .into_iter()
+        .map(|e| {
+            json!({
+                "name": e.name,
+                "display_name": e.display_name,
+                "kind": e.kind,
+                "base_url": e.base_url,
+                "default_speaker": e.default_speaker,
+                "default_speed": e.default_speed,
+            })
+        })
+        .collect();
+    Ok(text_response(json!(engines).to_string()))
+}
+
+/// Lists the styles of one engine (VOICEVOX by default) from the shared speaker list.
+async fn list_voices(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: ListVoicesArgs = match req.arguments {
+        Some(_) => parse_args(req)?,
+        None => ListVoicesArgs { engine: None },
+    };
+
+    let config = load_config();
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => default_engine(&config).await,
+    };
+    let Some(speakers) = speakers::speakers(&target.base_url).await else {
+        return Ok(error_response(Lang::of(&config).pick(
+            format!(
+                "{}に接続できないため話者一覧を取得できません💦",
+                target.display_name
+            ),
+            format!(
+                "Cannot list speakers because {} is not reachable",
+                target.display_name
+            ),
+        )));
+    };
+
+    let voices: Vec<serde_json::Value> = speakers
+        .iter()
+        .flat_map(|speaker| {
+            speaker.styles.iter().map(move |style| {
+                json!({ "id": style.id, "speaker": speaker.name, "style": style.name })
+            })
+        })
+        .collect();
+    Ok(text_response(json!(voices).to_string()))
+}
+
+/// Reads a flag from the manifest's `supported_features`; missing flags count as
+/// unsupported.
+fn manifest_feature(manifest: &serde_json::Value, name: &str) -> bool {
+    manifest["supported_features"][name]
+        .as_bool()
+        .unwrap_or(false)
+}
+
+/// One machine-readable summary of every engine: reachability, version, feature flags
+/// from `/engine_manifest` and defaults. Probes are cached for the health TTL.
+async fn capabilities() -> Result<CallToolResponse> {
+    let config = load_config();
+    let ttl = health::ttl(config.health_cache_ttl_secs);
+    let mut engines = Vec::new();
+    for engine in registered_engines(&config) {
+        let health = health::check(&engine.base_url, ttl).await;
+        let manifest = if health.reachable {
+            health::manifest(&engine.base_url, ttl).await
+        } else {
+            None
+        };
+        let features = manifest.map(|manifest| {
+            json!({
+                "prosody": ["adjust_speed_scale", "adjust_pitch_scale", "adjust_intonation_scale"]
+                    .iter()
+                    .all(|name| manifest_feature(&manifest, name)),
+                "morphing": manifest_feature(&manifest, "synthesis_morphing"),
+                "singing": manifest_feature(&manifest, "sing"),
+            })
+        });
+        engines.push(json!({
+            "name": engine.name,
+            "kind": engine.kind,
+            "base_url": engine.base_url,
+            "reachable": health.reachable,
+            "version": health.version,
+            "features": features,
+            "default_speaker": engine.default_speaker,
+        }));
+    }
+    Ok(text_response(
+        json!({
+            "engines": engines,
+            "playback": playback::is_supported(&config),
+            "say": cfg!(target_os = "macos"),
+        })
+        .to_string(),
+    ))
+}
+
+/// Reports whether each registered engine is reachable, using the health cache.
+async fn engine_status() -> Result<CallToolResponse> {
+    let config = load_config();
+    let ttl = health::ttl(config.health_cache_ttl_secs);
+    let sticky = health::sticky(sticky_duration(&config)).filter(|_| config.sticky_working_engine);
+    let mut engines = Vec::new();
+    for engine in registered_engines(&config) {
+        let health = health::check(&engine.base_url, ttl).await;
+        // Unknown (null) when the engine is down, has no default or lacks the endpoint.
+        let initialized = match engine.default_speaker.filter(|_| health.reachable) {
+            Some(speaker) => initialize::is_initialized(&config, &engine.base_url, speaker)
+                .await
+                .ok(),
+            None => None,
+        };
+        engines.push(json!({
+            "name": engine.name,
+            "base_url": engine.base_url,
+            "reachable": health.reachable,
+            "cache_age_ms": health.age.as_millis() as u64,
+            "default_speaker": engine.default_speaker,
+            "default_speaker_initialized": initialized,
+            "sticky": sticky.as_ref().is_some_and(|(name, _)| *name == engine.name),
+        }));
+    }
+    let mut response = text_response(json!(engines).to_string());
+    if let Some((name, remaining)) = sticky {
+        response.meta = Some(json!({
+            "sticky_engine": { "name": name, "expires_in_secs": remaining.as_secs() },
+        }));
+    }
+    Ok(response)
+}
+
+/// Loads a speaker's model now so its first synthesis isn't slowed down by loading.
+async fn initialize_speaker(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: InitializeSpeakerArgs = parse_args(req)?;
+    let config = load_config();
+    let lang = Lang::of(&config);
+    let engine = match args.engine.as_deref() {
+        Some(name) => resolve_engine(&config, name)?,
+        None => default_engine(&config).await,
+    };
+    let Some(speaker) = args.speaker.or(engine.default_speaker) else {
+        return Ok(error_response(lang.pick(
+            format!(
+                "{}にはデフォルト話者がないため speaker を指定してください",
+                engine.display_name
+            ),
+            format!(
+                "{} has no default speaker; pass speaker",
+                engine.display_name
+            ),
+        )));
+    };
+    let started = Instant::now();
+    let already = initialize::ensure(&config, &engine.base_url, speaker).await?;
+    Ok(text_response(
+        json!({
+            "engine": engine.name,
+            "speaker": speaker,
+            "already_initialized": already,
+            "elapsed_ms": started.elapsed().as_millis() as u64,
+        })
+        .to_string(),
+    ))
+}
+
+/// Drops cached health results and refetches every engine's speaker list.
+async fn reload_voices() -> Result<CallToolResponse> {
+    health::invalidate();
+    let config = load_config();
+    let lang = Lang::of(&config);
+    let mut lines = Vec::new();
+    for engine in registered_engines(&config) {
+        let name = &engine.display_name;
+        lines.push(match fetch_speakers(&engine.base_url).await {
+            Some(speakers) => lang.pick(
+                format!("✅ {}: {} 人", name, speakers.len()),
+                format!("✅ {}: {} speakers", name, speakers.len()),
+            ),
+            None => lang.pick(
+                format!("❌ {}: 接続できません", name),
+                format!("❌ {}: not reachable", name),
+            ),
+        });
+    }
+    Ok(text_response(lines.join("\n")))
+}
+
+/// Text synthesized by `diagnostics`.
+const DIAGNOSTICS_TEXT: &str = "テスト";
+/// Bytes of each response body included in the `diagnostics` report.
+const DIAGNOSTICS_PREVIEW_BYTES: usize = 32;
+
+/// The config as `diagnostics` reports it: header values and proxy credentials are
+/// replaced, since reports are meant to be pasted into public issues.
+fn redacted_config(config: &AppConfig) -> serde_json::Value {
+    let mut value = serde_json::to_value(config).unwrap_or_default();
+    if let Some(headers) = value
+        .get_mut("engine_headers")
+        .and_then(|v| v.as_object_mut())
+    {
+        for engine in headers.values_mut().filter_map(|v| v.as_object_mut()) {
+            for header in engine.values_mut() {
+                *header = json!("<redacted>");
+            }
+        }
+    }
+    let proxy = value
+        .get("proxy_url")
+        .and_then(|v| v.as_str())
+        .and_then(|url| reqwest::Url::parse(url).ok())
+        .filter(|url| !url.username().is_empty() || url.password().is_some());
+    if let Some(mut url) = proxy {
+        let _ = url.set_username("redacted");
+        let _ = url.set_password(None);
+        value["proxy_url"] = json!(url.to_string());
+    }
+    value
+}
+
+/// Sends one request and records what happened, without failing on errors. Returns the
+/// body on success so the next step can use it.
+async fn diagnostic_step(
+    name: &str,
+    request: reqwest::RequestBuilder,
+) -> (serde_json::Value, Option<Vec<u8>>) {
+    let started = Instant::now();
+    let result = match request.send().await {
+        Ok(resp) => {
+            let status = resp.status();
+            resp.bytes()
+                .await
+                .map(|body| (status, body.to_vec()))
+                .map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok((status, body)) => {
+            let preview = &body[..body.len().min(DIAGNOSTICS_PREVIEW_BYTES)];
+            let report = json!({
+                "step": name,
+                "status": status.as_u16(),
+                "elapsed_ms": elapsed_ms,
+                "body_bytes": body.len(),
+                "body_preview": String::from_utf8_lossy(preview),
+                "body_preview_hex": preview.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            });
+            (report, status.is_success().then_some(body))
+        }
+        Err(error) => (
+            json!({ "step": name, "elapsed_ms": elapsed_ms, "error": error }),
+            None,
+        ),
+    }
+}
+
+/// Runs `/version`, `/audio_query` and `/synthesis` once and reports every step, for
+/// attaching to bug reports. Steps after a failure are skipped, not errors.
+async fn diagnostics(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: DiagnosticsArgs = match req.arguments {
+        Some(_) => parse_args(req)?,
+        None => DiagnosticsArgs::default(),
+    };
+
+    let config = load_config();
+    let target = match &args.engine {
+        Some(name) => resolve_engine(&config, name)?,
+        None => Engine::Voicevox.target(&config),
+    };
+    let speaker = args.speaker.or(target.default_speaker).unwrap_or(1);
+    let base_url = &target.base_url;
+    let client = http::client(&config, base_url);
+
+    let mut steps = Vec::new();
+    let (report, version) =
+        diagnostic_step("version", client.get(format!("{}/version", base_url))).await;
+    steps.push(report);
+    let (report, query) = diagnostic_step(
+        "audio_query",
+        client.post(format!("{}/audio_query", base_url)).query(&[
+            ("text", DIAGNOSTICS_TEXT),
+            ("speaker", &speaker.to_string()),
+        ]),
+    )
+    .await;
+    steps.push(report);
+    if let Some(query) = query {
+        let (report, _) = diagnostic_step(
+            "synthesis",
+            client
+                .post(format!("{}/synthesis", base_url))
+                .query(&[("speaker", speaker.to_string())])
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(query),
+        )
+        .await;
+        steps.push(report);
+    }
+
+    let engine_version = version.map(|body| {
+        String::from_utf8_lossy(&body)
+            .trim()
+            .trim_matches('"')
+            .to_string()
+    });
+    Ok(text_response(
+        json!({
+            "speak_mcp_version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "config_path": get_config_path(),
+            "config": redacted_config(&config),
+            "engine": {
+                "name": target.name,
+                "base_url": target.base_url,
+                "version": engine_version,
+            },
+            "speaker": speaker,
+            "playback_supported": playback::is_supported(&config),
+            "steps": steps,
+        })
+        .to_string(),
+    ))
+}
+
+const SPEAKER_RESOURCE_SCHEME: &str = "speak://";
+
+#[derive(Debug, Deserialize)]
+struct ReadResourceParams {
+    uri: String,
+}
+
+fn speaker_resource_uri(engine: &str) -> String {
+    format!("{}{}/speakers", SPEAKER_RESOURCE_SCHEME, engine)
+}
+
+fn list_speaker_resources() -> serde_json::Value {
+    let config = load_config();
+    let resources: Vec<serde_json::Value> = registered_engines(&config)
+        .into_iter()
+        .map(|e| {
+            json!({
+                "uri": speaker_resource_uri(&e.name),
+                "name": format!("{} speakers", e.display_name),
+                "mimeType": "application/json",
+            })
+        })
+        .collect();
+    json!({ "resources": resources })
+}
+
+async fn read_speaker_resource(req: ReadResourceParams) -> Result<serde_json::Value> {
+    let engine_name = req
+        .uri
+        .strip_prefix(SPEAKER_RESOURCE_SCHEME)
+        .and_then(|rest| rest.strip_suffix("/speakers"))
+        .ok_or_else(|| anyhow::anyhow!("unknown resource: {}", req.uri))?;
+    let config = load_config();
+    let target = resolve_engine(&config, engine_name)?;
+    let speakers = speakers::speakers(&target.base_url)
+        .await
+        .ok_or_else(|| anyhow::anyhow!("{} is not reachable", target.display_name))?;
+    Ok(json!({
+        "contents": [{
+            "uri": req.uri,
+            "mimeType": "application/json",
+            "text": serde_json::to_string(&speakers)?,
+        }]
+    }))
+}
+
+/// Input schemas exactly as passed to `register_tool`, for `debug_schema`.
+fn registered_schemas() -> &'static Mutex<Vec<(String, serde_json::Value)>> {
+    static SCHEMAS: OnceLock<Mutex<Vec<(String, serde_json::Value)>>> = OnceLock::new();
+    SCHEMAS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+fn record_schema(tool: Tool) -> Tool {
+    registered_schemas()
+        .lock()
+        .unwrap()
+        .push((tool.name.clone(), tool.input_schema.clone()));
+    tool
+}
+
+/// Shows the schemas clients were given, e.g. to tell whether the speaker dropdown
+/// fell back to a plain integer because an engine was offline at startup.
+async fn debug_schema() -> Result<CallToolResponse> {
+    let schemas: serde_json::Map<String, serde_json::Value> = registered_schemas()
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect();
+    Ok(text_response(
+        serde_json::Value::Object(schemas).to_string(),
+    ))
+}
+
+async fn cache_stats() -> Result<CallToolResponse> {
+    let stats = cache::stats();
+    Ok(text_response(
+        json!({
+            "entries": stats.entries,
+            "total_bytes": stats.total_bytes,
+            "hits": stats.hits,
+            "misses": stats.misses,
+        })
+        .to_string(),
+    ))
+}
+
+async fn clear_cache() -> Result<CallToolResponse> {
+    let dropped = cache::clear();
+    Ok(text_response(Lang::of(&load_config()).pick(
+        format!("音声キャッシュを空にしました ({} 件)", dropped),
+        format!("Cleared the audio cache ({} entries)", dropped),
+    )))
+}
+
+async fn stop_speech() -> Result<CallToolResponse> {
+    let lang = Lang::of(&load_config());
+    Ok(text_response(if playback::stop_current() {
+        lang.pick("再生中の読み上げを止めました", "Stopped the current speech")
+    } else {
+        lang.pick("再生中の読み上げはありません", "Nothing is playing")
+    }))
+}
+
+async fn stop_all() -> Result<CallToolResponse> {
+    let lang = Lang::of(&load_config());
+    let (stopped, discarded) = playback::stop_all();
+    let message = lang.pick(
+        format!("読み上げを止め、待機中の {} 件を破棄しました", discarded),
+        format!("Stopped speech and discarded {} queued item(s)", discarded),
+    );
+    Ok(text_response(
+        json!({
+            "message": message,
+            "stopped_current": stopped,
+            "discarded": discarded,
+            "pending": 0,
+        })
+        .to_string(),
+    ))
+}
+
+async fn speech_status() -> Result<CallToolResponse> {
+    let status = playback::status();
+    Ok(text_response(
+        json!({
+            "paused": playback::is_paused(),
+            "playing": status.playing,
+            "pending": status.pending,
+        })
+        .to_string(),
+    ))
+}
+
+/// Deserializes tool arguments straight from the request.
+///
+/// Some clients double-encode: the arguments arrive as a single string field holding
+/// the JSON object. When the object doesn't fit `T` and consists of exactly one string
+/// that parses as a JSON object, that inner object is used instead.
+fn parse_args<T: serde::de::DeserializeOwned>(req: CallToolRequest) -> Result<T> {
+    let args = req
+        .arguments
+        .ok_or_else(|| anyhow::anyhow!("Arguments missing"))?;
+    let value = serde_json::Value::Object(args.into_iter().collect());
+    let err = match serde_json::from_value::<T>(value.clone()) {
+        Ok(args) => return Ok(args),
+        Err(e) => e,
+    };
+
+    let inner = value
+        .as_object()
+        .filter(|map| map.len() == 1)
+        .and_then(|map| map.values().next())
+        .and_then(|v| v.as_str())
+        .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
+        .filter(|v| v.is_object());
+    let invalid = |e: serde_json::Error| {
+        anyhow::anyhow!(Lang::of(&load_config()).pick(
+            format!("引数の形式が正しくありません: {}", e),
+            format!("Invalid arguments: {}", e),
+        ))
+    };
+    match inner {
+        Some(inner) => serde_json::from_value(inner).map_err(invalid),
+        None => Err(invalid(err)),
+    }
+}
+
+#[cfg(test)]
+mod parse_args_tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Args {
+        text: String,
+        speaker: Option<u32>,
+    }
+
+    fn request(arguments: serde_json::Value) -> CallToolRequest {
+        serde_json::from_value(json!({ "name": "speak", "arguments": arguments })).unwrap()
+    }
+
+    #[test]
+    fn accepts_an_object() {
+        let args: Args =
+            parse_args(request(json!({ "text": "こんにちは", "speaker": 3 }))).unwrap();
+        assert_eq!(
+            args,
+            Args {
+                text: "こんにちは".to_string(),
+                speaker: Some(3)
+            }
+        );
+    }
+
+    #[test]
+    fn unwraps_a_stringified_object() {
+        let inner = json!({ "text": "こんにちは", "speaker": 3 }).to_string();
+        let args: Args = parse_args(request(json!({ "arguments": inner }))).unwrap();
+        assert_eq!(args.text, "こんにちは");
+        assert_eq!(args.speaker, Some(3));
+    }
+
+    #[test]
+    fn keeps_a_one_field_object_that_already_fits() {
+        // The lone field is a string that happens to be JSON, but the object is valid
+        // as it is, so it must not be unwrapped.
+        let text = json!({ "speaker": 3 }).to_string();
+        let args: Args = parse_args(request(json!({ "text": text.clone() }))).unwrap();
+        assert_eq!(args.text, text);
+        assert_eq!(args.speaker, None);
+    }
+
+    #[test]
+    fn rejects_malformed_arguments() {
+        assert!(parse_args::<Args>(request(json!({ "text": 5 }))).is_err());
+        assert!(parse_args::<Args>(request(json!({ "arguments": "{not json" }))).is_err());
+        assert!(parse_args::<Args>(request(json!({ "arguments": "[1, 2]" }))).is_err());
+    }
+}
+
+fn text_response(text: impl Into<String>) -> CallToolResponse {
+    CallToolResponse {
+        content: vec![ToolResponseContent::Text { text: text.into() }],
+        is_error: Some(false),
+        meta: None,
+    }
+}
+
+fn error_response(text: impl Into<String>) -> CallToolResponse {
+    CallToolResponse {
+        content: vec![ToolResponseContent::Text { text: text.into() }],
+        is_error: Some(true),
+        meta: None,
+    }
+}
+
+/// Returns the response for a blank `text`, or `None` if there is something to speak.
+fn reject_empty_text(text: &str, config: &AppConfig) -> Option<CallToolResponse> {
+    if !text.trim().is_empty() {
+        return None;
+    }
+    let message = messages::empty_text(Lang::of(config));
+    Some(if config.empty_text_is_error.unwrap_or(true) {
+        error_response(message)
+    } else {
+        text_response(message)
+    })
+}
+
+async fn set_default_speaker(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SetDefaultSpeakerArgs = parse_args(req)?;
+
+    // Refuse to persist an ID the engine doesn't know about.
+    let config = load_config();
+    let lang = Lang::of(&config);
+    let target = resolve_engine(&config, &args.engine)?;
+    let name = &target.display_name;
+    let speakers = fetch_speakers(&target.base_url).await.ok_or_else(|| {
+        anyhow::anyhow!(lang.pick(
+            format!("{}に接続できないため話者を確認できません💦", name),
+            format!("Cannot check the speaker because {} is not reachable", name),
+        ))
+    })?;
+    let label = speakers
+        .iter()
+        .flat_map(|speaker| {
+            speaker
+                .styles
+                .iter()
+                .map(move |style| (style.id, format!("{} ({})", speaker.name, style.name)))
+        })
+        .find(|(id, _)| *id == args.speaker)
+        .map(|(_, label)| label)
+        .ok_or_else(|| {
+            anyhow::anyhow!(lang.pick(
+                format!("{}に話者ID {} は存在しません", name, args.speaker),
+                format!("{} has no speaker ID {}", name, args.speaker),
+            ))
+        })?;
+
+    let mut saved = false;
+    update_config(|config| saved = config.set_default_speaker(&target.name, args.speaker))?;
+    if !saved {
+        return Ok(error_response(not_in_config_file(lang, name)));
+    }
+
+    Ok(text_response(lang.pick(
+        format!(
+            "{}のデフォルト話者を {} (ID: {}) に設定しました！",
+            name, label, args.speaker
+        ),
+        format!(
+            "Set the {} default speaker to {} (ID: {})!",
+            name, label, args.speaker
+        ),
+    )))
+}
+
+/// Error for a default that can't be saved because the engine was found by
+/// `autodiscover_ports` and exists only in memory.
+fn not_in_config_file(lang: Lang, name: &str) -> String {
+    lang.pick(
+        format!(
+            "{}は設定ファイルに登録されていないため、デフォルトを保存できません。config の engines に追加してください",
+            name
+        ),
+        format!(
+            "{} isn't in the config file, so its default can't be saved; add it to engines in the config",
+            name
+        ),
+    )
+}
+
+async fn set_default_speed(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SetDefaultSpeedArgs = parse_args(req)?;
+
+    let config = load_config();
+    let lang = Lang::of(&config);
+    let target = resolve_engine(&config, &args.engine)?;
+    if !(SPEED_SCALE_RANGE.0..=SPEED_SCALE_RANGE.1).contains(&args.speed) {
+        return Err(anyhow::anyhow!(lang.pick(
+            format!(
+                "speed は 0.5 〜 2.0 の範囲で指定してください (指定値: {})",
+                args.speed
+            ),
+            format!("speed must be between 0.5 and 2.0 (got {})", args.speed),
+        )));
+    }
+
+    let name = &target.display_name;
+    let mut saved = false;
+    update_config(|config| saved = config.set_default_speed(&target.name, args.speed))?;
+    if !saved {
+        return Ok(error_response(not_in_config_file(lang, name)));
+    }
+
+    Ok(text_response(lang.pick(
+        format!("{}のデフォルト速度を {} に設定しました！", name, args.speed),
+        format!("Set the {} default speed to {}!", name, args.speed),
+    )))
+}
+
+#[cfg(target_os = "macos")]
+const SAY_VOICE_ENV: &str = "SPEAK_MCP_SAY_VOICE";
+#[cfg(target_os = "macos")]
+const SAY_RATE_ENV: &str = "SPEAK_MCP_SAY_RATE";
+
+/// Words-per-minute rate for `say` from `SPEAK_MCP_SAY_RATE`, ignoring invalid values.
+#[cfg(target_os = "macos")]
+fn say_rate_from_env() -> Option<u32> {
+    let value = std::env::var(SAY_RATE_ENV).ok()?;
+    match value.trim().parse() {
+        Ok(rate) => Some(rate),
+        Err(_) => {
+            eprintln!("⚠️ {} の値が数値ではありません: {}", SAY_RATE_ENV, value);
+            None
+        }
+    }
+}
+
+/// Where the `say` voice can come from, highest precedence first: the call, a voice
+/// installed for `locale`, `SPEAK_MCP_SAY_VOICE`, then `macos_default_voice`. The
+/// system default applies when none is set.
+#[cfg(target_os = "macos")]
+fn say_voice_layers(
+    voice: Option<String>,
+    locale: Option<&str>,
+    config: &AppConfig,
+) -> [(&'static str, Option<String>); 4] {
+    // The installed voices are only listed when the call didn't name one.
+    let for_locale = locale
+        .filter(|_| voice.is_none())
+        .and_then(say_voice_for_locale);
+    [
+        ("call", voice),
+        ("locale", for_locale),
+        (
+            SAY_VOICE_ENV,
+            std::env::var(SAY_VOICE_ENV).ok().filter(|v| !v.is_empty()),
+        ),
+        ("macos_default_voice", config.macos_default_voice.clone()),
+    ]
+}
+
+/// Where the `say` rate can come from: the call, then `SPEAK_MCP_SAY_RATE`.
+#[cfg(target_os = "macos")]
+fn say_rate_layers(speed: Option<u32>) -> [(&'static str, Option<u32>); 2] {
+    [("call", speed), (SAY_RATE_ENV, say_rate_from_env())]
+}
+
+/// Speaks with the macOS `say` command.
+#[cfg(target_os = "macos")]
+async fn say(mut args: SpeakArgs, config: &AppConfig) -> Result<CallToolResponse> {
+    if let Err(e) = read_resource_text(config, &mut args.text, args.resource_uri.as_deref()).await {
+        return Ok(error_response(e.to_string()));
+    }
+    if let Some(response) = reject_empty_text(&args.text, config) {
+        return Ok(response);
+    }
+
+    let text = text::prepare(&args.text, config);
+    let mut cmd = Command::new("say");
+    cmd.arg(&text);
+
+    let voice = say_voice_layers(args.voice, args.locale.as_deref(), config)
+        .into_iter()
+        .find_map(|(_, voice)| voice);
+    if let Some(v) = &voice {
+        cmd.arg("-v").arg(v);
+    }
+    let rate = say_rate_layers(args.speed)
+        .into_iter()
+        .find_map(|(_, rate)| rate);
+    if let Some(s) = rate {
+        cmd.arg("-r").arg(s.to_string());
+    }
+    let status = cmd
+        .status()
+        .map_err(|e| process::spawn_error("say (macOS)", e))?;
+    if status.success() {
+        usage_log::record(config, "say", json!(voice), &text);
+        let mut response = text_response(Lang::of(config).pick(
+            "Macのsayで読み上げたよ！🎵",
+            "Spoke with the Mac say command! 🎵",
+        ));
+        if args.verbose {
+            // `null` voice means the system default was used.
+            push_verbose(
+                &mut response,
+                config,
+                json!({ "voice": voice, "locale": args.locale }),
+            );
+        }
+        Ok(response)
+    } else {
+        Err(anyhow::anyhow!(Lang::of(config).pick(
+            format!("sayコマンド失敗💦 ({})", status),
+            format!("The say command failed ({})", status),
+        )))
+    }
+}
+
+/// Copies the frontmost app's selection with a simulated ⌘C and reads it back, leaving
+/// the user's clipboard as it was.
+#[cfg(target_os = "macos")]
+fn copy_selection(lang: Lang) -> Result<String> {
+    use std::io::Write;
+
+    let previous = Command::new("pbpaste")
+        .output()
+        .map_err(|e| process::spawn_error("pbpaste", e))?
+        .stdout;
+    // Cleared first so an empty result reliably means "nothing was selected".
+    Command::new("osascript")
+        .args(["-e", "set the clipboard to \"\""])
+        .status()
+        .map_err(|e| process::spawn_error("osascript", e))?;
+    let output = Command::new("osascript")
+        .args([
+            "-e",
+            "tell application \"System Events\" to keystroke \"c\" using command down",
+            "-e",
+            "delay 0.2",
+            "-e",
+            "the clipboard as text",
+        ])
+        .output()
+        .map_err(|e| process::spawn_error("osascript", e))?;
+
+    let mut restore = Command::new("pbcopy")
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| process::spawn_error("pbcopy", e))?;
+    if let Some(stdin) = restore.stdin.as_mut() {
+        stdin.write_all(&previous)?;
+    }
+    restore.wait()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // -1719 / -25211 / "not allowed": System Events needs the Accessibility permission.
+        if stderr.contains("-1719") || stderr.contains("-25211") || stderr.contains("not allowed") {
+            return Err(anyhow::anyhow!(lang.pick(
+                "選択中のテキストを取得する権限がありません。システム設定 > プライバシーとセキュリティ > アクセシビリティ で、MCP クライアント (ターミナルなど) を許可してください",
+                "No permission to read the selection. Allow the MCP client (e.g. Terminal) in System Settings > Privacy & Security > Accessibility",
+            )));
+        }
+        return Err(anyhow::anyhow!(lang.pick(
+            format!("選択中のテキストを取得できませんでした: {}", stderr.trim()),
+            format!("Could not read the selection: {}", stderr.trim()),
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Speaks the text currently selected in the frontmost app with the default engine.
+#[cfg(target_os = "macos")]
+async fn speak_selection() -> Result<CallToolResponse> {
+    let config = load_config();
+    let lang = Lang::of(&config);
+    let text = match tokio::task::spawn_blocking(move || copy_selection(lang)).await? {
+        Ok(text) => text,
+        Err(e) => return Ok(error_response(e.to_string())),
+    };
+    if text.is_empty() {
+        return Ok(error_response(
+            lang.pick("選択中のテキストがありません", "No text is selected"),
+        ));
+    }
+
+    let target = default_engine(&config).await;
+    let args = VoiceEngineArgs {
+        text,
+        resource_uri: None,
+        speaker: None,
+        speed: None,
+        pitch: None,
+        priority: Priority::Normal,
+        resume_queue: None,
+        playback_mode: None,
+        async_playback: None,
+        spell_numbers: None,
+        verify_kana: None,
+        return_audio: None,
+        start: None,
+        end: None,
+        sentences: None,
+        remember: false,
+    };
+    speak_on(&target, &config, args).await
+}
+
+/// Parses `say -v '?'` output into `(name, locale)` pairs.
+#[cfg(target_os = "macos")]
+fn list_say_voices() -> Result<Vec<(String, String)>> {
+    let output = Command::new("say")
+        .arg("-v")
+        .arg("?")
+        .output()
+        .map_err(|e| process::spawn_error("say (macOS)", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .filter_map(|line| {
+            // e.g. "Kyoko               ja_JP    # こんにちは、私の名前はKyokoです。"
+            let head = line.split('#').next()?.trim_end();
+            let (name, locale) = head.rsplit_once(char::is_whitespace)?;
+            Some((name.trim().to_string(), locale.to_string()))
+        })
+        .collect())
+}
+
+/// First installed `say` voice for `locale`. Both "ja_JP" and "ja-JP" are accepted, and
+/// a bare language ("ja") matches any region.
+#[cfg(target_os = "macos")]
+fn say_voice_for_locale(locale: &str) -> Option<String> {
+    let wanted = locale.trim().replace('-', "_");
+    let voices = list_say_voices().ok()?;
+    let found = voices.into_iter().find(|(_, voice_locale)| {
+        voice_locale.eq_ignore_ascii_case(&wanted)
+            || voice_locale
+                .split_once('_')
+                .is_some_and(|(lang, _)| lang.eq_ignore_ascii_case(&wanted))
+    });
+    if found.is_none() {
+        eprintln!(
+            "⚠️ {} の say の声が見つからないため既定の声を使います",
+            locale
+        );
+    }
+    found.map(|(name, _)| name)
+}
+
+#[cfg(target_os = "macos")]
+async fn set_default_voice(req: CallToolRequest) -> Result<CallToolResponse> {
+    let args: SetDefaultVoiceArgs = parse_args(req)?;
+
+    let lang = Lang::of(&load_config());
+    let voices = list_say_voices()?;
+    if !voices.iter().any(|(name, _)| *name == args.voice) {
+        return Err(anyhow::anyhow!(lang.pick(
+            format!("sayコマンドに \"{}\" という声はありません", args.voice),
+            format!("The say command has no voice named \"{}\"", args.voice),
+        )));
+    }
+
+    update_config(|config| config.macos_default_voice = Some(args.voice.clone()))?;
+
+    Ok(text_response(lang.pick(
+        format!("Macのデフォルトの声を {} に設定しました！", args.voice),
+        format!("Set the Mac default voice to {}!", args.voice),
+    )))
+}
+
+/// Probes each engine once and prints whether it is reachable.
+async fn check_engines() -> bool {
+    let config = load_config();
+    let mut all_ready = true;
+    for engine in registered_engines(&config) {
+        let base_url = engine.base_url;
+        match fetch_speakers(&base_url).await {
+            Some(speakers) => {
+                let styles: usize = speakers.iter().map(|s| s.styles.len()).sum();
+                println!(
+                    "✅ {} ({}): {} speakers / {} styles",
+                    engine.display_name,
+                    base_url,
+                    speakers.len(),
+                    styles
+                );
+            }
+            None => {
+                all_ready = false;
+                println!("❌ {} ({}): not reachable", engine.display_name, base_url);
+            }
+        }
+    }
+    all_ready
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // One-shot CLI modes. Stdout is the MCP transport otherwise, so these must exit
+    // before the server is built.
+    if let Some(flag) = std::env::args().nth(1) {
+        match flag.as_str() {
+            "--version" | "-V" => {
+                println!("speak-mcp {}", env!("CARGO_PKG_VERSION"));
+                return Ok(());
+            }
+            "--print-config-path" => {
+                println!("{}", get_config_path().display());
+                return Ok(());
+            }
+            "--check" => {
+                println!("Config: {}", get_config_path().display());
+                let ready = check_engines().await;
+                std::process::exit(if ready { 0 } else { 1 });
+            }
+            "--http" => {
+                let Some(port) = std::env::args().nth(2).and_then(|p| p.parse::<u16>().ok()) else {
+                    eprintln!("Usage: speak-mcp --http <port>");
+                    std::process::exit(2);
+                };
+                #[cfg(feature = "http")]
+                return web::serve(port).await;
+                #[cfg(not(feature = "http"))]
+                {
+                    eprintln!(
+                        "--http {} を使うには http feature を有効にしてビルドしてください (cargo build --features http)",
+                        port
+                    );
+                    std::process::exit(2);
+                }
+            }
+            other => {
+                eprintln!("Unknown option: {}", other);
+                eprintln!(
+                    "Usage: speak-mcp [--version | --print-config-path | --check | --http <port>]"
+                );
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // Session overrides from speak-config's "Apply" last until the server restarts.
+    if let Err(e) = speak_common::clear_session_config() {
+        eprintln!("⚠️ config.session.json を削除できませんでした: {}", e);
+    }
+
+    let transport = ServerStdioTransport;
+    let mut builder = Server::builder(transport)
+        .name("speak-mcp")
+        .version(env!("CARGO_PKG_VERSION"));
+
+    let config = load_config();
+
+    // Fetch speakers at startup
+    // Note: We intentionally ignore errors here and fallback to default schema
+    // to ensure the server starts even if TTS engines are down.
+    // Gives engines launched alongside us (e.g. by a process manager) time to bind
+    // their port before the one fetch that shapes the tool schemas.
+    if let Some(delay) = config.startup_delay_ms.filter(|ms| *ms > 0) {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+    // Discovered engines must be registered before the engine enums are built.
+    discovery::run(&config).await;
+    if config.warm_up_speakers {
+        initialize::warm_up(&config);
+    }
+    let engine_names: Vec<String> = registered_engines(&config)
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    let voicevox_speakers = fetch_speakers(&Engine::Voicevox.base_url(&config)).await;
+    let aivis_speakers = fetch_speakers(&Engine::Aivis.base_url(&config)).await;
+
+    // Engines that were down keep the integer fallback schema (tools can't be
+    // re-registered), but the poller lets list_voices and speaker validation catch up.
+    let offline_engines: Vec<String> = [
+        (Engine::Voicevox, voicevox_speakers.is_none()),
+        (Engine::Aivis, aivis_speakers.is_none()),
+    ]
+    .into_iter()
+    .filter(|(_, offline)| *offline)
+    .map(|(engine, _)| engine.base_url(&config))
+    .collect();
+
+    // VOICEVOX Engine with Dynamic Schema and Config Default
+    let vv_default = config.voicevox_default_speaker;
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_voicevox".to_string(),
+            description: Some(format!(
+                "VOICEVOXを使用して読み上げます。({})",
+                Engine::Voicevox.base_url(&config)
+            )),
+            input_schema: build_speaker_choice_schema(
+                voicevox_speakers,
+                vv_default,
+                config.max_schema_oneof,
+            ),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { call_voicevox_compatible(Engine::Voicevox, req).await }),
+    );
+
+    // Aivis Speech Engine with Dynamic Schema and Config Default
+    let aivis_default = config.aivis_default_speaker;
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_aivis".to_string(),
+            description: Some(format!(
+                "Aivis Speechを使用して読み上げます。({})",
+                Engine::Aivis.base_url(&config)
+            )),
+            input_schema: build_speaker_choice_schema(
+                aivis_speakers,
+                aivis_default,
+                config.max_schema_oneof,
+            ),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { call_voicevox_compatible(Engine::Aivis, req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "list_voices".to_string(),
+            description: Some(
+                "エンジンの話者とスタイルの一覧を返します。engine を省略すると engine_priority \
+                 (set_engine_priority で変更可) の中で最初に応答するエンジン、未設定なら VOICEVOX です。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() }
+                }
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { list_voices(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "engine_status".to_string(),
+            description: Some(
+                "各エンジンに接続できるかを返します。結果は数秒間キャッシュされ、cache_age_ms はその経過時間です。"
+                    .to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { engine_status().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "initialize_speaker".to_string(),
+            description: Some(
+                "話者のモデルをエンジンに読み込ませ、最初の読み上げが遅くならないようにします。読み込み済みなら何もしません。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "speaker": {
+                        "type": "integer",
+                        "description": "省略するとエンジンのデフォルト話者"
+                    }
+                }
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { initialize_speaker(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "reload_voices".to_string(),
+            description: Some(
+                "エンジンの状態キャッシュを破棄し、話者一覧を取得し直します。".to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { reload_voices().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "capabilities".to_string(),
+            description: Some(
+                "登録されている各エンジンの接続可否・バージョン・対応機能 (音高などの調整・モーフィング・歌唱)・デフォルト話者を JSON でまとめて返します。結果は health_cache_ttl_secs の間キャッシュされます。"
+                    .to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: Some(json!({
+                "type": "object",
+                "properties": {
+                    "engines": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "kind": { "type": "string" },
+                                "base_url": { "type": "string" },
+                                "reachable": { "type": "boolean" },
+                                "version": { "type": ["string", "null"] },
+                                "features": {
+                                    "type": ["object", "null"],
+                                    "properties": {
+                                        "prosody": { "type": "boolean" },
+                                        "morphing": { "type": "boolean" },
+                                        "singing": { "type": "boolean" }
+                                    }
+                                },
+                                "default_speaker": { "type": ["integer", "null"] }
+                            },
+                            "required": ["name", "kind", "base_url", "reachable"]
+                        }
+                    },
+                    "playback": { "type": "boolean" },
+                    "say": { "type": "boolean" }
+                },
+                "required": ["engines", "playback", "say"]
+            })),
+        }),
+        |_req| Box::pin(async move { capabilities().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "diagnostics".to_string(),
+            description: Some(
+                "短い文を 1 回合成し、設定 (秘密情報は伏せ字)・エンジンのバージョン・各リクエストのステータスと所要時間・応答の先頭バイトを JSON で返します。不具合報告用です。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "speaker": { "type": "integer" }
+                }
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { diagnostics(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "describe_config".to_string(),
+            description: Some(
+                "speak_auto を同じ引数で呼んだときに使われるエンジン・話者・速度・音高と、それぞれをどの設定が決めたか (call > named_voice > speaker_override > engine_default > builtin) を返します。合成は行いません。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "言語による振り分けを確認するときに指定します" },
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "voice": { "type": "string" },
+                    "speaker": { "type": "integer" },
+                    "speed": { "type": "number" },
+                    "pitch": { "type": "number" }
+                }
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { describe_config(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "export_config".to_string(),
+            description: Some(
+                "現在有効な設定 (一時的な上書きや環境変数によるエンジン URL を反映したもの) を、設定ファイルとして読み込める JSON / TOML に書き出します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "書き出し先の絶対パス。拡張子 .json / .toml で形式が決まります"
+                    },
+                    "include_secrets": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "true にすると engine_headers の値とプロキシの認証情報もそのまま書き出します"
+                    },
+                    "overwrite": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "true にすると既存のファイルを上書きします"
+                    }
+                },
+                "required": ["path"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { export_config(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "debug_schema".to_string(),
+            description: Some(
+                "登録されている各ツールの input_schema を返します (話者の選択肢が表示されないときの確認用)。"
+                    .to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { debug_schema().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "cache_stats".to_string(),
+            description: Some(
+                "音声キャッシュの件数・合計バイト数・ヒット/ミス回数を返します。".to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { cache_stats().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "clear_cache".to_string(),
+            description: Some("音声キャッシュを空にします。".to_string()),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { clear_cache().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "stop_speech".to_string(),
+            description: Some(
+                "再生中の読み上げを止めます。待機中の読み上げはそのまま続きます。".to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { stop_speech().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "stop_all".to_string(),
+            description: Some(
+                "再生中の読み上げを止め、待機中の読み上げもすべて破棄します。破棄した件数を返します。"
+                    .to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { stop_all().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "pause_speech".to_string(),
+            description: Some(
+                "読み上げを一時的にミュートします。サーバーを再起動すると解除されます。"
+                    .to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| {
+            Box::pin(async move {
+                playback::set_paused(true);
+                Ok(text_response(
+                    Lang::of(&load_config())
+                        .pick("読み上げを一時停止しました🔇", "Speech paused 🔇"),
+                ))
+            })
+        },
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "resume_speech".to_string(),
+            description: Some("pause_speech で止めた読み上げを再開します。".to_string()),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| {
+            Box::pin(async move {
+                playback::set_paused(false);
+                Ok(text_response(
+                    Lang::of(&load_config()).pick("読み上げを再開しました🔊", "Speech resumed 🔊"),
+                ))
+            })
+        },
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speech_status".to_string(),
+            description: Some("一時停止中か、再生中か、待機中の読み上げ数を返します。".to_string()),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { speech_status().await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_lines".to_string(),
+            description: Some(
+                "複数の行を 1 行ずつ順番に読み上げます。行の間に pause_ms の間を空けます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "lines": { "type": "array", "items": { "type": "string" } },
+                    "speaker": { "type": "integer" },
+                    "speed": { "type": "number" },
+                    "pause_ms": { "type": "integer", "minimum": 0, "default": 500 },
+                    "numbered": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "true にすると各行の前に「1つ目、」のような番号を付けます"
+                    }
+                },
+                "required": ["engine", "lines"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_lines(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_dialogue".to_string(),
+            description: Some(
+                "複数の話者の会話を 1 つのステレオ音声にして読み上げます。セグメントごとにエンジン・話者と左右の位置 (pan) を指定できます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "segments": {
+                        "type": "array",
+                        "minItems": 1,
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "text": { "type": "string" },
+                                "engine": { "type": "string", "enum": engine_names.clone() },
+                                "speaker": { "type": "integer" },
+                                "speed": { "type": "number" },
+                                "pitch": { "type": "number" },
+                                "pan": {
+                                    "type": "number",
+                                    "minimum": -1.0,
+                                    "maximum": 1.0,
+                                    "default": 0.0,
+                                    "description": "-1.0 で左、0.0 で中央、1.0 で右から聞こえます"
+                                }
+                            },
+                            "required": ["text"]
+                        }
+                    },
+                    "pause_ms": {
+                        "type": "integer",
+                        "minimum": 0,
+                        "default": 300,
+                        "description": "セリフの間に入れる無音 (ミリ秒)"
+                    }
+                },
+                "required": ["segments"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_dialogue(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "benchmark".to_string(),
+            description: Some(
+                "短い文を指定回数合成し、audio_query と synthesis の所要時間 (最小/中央値/最大) を返します。音声は再生しません。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "speaker": { "type": "integer" },
+                    "iterations": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_BENCHMARK_ITERATIONS,
+                        "default": 5
+                    }
+                },
+                "required": ["engine"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { benchmark(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "sing_voicevox".to_string(),
+            description: Some(
+                "VOICEVOXの歌唱機能で楽譜を歌います。speaker には歌唱用スタイルのIDを指定します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "speaker": { "type": "integer" },
+                    "notes": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "lyric": { "type": "string", "description": "歌詞 (1音節、ひらがな/カタカナ)" },
+                                "key": {
+                                    "type": ["integer", "null"],
+                                    "description": "MIDIノート番号 (60 = ド)。休符は null"
+                                },
+                                "duration_ms": { "type": "integer", "minimum": 1 }
+                            },
+                            "required": ["duration_ms"]
+                        }
+                    },
+                    "teacher": { "type": "integer", "default": DEFAULT_SING_TEACHER }
+                },
+                "required": ["speaker", "notes"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { sing_voicevox(req).await }),
+    );
+
+    // Unified tool over the engine registry (built-ins plus `engines` from config)
+    let mut speak_auto_schema = build_speaker_choice_schema(None, None, None);
+    speak_auto_schema["properties"]["engine"] = json!({
+        "type": "string",
+        "enum": engine_names.clone(),
+        "description": "省略すると、英語の文章は english_engine、それ以外は VOICEVOX で読み上げます"
+    });
+    let voice_names: Vec<String> = config.named_voices.keys().cloned().collect();
+    speak_auto_schema["properties"]["voice"] = if voice_names.is_empty() {
+        json!({ "type": "string", "description": "config の named_voices に定義したボイス名" })
+    } else {
+        json!({ "type": "string", "enum": voice_names })
+    };
+    speak_auto_schema["properties"]["verbose"] = json!({
+        "type": "boolean",
+        "default": false,
+        "description": "判定した言語と使用したエンジンも返します"
+    });
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_auto".to_string(),
+            description: Some(
+                "登録されている任意のエンジンで読み上げます。engine を省略すると、英語の文は \
+                 english_engine、それ以外は engine_priority (set_engine_priority で変更可) の中で\
+                 最初に応答するエンジン、未設定なら VOICEVOX を使います。"
+                    .to_string(),
+            ),
+            input_schema: speak_auto_schema,
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_auto(req).await }),
+    );
+
+    // Keys are fixed at startup like the voice names above; the text is looked up per
+    // call, so rewording a phrase doesn't need a restart.
+    let phrase_schema = if config.quick_phrases.is_empty() {
+        json!({ "type": "string", "description": "config の quick_phrases に定義したキー" })
+    } else {
+        let one_of: Vec<serde_json::Value> = config
+            .quick_phrases
+            .iter()
+            .map(|(key, text)| json!({ "const": key, "title": text }))
+            .collect();
+        json!({ "type": "string", "oneOf": one_of })
+    };
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_phrase".to_string(),
+            description: Some(
+                "config の quick_phrases に登録した定型文をキーで選んで、デフォルトのエンジンと話者で読み上げます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "phrase": phrase_schema,
+                    "async_playback": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "true にすると再生の完了を待たずにすぐ応答します"
+                    }
+                },
+                "required": ["phrase"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_phrase(req).await }),
+    );
+
+    let stream_properties = json!({
+        "session_id": {
+            "type": "string",
+            "description": "ストリームを識別する任意の文字列。同じ ID の呼び出しが 1 つの読み上げになります"
+        },
+        "text": { "type": "string", "description": "前回の呼び出しに続くテキスト" },
+        "engine": { "type": "string", "enum": engine_names.clone() },
+        "speaker": { "type": "integer" },
+        "speed": { "type": "number" },
+        "pitch": { "type": "number" }
+    });
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_stream_append".to_string(),
+            description: Some(
+                "生成中のテキストを少しずつ渡し、文が完成したそばから読み上げます。文末 (。！？ や改行) までの部分を合成してキューに入れ、残りは次の呼び出しまで保留します。エンジン・話者・速度は最初の呼び出しの値が使われます。最後に speak_stream_finish を呼んでください。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": stream_properties.clone(),
+                "required": ["session_id", "text"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_stream(req, false).await }),
+    );
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_stream_finish".to_string(),
+            description: Some(
+                "speak_stream_append のストリームを終了し、保留中のテキスト (と text があればそれも) を読み上げます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": stream_properties,
+                "required": ["session_id"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_stream(req, true).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "retry_failed".to_string(),
+            description: Some(
+                "エンジンの停止などで読み上げられず failed_log_path に保存されたテキストを、古い順に読み上げ直します。成功したものはファイルから削除されます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "limit": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "読み上げ直す最大件数 (省略時はすべて)"
+                    }
+                }
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { retry_failed(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_with_timing".to_string(),
+            description: Some(
+                "読み上げをバックグラウンドで始め、字幕やカラオケ表示用にアクセント句・モーラごとの開始・終了時刻 (秒) をすぐに返します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "text": { "type": "string" },
+                    "speaker": { "type": "integer" },
+                    "speed": { "type": "number" },
+                    "pitch": { "type": "number" },
+                    "play": {
+                        "type": "boolean",
+                        "default": true,
+                        "description": "false にすると再生せずにタイミングだけを返します"
+                    }
+                },
+                "required": ["text"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { speak_with_timing(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "speak_reading".to_string(),
+            description: Some(
+                "日本語の学習用に、VOICEVOX で読み上げて、使われた読み (カタカナ)・ローマ字・アクセント句ごとのアクセント位置を返します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string" },
+                    "speaker": { "type": "integer" },
+                    "speed": { "type": "number", "description": "ゆっくり聞きたいときは 0.8 など" },
+                    "play": {
+                        "type": "boolean",
+                        "default": true,
+                        "description": "false にすると再生せずに読みだけを返します"
+                    }
+                },
+                "required": ["text"]
+            }),
+            output_schema: Some(json!({
+                "type": "object",
+                "properties": {
+                    "original": { "type": "string" },
+                    "kana": { "type": "string", "description": "VOICEVOX の読み記法 (' がアクセント、/ が句の区切り)" },
+                    "reading": { "type": "string", "description": "アクセント句を空白で区切ったカタカナ" },
+                    "romaji": { "type": "string" },
+                    "phrases": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "reading": { "type": "string" },
+                                "romaji": { "type": "string" },
+                                "accent": { "type": "integer", "description": "アクセントのあるモーラの位置 (1 始まり)" }
+                            }
+                        }
+                    },
+                    "played": { "type": "boolean" }
+                },
+                "required": ["original", "kana", "reading", "romaji", "phrases", "played"]
+            })),
+        }),
+        |req| Box::pin(async move { speak_reading(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "get_audio_query".to_string(),
+            description: Some(
+                "テキストから audio_query (アクセント句・モーラ・音高) の JSON を取得します。編集して synthesize_query に渡せます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "text": { "type": "string" },
+                    "speaker": { "type": "integer" }
+                },
+                "required": ["text"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { get_audio_query(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "synthesize_query".to_string(),
+            description: Some(
+                "get_audio_query で取得した (編集済みの) JSON から音声を合成して再生します。output_path を指定するとファイルに保存します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "query": { "type": "object" },
+                    "speaker": { "type": "integer" },
+                    "output_path": { "type": "string" },
+                    "sample_rate": { "type": "integer" },
+                    "channels": { "type": "string", "enum": ["mono", "stereo"] },
+                    "normalize_peak_dbfs": { "type": "number", "maximum": 0 },
+                    "waveform_points": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_WAVEFORM_POINTS,
+                        "description": "指定すると、この数の区間ごとの最小値・最大値 (-1〜1) を waveform として返します"
+                    }
+                },
+                "required": ["query"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { synthesize_query(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "save_speech".to_string(),
+            description: Some(
+                "読み上げ音声を再生せずに WAV ファイルに保存します。sample_rate / channels / normalize_peak_dbfs で出力形式を指定できます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "text": { "type": "string" },
+                    "speaker": { "type": "integer" },
+                    "speed": { "type": "number" },
+                    "output_path": { "type": "string" },
+                    "format": {
+                        "type": "string",
+                        "enum": ["wav", "mp3"],
+                        "description": "省略するとエンジンごとの default_save_formats、なければ wav"
+                    },
+                    "sample_rate": {
+                        "type": "integer",
+                        "minimum": EXPORT_SAMPLE_RATE_RANGE.0,
+                        "maximum": EXPORT_SAMPLE_RATE_RANGE.1
+                    },
+                    "channels": { "type": "string", "enum": ["mono", "stereo"] },
+                    "normalize_peak_dbfs": { "type": "number", "maximum": 0 },
+                    "skip_if_exists": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "true にすると、output_path に同じ内容で保存済みのファイルがあれば合成せずに再利用します"
+                    }
+                },
+                "required": ["text", "output_path"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { save_speech(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "batch_synthesize".to_string(),
+            description: Some(
+                "複数の {text, output_path, speaker} をまとめて WAV ファイルに保存します。records の配列か JSON Lines ファイル (jsonl_path) を受け付け、失敗した項目は一覧で返します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "records": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "text": { "type": "string" },
+                                "output_path": { "type": "string" },
+                                "engine": { "type": "string", "enum": engine_names.clone() },
+                                "speaker": { "type": "integer" },
+                                "speed": { "type": "number" }
+                            },
+                            "required": ["text", "output_path"]
+                        }
+                    },
+                    "jsonl_path": { "type": "string" },
+                    "skip_if_exists": {
+                        "type": "boolean",
+                        "default": false,
+                        "description": "true にすると、同じ内容で保存済みのファイルは合成せずに再利用します (reused に数えます)"
+                    }
+                }
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { batch_synthesize(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "prepare_speech".to_string(),
+            description: Some(
+                "音声を先に合成して保持し、トークンを返します。play_prepared にトークンを渡すとすぐに再生できます。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "text": { "type": "string" },
+                    "speaker": { "type": "integer" },
+                    "speed": { "type": "number" },
+                    "pitch": { "type": "number" }
+                },
+                "required": ["text"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { prepare_speech(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "play_prepared".to_string(),
+            description: Some("prepare_speech で合成しておいた音声を再生します。".to_string()),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "token": { "type": "string" },
+                    "priority": { "type": "string", "enum": ["normal", "high"], "default": "normal" }
+                },
+                "required": ["token"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { play_prepared(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "list_engines".to_string(),
+            description: Some(
+                "登録されているエンジンの一覧 (名前・種類・URL・デフォルト話者) を返します。"
+                    .to_string(),
+            ),
+            input_schema: json!({ "type": "object", "properties": {} }),
+            output_schema: None,
+        }),
+        |_req| Box::pin(async move { list_engines().await }),
+    );
+
+    // Runtime config updates, shared with speak-config through speak-common
+    builder.register_tool(
+        record_schema(Tool {
+            name: "set_default_speaker".to_string(),
+            description: Some(
+                "登録されているエンジンのデフォルト話者を変更し、設定ファイルに保存します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "speaker": { "type": "integer" }
+                },
+                "required": ["engine", "speaker"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { set_default_speaker(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "set_engine_priority".to_string(),
+            description: Some(
+                "speak_auto で engine を省略したときに使うエンジンの優先順位を設定します。先頭から順に、接続できる最初のエンジンを使います。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engines": {
+                        "type": "array",
+                        "items": { "type": "string", "enum": engine_names.clone() }
+                    }
+                },
+                "required": ["engines"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { set_engine_priority(req).await }),
+    );
+
+    builder.register_tool(
+        record_schema(Tool {
+            name: "set_default_speed".to_string(),
+            description: Some(
+                "登録されているエンジンのデフォルト速度 (0.5〜2.0) を変更し、設定ファイルに保存します。"
+                    .to_string(),
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "engine": { "type": "string", "enum": engine_names.clone() },
+                    "speed": { "type": "number", "minimum": 0.5, "maximum": 2.0 }
+                },
+                "required": ["engine", "speed"]
+            }),
+            output_schema: None,
+        }),
+        |req| Box::pin(async move { set_default_speed(req).await }),
+    );
+
+    #[cfg(target_os = "macos")]
+    {
+        builder.register_tool(
+            record_schema(Tool {
+                name: "speak".to_string(),
+                description: Some("Mac標準のsayコマンドで読み上げます。".to_string()),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "text": { "type": "string", "description": "resource_uri を指定する場合は省略します" },
+                        "resource_uri": {
+                            "type": "string",
+                            "description": "text の代わりに読み上げるテキストの場所 (file:// または http(s)://、1 MiB まで)"
+                        },
+                        "voice": { "type": "string" },
+                        "locale": {
+                            "type": "string",
+                            "description": "voice を省略したとき、この言語 (例: ja_JP, en_US, ja) の声を使います"
+                        },
+                        "speed": { "type": "integer" },
+                        "verbose": {
+                            "type": "boolean",
+                            "default": false,
+                            "description": "true にすると使った声を返します"
+                        }
+                    }
+                }),
+                output_schema: None,
+            }),
+            |req| {
+                // Cloning string again inside closure if needed, but here simple clone for move is enough if we structured it differently.
+                // However, since we can't easily move captured variable into Fn callback multiple times if it's not Copy,
+                // we might need a slight adjustment but for this SDK style:
+                // Actually the closure is `Fn(CallToolRequest) -> Pin<Box<...>>`.
+                // We need to be careful about borrowing.
+                // Let's rely on simple `move` for now assuming `builder.register_tool` takes `impl Fn ... + Send + Sync + 'static`.
+                // String is Clone but not Copy. We can use Arc if needed, or clone inside the closure if logic permits.
+                // Here, we'll try simple move and clone if necessary.
+                // Wait, `builder.register_tool` usually takes a closure that is called multiple times.
+                // So the closure must capture variables.
+                // For `speak_voicevox` `move` works because `u32` is Copy.
+                // For `speak` with `String`, we need to wrap in Arc or clone efficiently.
+                // Let's use a static/const default or Arc for now to be safe.
+                // Actually let's just create a local clone for the closure scope.
+
+                // Workaround: We can't move non-Copy types into Fn closure easily if we want to use them repeatedly without Clone inside.
+                // But `String` needs to be cloned *per call* if we want to pass it? No, we just need access.
+                // Let's keep it simple: We won't support default voice for `say` in schema yet, just logic.
+
+                Box::pin(async move {
+                    let args: SpeakArgs = parse_args(req)?;
+
+                    // Loading config every time is robust for updates!
+                    say(args, &load_config()).await
+                })
+            },
+        );
+
+        builder.register_tool(
+            record_schema(Tool {
+                name: "speak_selection".to_string(),
+                description: Some(
+                    "いま選択されているテキストを読み上げます。アクセシビリティの許可が必要です。"
+                        .to_string(),
+                ),
+                input_schema: json!({ "type": "object", "properties": {} }),
+                output_schema: None,
+            }),
+            |_req| Box::pin(async move { speak_selection().await }),
+        );
+
+        builder.register_tool(
+            record_schema(Tool {
+                name: "set_default_voice".to_string(),
+                description: Some(
+                    "sayコマンドのデフォルトの声を変更し、config.json に保存します。".to_string(),
+                ),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "voice": { "type": "string" }
+                    },
+                    "required": ["voice"]
+                }),
+                output_schema: None,
+            }),
+            |req| Box::pin(async move { set_default_voice(req).await }),
+        );
+    }
+
+    if !playback::is_supported(&config) {
+        eprintln!(
+            "⚠️ このプラットフォーム ({}) では音声を再生できません。読み上げツールはエラーを返します。",
+            std::env::consts::OS
+        );
+    }
+
+    // Speaker lists as MCP resources (`speak://<engine>/speakers`), read from the shared
+    // cache so `reload_voices` refreshes them too.
+    let builder = builder
+        .capabilities(ServerCapabilities {
+            tools: Some(json!({})),
+            resources: Some(json!({})),
+            ..Default::default()
+        })
+        .request_handler("resources/list", |_req: serde_json::Value| {
+            Box::pin(async move { Ok(list_speaker_resources()) })
+        })
+        .request_handler("resources/read", |req: ReadResourceParams| {
+            Box::pin(async move { read_speaker_resource(req).await })
+        });
+
+    let server = builder.build();
+    eprintln!("Speak MCP Server (Multi-Engine) 起動中...🌟");
+    if let Some(secs) = config.background_speaker_poll_secs.filter(|secs| *secs > 0) {
+        speakers::spawn_poller(offline_engines, Duration::from_secs(secs));
+    }
+    if let Some(port) = config.http_port {
+        #[cfg(feature = "http")]
+        tokio::spawn(async move {
+            if let Err(e) = web::serve(port).await {
+                eprintln!(
+                    "⚠️ HTTP モードを開始できませんでした (port {}): {}",
+                    port, e
+                );
+            }
+        });
+        #[cfg(not(feature = "http"))]
+        eprintln!(
+            "⚠️ http_port ({}) が設定されていますが、http feature なしでビルドされているため無視します",
+            port
+        );
+    }
     server.listen().await?;
 
     Ok(())
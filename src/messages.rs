@@ -0,0 +1,53 @@
+//! Response text in the language chosen by `message_language`.
+//!
+//! Only text written by this server is localized. Details passed through from
+//! engines, players and the OS stay as they were reported.
+
+use speak_common::AppConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Japanese,
+    English,
+}
+
+impl Lang {
+    /// `"ja"` (the default) or `"en"`; any other value falls back to English.
+    pub fn of(config: &AppConfig) -> Lang {
+        match config.message_language.as_deref() {
+            None | Some("ja") => Lang::Japanese,
+            Some(_) => Lang::English,
+        }
+    }
+
+    pub fn pick<T>(self, ja: T, en: T) -> T {
+        match self {
+            Lang::Japanese => ja,
+            Lang::English => en,
+        }
+    }
+}
+
+// Messages shared by several tools.
+
+pub fn played(lang: Lang) -> &'static str {
+    lang.pick("読み上げ完了！✨", "Finished speaking! ✨")
+}
+
+pub fn playback_failed(lang: Lang, error: impl std::fmt::Display) -> String {
+    lang.pick(
+        format!("再生できませんでした💦 {}", error),
+        format!("Playback failed: {}", error),
+    )
+}
+
+pub fn saved(lang: Lang, path: &str) -> String {
+    lang.pick(
+        format!("{} に保存しました！", path),
+        format!("Saved to {}!", path),
+    )
+}
+
+pub fn empty_text(lang: Lang) -> &'static str {
+    lang.pick("text が空です", "text is empty")
+}
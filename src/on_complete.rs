@@ -0,0 +1,37 @@
+//! Optional user command run after an utterance has finished playing
+//! (`on_complete_command`), e.g. to turn off a "speaking" light.
+//!
+//! Unlike `post_process_command` it never sees the audio. `{engine}`, `{speaker}` and
+//! `{duration}` (seconds) in the command are replaced with the utterance's values. The
+//! command runs detached through the same shell as `post_process_command`; its output
+//! is discarded because stdout is the MCP transport.
+
+use crate::post_process::{SHELL, shell};
+use speak_common::process;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Starts `command` for a finished utterance and returns immediately. Failures are
+/// only logged.
+pub fn run(command: &str, engine: &str, speaker: u32, duration: Duration) {
+    let command = command
+        .replace("{engine}", engine)
+        .replace("{speaker}", &speaker.to_string())
+        .replace("{duration}", &format!("{:.3}", duration.as_secs_f64()));
+    tokio::spawn(async move {
+        let status = shell(&command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("⚠️ on_complete_command failed ({})", status),
+            Err(e) => eprintln!(
+                "⚠️ on_complete_command failed: {}",
+                process::spawn_error(SHELL, e)
+            ),
+        }
+    });
+}
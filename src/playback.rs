@@ -0,0 +1,625 @@
+//! Playback queue shared by every speak tool.
+//!
+//! All audio goes through a single worker task so utterances from concurrent tool
+//! calls never overlap. Callers enqueue audio bytes (WAV, or MP3 from engines that
+//! return it) and wait until their item has been played, interrupted or discarded.
+
+use crate::disk;
+use crate::messages::Lang;
+use crate::wav;
+use anyhow::Result;
+use serde::Deserialize;
+use speak_common::AppConfig;
+use speak_common::process;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::sync::{Notify, oneshot};
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    Normal,
+    /// Jumps to the head of the queue and cuts off whatever is currently playing.
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Played,
+    /// Playback started but was stopped before it finished.
+    Interrupted,
+    /// Removed from the queue before playback started.
+    Discarded,
+    /// Not played because speech is paused via `pause_speech`.
+    Muted,
+}
+
+/// Ephemeral runtime mute toggled by `pause_speech` / `resume_speech`.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+pub struct Status {
+    pub playing: bool,
+    pub pending: usize,
+}
+
+pub fn status() -> Status {
+    let queue = queue();
+    let pending = queue.pending.lock().unwrap().len();
+    let playing = queue.current.lock().unwrap().is_some();
+    Status { playing, pending }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+}
+
+impl AudioFormat {
+    /// Identifies the format from magic bytes rather than trusting `Content-Type`,
+    /// which some engines and proxies get wrong.
+    fn detect(data: &[u8]) -> Option<Self> {
+        if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WAVE" {
+            Some(AudioFormat::Wav)
+        } else if data.starts_with(b"ID3")
+            || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0)
+        {
+            Some(AudioFormat::Mp3)
+        } else {
+            None
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => ".wav",
+            AudioFormat::Mp3 => ".mp3",
+        }
+    }
+}
+
+struct Job {
+    audio: Vec<u8>,
+    format: AudioFormat,
+    done: oneshot::Sender<Result<Outcome>>,
+}
+
+struct PlaybackQueue {
+    pending: Mutex<VecDeque<Job>>,
+    wake: Notify,
+    /// Cancels the item the worker is currently playing, if any.
+    current: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl PlaybackQueue {
+    fn interrupt_current(&self) -> bool {
+        match self.current.lock().unwrap().take() {
+            Some(cancel) => cancel.send(()).is_ok(),
+            None => false,
+        }
+    }
+}
+
+fn queue() -> &'static PlaybackQueue {
+    static QUEUE: OnceLock<PlaybackQueue> = OnceLock::new();
+    QUEUE.get_or_init(|| {
+        tokio::spawn(run_worker());
+        PlaybackQueue {
+            pending: Mutex::new(VecDeque::new()),
+            wake: Notify::new(),
+            current: Mutex::new(None),
+        }
+    })
+}
+
+async fn run_worker() {
+    let queue = queue();
+    let mut last_finished: Option<Instant> = None;
+    loop {
+        // `inter_utterance_ms` separates consecutive items, whichever calls they came
+        // from. Waiting before the pop lets an item enqueued meanwhile still be next.
+        let gap =
+            Duration::from_millis(speak_common::load_config().inter_utterance_ms.unwrap_or(0));
+        if let Some(remaining) = last_finished.and_then(|t| gap.checked_sub(t.elapsed())) {
+            tokio::time::sleep(remaining).await;
+        }
+
+        // Pop and register the cancel handle under the same lock that high-priority
+        // enqueues take, so an interrupt can never slip in between the two.
+        let next = {
+            let mut pending = queue.pending.lock().unwrap();
+            pending.pop_front().map(|job| {
+                let (cancel_tx, cancel_rx) = oneshot::channel();
+                *queue.current.lock().unwrap() = Some(cancel_tx);
+                (job, cancel_rx)
+            })
+        };
+
+        let Some((job, cancel)) = next else {
+            queue.wake.notified().await;
+            continue;
+        };
+
+        let config = speak_common::load_config();
+        let limit = watchdog_limit(&job.audio, &config);
+        let result = if config.use_internal_player {
+            let volume = config.internal_player_volume.unwrap_or(1.0);
+            with_watchdog(limit, play_internal(job.audio.clone(), volume, cancel)).await
+        } else {
+            let retries = config.playback_retries.unwrap_or(0);
+            play_with_retries(&job, &config, cancel, retries, limit).await
+        };
+        queue.current.lock().unwrap().take();
+        last_finished = Some(Instant::now());
+        let _ = job.done.send(result);
+    }
+}
+
+const RETRY_DELAY: Duration = Duration::from_millis(300);
+
+/// Used when `playback_timeout_secs` is unset.
+const DEFAULT_WATCHDOG_MARGIN: Duration = Duration::from_secs(10);
+/// Limit for audio whose length isn't known (MP3), generous enough for long speech.
+const UNKNOWN_LENGTH_LIMIT: Duration = Duration::from_secs(600);
+
+/// How long the player gets before it is considered stuck: the audio's own length plus
+/// `playback_timeout_secs`, so long speech is never cut off. `None` when
+/// `playback_timeout_secs` is 0.
+fn watchdog_limit(audio: &[u8], config: &AppConfig) -> Option<Duration> {
+    let margin = config
+        .playback_timeout_secs
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_WATCHDOG_MARGIN);
+    if margin.is_zero() {
+        return None;
+    }
+    Some(match wav::Wav::parse(audio) {
+        Ok(wav) => wav.duration() + margin,
+        Err(_) => UNKNOWN_LENGTH_LIMIT.max(margin),
+    })
+}
+
+/// The player didn't finish within its `watchdog_limit` and was stopped.
+#[derive(Debug)]
+struct PlaybackTimedOut(Duration);
+
+impl std::fmt::Display for PlaybackTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "player did not finish within {:.1}s and was stopped (see playback_timeout_secs)",
+            self.0.as_secs_f64()
+        )
+    }
+}
+
+impl std::error::Error for PlaybackTimedOut {}
+
+/// Runs `playing` with the watchdog. On timeout the future is dropped: an external
+/// player's process is killed (`kill_on_drop`) and the internal player's `StopOnDrop`
+/// stops its sink, so a hung clip never plays on under the next item.
+async fn with_watchdog(
+    limit: Option<Duration>,
+    playing: impl std::future::Future<Output = Result<Outcome>>,
+) -> Result<Outcome> {
+    let Some(limit) = limit else {
+        return playing.await;
+    };
+    match tokio::time::timeout(limit, playing).await {
+        Ok(result) => result,
+        Err(_) => {
+            eprintln!("⚠️ Playback timed out after {:.1}s", limit.as_secs_f64());
+            Err(PlaybackTimedOut(limit).into())
+        }
+    }
+}
+
+/// The player ran but exited unsuccessfully, which is usually transient (e.g. the
+/// audio device was busy), unlike a missing player.
+#[derive(Debug)]
+struct PlayerFailed(&'static str);
+
+impl std::fmt::Display for PlayerFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed", self.0)
+    }
+}
+
+impl std::error::Error for PlayerFailed {}
+
+/// Plays `job`, retrying up to `retries` times when the player exits with an error.
+/// Other failures (e.g. the player is missing or hung) are returned immediately.
+async fn play_with_retries(
+    job: &Job,
+    config: &AppConfig,
+    mut cancel: oneshot::Receiver<()>,
+    retries: u32,
+    limit: Option<Duration>,
+) -> Result<Outcome> {
+    let mut attempt = 0;
+    loop {
+        match with_watchdog(
+            limit,
+            play_audio(&job.audio, job.format, config, &mut cancel),
+        )
+        .await
+        {
+            Err(e) if attempt < retries && e.is::<PlayerFailed>() => {
+                attempt += 1;
+                eprintln!(
+                    "Playback failed ({}), retrying ({}/{})",
+                    e, attempt, retries
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => {}
+                    _ = &mut cancel => return Ok(Outcome::Interrupted),
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A queued item; resolves once it has been played, interrupted or discarded.
+pub struct Pending(oneshot::Receiver<Result<Outcome>>);
+
+impl Pending {
+    pub async fn wait(self) -> Result<Outcome> {
+        self.0
+            .await
+            .map_err(|_| anyhow::anyhow!("Playback worker stopped"))?
+    }
+}
+
+/// Queues `audio` for playback and returns immediately.
+///
+/// With `Priority::High` the item is placed at the head of the queue and the current
+/// utterance is stopped. When `resume_queue` is false, the items that were waiting are
+/// discarded instead of being played afterwards.
+pub fn enqueue(audio: Vec<u8>, priority: Priority, resume_queue: bool) -> Result<Pending> {
+    if !is_supported(&speak_common::load_config()) {
+        return Err(anyhow::anyhow!(
+            "no audio backend available on this platform ({})",
+            std::env::consts::OS
+        ));
+    }
+    let format = detect_format(&audio)?;
+
+    let (done, result) = oneshot::channel();
+    if is_paused() {
+        let _ = done.send(Ok(Outcome::Muted));
+        return Ok(Pending(result));
+    }
+
+    let queue = queue();
+    let job = Job {
+        audio,
+        format,
+        done,
+    };
+    {
+        let mut pending = queue.pending.lock().unwrap();
+        match priority {
+            Priority::Normal => pending.push_back(job),
+            Priority::High => {
+                if !resume_queue {
+                    for skipped in pending.drain(..) {
+                        let _ = skipped.done.send(Ok(Outcome::Discarded));
+                    }
+                }
+                pending.push_front(job);
+                queue.interrupt_current();
+            }
+        }
+    }
+    queue.wake.notify_one();
+
+    Ok(Pending(result))
+}
+
+/// Stops the utterance that is currently playing. Returns `false` if nothing was playing.
+pub fn stop_current() -> bool {
+    queue().interrupt_current()
+}
+
+/// Discards every waiting item and stops the current one. Returns whether something
+/// was playing and how many items were discarded. Done under the queue lock, so an
+/// item enqueued concurrently either lands before the flush (and is discarded) or
+/// after it, never in between.
+pub fn stop_all() -> (bool, usize) {
+    let queue = queue();
+    let mut pending = queue.pending.lock().unwrap();
+    let discarded = pending.len();
+    for skipped in pending.drain(..) {
+        let _ = skipped.done.send(Ok(Outcome::Discarded));
+    }
+    (queue.interrupt_current(), discarded)
+}
+
+/// MIME type for audio returned to the client instead of (or as well as) played.
+pub fn mime_type(audio: &[u8]) -> &'static str {
+    match AudioFormat::detect(audio) {
+        Some(AudioFormat::Mp3) => "audio/mpeg",
+        _ => "audio/wav",
+    }
+}
+
+/// Rejects buffers that are neither WAV nor MP3, e.g. an HTML error page returned
+/// because the configured port belongs to some other web server.
+fn detect_format(data: &[u8]) -> Result<AudioFormat> {
+    if let Some(format) = AudioFormat::detect(data) {
+        return Ok(format);
+    }
+    let head = &data[..data.len().min(16)];
+    let hex: Vec<String> = head.iter().map(|b| format!("{:02x}", b)).collect();
+    Err(anyhow::anyhow!(
+        "engine returned non-WAV/MP3 data ({} bytes), first bytes: {} {:?}",
+        data.len(),
+        hex.join(" "),
+        String::from_utf8_lossy(head)
+    ))
+}
+
+/// Whether this build has an external player for the platform.
+pub const SUPPORTED: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// Whether audio can be played at all: an external player exists, or the built-in one
+/// is enabled (it works on every platform rodio supports).
+pub fn is_supported(config: &AppConfig) -> bool {
+    SUPPORTED || config.use_internal_player
+}
+
+/// Raises the internal player's stop flag when dropped, so the blocking thread also
+/// stops when the future playing it is abandoned (e.g. by the watchdog), not only when
+/// it is cancelled.
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Plays through rodio in-process instead of an external player (`use_internal_player`).
+///
+/// rodio's output stream isn't `Send`, so everything lives on a blocking thread that
+/// polls a stop flag; cancelling sets the flag and waits for the sink to stop.
+async fn play_internal(
+    data: Vec<u8>,
+    volume: f32,
+    mut cancel: oneshot::Receiver<()>,
+) -> Result<Outcome> {
+    let stop = StopOnDrop(Arc::new(AtomicBool::new(false)));
+    let stop_flag = stop.0.clone();
+    let mut playing = tokio::task::spawn_blocking(move || -> Result<()> {
+        let (_stream, handle) = rodio::OutputStream::try_default()?;
+        let sink = rodio::Sink::try_new(&handle)?;
+        sink.set_volume(volume);
+        sink.append(rodio::Decoder::new(std::io::Cursor::new(data))?);
+        while !sink.empty() {
+            if stop_flag.load(Ordering::SeqCst) {
+                sink.stop();
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Ok(())
+    });
+
+    tokio::select! {
+        result = &mut playing => {
+            result??;
+            Ok(Outcome::Played)
+        }
+        _ = &mut cancel => {
+            stop.0.store(true, Ordering::SeqCst);
+            let _ = playing.await;
+            Ok(Outcome::Interrupted)
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn player_command(path: &str) -> Option<(Command, &'static str)> {
+    let mut cmd = Command::new("afplay");
+    cmd.arg(path);
+    Some((cmd, "afplay"))
+}
+
+#[cfg(target_os = "windows")]
+fn player_command(path: &str) -> Option<(Command, &'static str)> {
+    let script = format!(
+        "(New-Object System.Media.SoundPlayer '{}').PlaySync()",
+        path
+    );
+    let mut cmd = Command::new("powershell");
+    cmd.arg("-Command").arg(script);
+    Some((cmd, "powershell"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn player_command(_path: &str) -> Option<(Command, &'static str)> {
+    None
+}
+
+/// Creates the temp file the player reads from, falling back to the configured
+/// `temp_dir` when the system temp directory is full or read-only. `size` is the
+/// expected file size, checked against the free space before anything is written.
+fn create_temp_file(
+    suffix: &str,
+    size: u64,
+    temp_dir: Option<&str>,
+    lang: Lang,
+) -> Result<tempfile::NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    builder.suffix(suffix);
+    let system_dir = std::env::temp_dir();
+    let system_err = match disk::ensure_space(&system_dir, size, lang)
+        .and_then(|()| builder.tempfile().map_err(Into::into))
+    {
+        Ok(file) => return Ok(file),
+        Err(e) => e,
+    };
+
+    let Some(temp_dir) = temp_dir.filter(|dir| !dir.is_empty()) else {
+        return Err(anyhow::anyhow!(lang.pick(
+            format!(
+                "一時ファイルを作成できません ({}): {}。空き容量を確保するか、config の temp_dir に書き込み可能なフォルダを指定してください",
+                system_dir.display(),
+                system_err
+            ),
+            format!(
+                "Could not create a temporary file ({}): {}. Free up space or set temp_dir in the config to a writable folder",
+                system_dir.display(),
+                system_err
+            ),
+        )));
+    };
+    disk::ensure_space(std::path::Path::new(temp_dir), size, lang)
+        .and_then(|()| builder.tempfile_in(temp_dir).map_err(Into::into))
+        .map_err(|e| {
+            anyhow::anyhow!(lang.pick(
+                format!(
+                    "一時ファイルを作成できません ({}: {} / {}: {})。空き容量を確保するか、temp_dir を確認してください",
+                    system_dir.display(),
+                    system_err,
+                    temp_dir,
+                    e
+                ),
+                format!(
+                    "Could not create a temporary file ({}: {} / {}: {}). Free up space or check temp_dir",
+                    system_dir.display(),
+                    system_err,
+                    temp_dir,
+                    e
+                ),
+            ))
+        })
+}
+
+/// Rough size of 16-bit 44.1 kHz stereo WAV relative to a 128 kbps MP3.
+#[cfg(target_os = "windows")]
+const MP3_TO_WAV_SIZE_RATIO: u64 = 11;
+
+/// SoundPlayer only understands WAV, so MP3 is converted with ffmpeg first.
+#[cfg(target_os = "windows")]
+async fn transcode_to_wav(
+    path: &str,
+    mp3_size: u64,
+    config: &AppConfig,
+) -> Result<tempfile::NamedTempFile> {
+    let wav_file = create_temp_file(
+        ".wav",
+        mp3_size * MP3_TO_WAV_SIZE_RATIO,
+        config.temp_dir.as_deref(),
+        Lang::of(config),
+    )?;
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i", path])
+        .arg(wav_file.path())
+        .status()
+        .await
+        .map_err(|e| process::spawn_error("ffmpeg (needed for mp3 playback)", e))?;
+    if !status.success() {
+        return Err(process::exit_error("ffmpeg", status));
+    }
+    Ok(wav_file)
+}
+
+async fn play_audio(
+    data: &[u8],
+    format: AudioFormat,
+    config: &AppConfig,
+    cancel: &mut oneshot::Receiver<()>,
+) -> Result<Outcome> {
+    // The extension matters: players pick their decoder from it.
+    let mut temp_file = create_temp_file(
+        format.extension(),
+        data.len() as u64,
+        config.temp_dir.as_deref(),
+        Lang::of(config),
+    )?;
+    temp_file.write_all(data)?;
+    let path = temp_file
+        .path()
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid path"))?;
+
+    #[cfg(target_os = "windows")]
+    let transcoded = match format {
+        AudioFormat::Mp3 => Some(transcode_to_wav(path, data.len() as u64, config).await?),
+        AudioFormat::Wav => None,
+    };
+    #[cfg(target_os = "windows")]
+    let path = match &transcoded {
+        Some(wav_file) => wav_file
+            .path()
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid path"))?,
+        None => path,
+    };
+
+    let Some((mut cmd, player)) = player_command(path) else {
+        return Err(anyhow::anyhow!(
+            "no audio backend available on this platform ({})",
+            std::env::consts::OS
+        ));
+    };
+    let mut child = cmd
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| process::spawn_error(player, e))?;
+
+    tokio::select! {
+        status = child.wait() => {
+            if !status?.success() {
+                return Err(PlayerFailed(player).into());
+            }
+            Ok(Outcome::Played)
+        }
+        _ = cancel => {
+            let _ = child.kill().await;
+            Ok(Outcome::Interrupted)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn watchdog_timeout_raises_the_stop_flag() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let guard = StopOnDrop(stop.clone());
+        let hung = async move {
+            let _guard = guard;
+            std::future::pending::<Result<Outcome>>().await
+        };
+
+        let result = with_watchdog(Some(Duration::from_millis(10)), hung).await;
+
+        assert!(result.unwrap_err().is::<PlaybackTimedOut>());
+        assert!(stop.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn watchdog_passes_through_a_finished_result() {
+        let result =
+            with_watchdog(Some(Duration::from_secs(5)), async { Ok(Outcome::Played) }).await;
+        assert_eq!(result.unwrap(), Outcome::Played);
+    }
+}
@@ -0,0 +1,136 @@
+//! Optional user command run on every synthesized WAV (`post_process_command`).
+//!
+//! The command runs through the platform shell. By default the WAV is piped through
+//! stdin/stdout; if the command contains `{in}` / `{out}`, those are replaced with temp
+//! file paths instead, for tools that only work on files. The paths are inserted
+//! already quoted for the shell, so the placeholders must not be quoted again.
+
+use anyhow::Result;
+use speak_common::process;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[cfg(target_os = "windows")]
+pub const SHELL: &str = "cmd";
+#[cfg(not(target_os = "windows"))]
+pub const SHELL: &str = "sh";
+
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new(SHELL);
+    #[cfg(target_os = "windows")]
+    cmd.arg("/C");
+    #[cfg(not(target_os = "windows"))]
+    cmd.arg("-c");
+    cmd.arg(command);
+    cmd
+}
+
+/// Runs `command` on `wav`. On any failure the original audio is returned and a
+/// warning is logged, so a broken hook never silences speech.
+pub async fn apply(command: &str, wav: Vec<u8>) -> Vec<u8> {
+    let result = if command.contains("{in}") || command.contains("{out}") {
+        run_with_files(command, &wav).await
+    } else {
+        run_with_pipes(command, &wav).await
+    };
+    match result {
+        Ok(processed) if !processed.is_empty() => processed,
+        Ok(_) => {
+            eprintln!("⚠️ post_process_command produced no output; using the original audio");
+            wav
+        }
+        Err(e) => {
+            eprintln!(
+                "⚠️ post_process_command failed ({}); using the original audio",
+                e
+            );
+            wav
+        }
+    }
+}
+
+async fn run_with_pipes(command: &str, wav: &[u8]) -> Result<Vec<u8>> {
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| process::spawn_error(SHELL, e))?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("stdin unavailable"))?;
+    let input = wav.to_vec();
+    // Feed stdin concurrently so a command that streams output can't deadlock.
+    let writer = tokio::spawn(async move { stdin.write_all(&input).await });
+    let output = child.wait_with_output().await?;
+    let _ = writer.await;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("exited with {}", output.status));
+    }
+    Ok(output.stdout)
+}
+
+async fn run_with_files(command: &str, wav: &[u8]) -> Result<Vec<u8>> {
+    let mut input = tempfile::Builder::new().suffix(".wav").tempfile()?;
+    input.write_all(wav)?;
+    let output = tempfile::Builder::new().suffix(".wav").tempfile()?;
+
+    let command = command
+        .replace("{in}", &quote(input.path()))
+        .replace("{out}", &quote(output.path()));
+    let status = shell(&command)
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|e| process::spawn_error(SHELL, e))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("exited with {}", status));
+    }
+    Ok(tokio::fs::read(output.path()).await?)
+}
+
+/// Quotes `path` as one shell word, so a temp dir with spaces (e.g. a Windows profile
+/// named "First Last") doesn't split the command. Windows paths can't contain `"`.
+#[cfg(target_os = "windows")]
+fn quote(path: &Path) -> String {
+    format!("\"{}\"", path.to_string_lossy())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_paths_survive_spaces_and_quotes() {
+        assert_eq!(
+            quote(Path::new("/tmp/First Last/a.wav")),
+            "'/tmp/First Last/a.wav'"
+        );
+        assert_eq!(quote(Path::new("/tmp/it's.wav")), r"'/tmp/it'\''s.wav'");
+    }
+
+    #[tokio::test]
+    async fn the_shell_reads_a_quoted_path_with_spaces_as_one_word() {
+        let dir = std::env::temp_dir().join(format!("speak mcp {}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("First Last's.wav");
+        std::fs::write(&path, "RIFF").unwrap();
+
+        let output = shell(&format!("cat {}", quote(&path)))
+            .output()
+            .await
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"RIFF");
+    }
+}
@@ -0,0 +1,59 @@
+//! Audio synthesized ahead of time by `prepare_speech`, kept under a token until
+//! `play_prepared` asks for it or the entry expires.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Used when `prepared_ttl_secs` is unset.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+struct Entry {
+    audio: Vec<u8>,
+    expires_at: Instant,
+}
+
+fn store() -> &'static Mutex<HashMap<String, Entry>> {
+    static STORE: OnceLock<Mutex<HashMap<String, Entry>>> = OnceLock::new();
+    STORE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn new_token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    format!(
+        "sp-{:x}{:08x}",
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        nanos
+    )
+}
+
+/// Stores `audio` for `ttl` and returns its token. Expired entries are dropped here
+/// and on lookup, so memory stays bounded without a background task.
+pub fn insert(audio: Vec<u8>, ttl: Duration) -> String {
+    let token = new_token();
+    let mut store = store().lock().unwrap();
+    let now = Instant::now();
+    store.retain(|_, entry| entry.expires_at > now);
+    store.insert(
+        token.clone(),
+        Entry {
+            audio,
+            expires_at: now + ttl,
+        },
+    );
+    token
+}
+
+/// Returns the audio for `token` if it hasn't expired. The entry stays available for
+/// replays until it expires.
+pub fn get(token: &str) -> Option<Vec<u8>> {
+    let mut store = store().lock().unwrap();
+    let now = Instant::now();
+    store.retain(|_, entry| entry.expires_at > now);
+    store.get(token).map(|entry| entry.audio.clone())
+}
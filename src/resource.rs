@@ -0,0 +1,98 @@
+//! Text read by reference through `resource_uri` instead of being passed inline.
+//!
+//! async-mcp can't send requests to the client, so the client's own resources are not
+//! reachable; only local files (`file://`) and web pages (`http(s)://`) are supported.
+
+use crate::http;
+use crate::messages::Lang;
+use anyhow::Result;
+use speak_common::AppConfig;
+
+/// Largest resource that is read. Far more than anyone wants spoken in one go, but it
+/// keeps a wrong URI from pulling a huge file into memory.
+pub const MAX_BYTES: usize = 1024 * 1024;
+
+fn too_large(lang: Lang, uri: &str) -> anyhow::Error {
+    anyhow::anyhow!(lang.pick(
+        format!("{} が大きすぎます (上限 {} KiB)", uri, MAX_BYTES / 1024),
+        format!("{} is too large (limit {} KiB)", uri, MAX_BYTES / 1024),
+    ))
+}
+
+/// Reads the text at `uri`. Fails for other schemes, content over `MAX_BYTES` and
+/// content that isn't UTF-8.
+pub async fn read_text(uri: &str, config: &AppConfig) -> Result<String> {
+    let lang = Lang::of(config);
+    let bytes = if let Some(path) = uri.strip_prefix("file://") {
+        read_file(file_path(path), uri, lang)?
+    } else if uri.starts_with("http://") || uri.starts_with("https://") {
+        fetch(uri, config, lang).await?
+    } else {
+        return Err(anyhow::anyhow!(lang.pick(
+            format!(
+                "resource_uri のスキームに対応していません: {} (file:// / http:// / https:// のみ)",
+                uri
+            ),
+            format!(
+                "Unsupported resource_uri scheme: {} (only file://, http:// and https://)",
+                uri
+            ),
+        )));
+    };
+    String::from_utf8(bytes).map_err(|_| {
+        anyhow::anyhow!(lang.pick(
+            format!("{} は UTF-8 のテキストではありません", uri),
+            format!("{} is not UTF-8 text", uri),
+        ))
+    })
+}
+
+/// `file:///C:/notes.txt` names `C:/notes.txt` on Windows; elsewhere the leading `/`
+/// belongs to the path.
+fn file_path(path: &str) -> &str {
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if cfg!(windows) && path.as_bytes().get(2) == Some(&b':') {
+        &path[1..]
+    } else {
+        path
+    }
+}
+
+fn read_file(path: &str, uri: &str, lang: Lang) -> Result<Vec<u8>> {
+    let len = std::fs::metadata(path)
+        .map_err(|e| {
+            anyhow::anyhow!(lang.pick(
+                format!("{} を読めません: {}", uri, e),
+                format!("Can't read {}: {}", uri, e),
+            ))
+        })?
+        .len();
+    if len > MAX_BYTES as u64 {
+        return Err(too_large(lang, uri));
+    }
+    std::fs::read(path).map_err(Into::into)
+}
+
+/// Downloads through the same client setup as engine requests (proxy settings apply),
+/// stopping as soon as the body passes `MAX_BYTES`.
+async fn fetch(uri: &str, config: &AppConfig, lang: Lang) -> Result<Vec<u8>> {
+    let mut resp = http::client(config, uri)
+        .get(uri)
+        .send()
+        .await?
+        .error_for_status()?;
+    if resp
+        .content_length()
+        .is_some_and(|len| len > MAX_BYTES as u64)
+    {
+        return Err(too_large(lang, uri));
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_BYTES {
+            return Err(too_large(lang, uri));
+        }
+    }
+    Ok(body)
+}
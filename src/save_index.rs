@@ -0,0 +1,88 @@
+//! Index of files written by `save_speech` / `batch_synthesize`, so `skip_if_exists` can
+//! reuse an existing file instead of synthesizing the same request again.
+//!
+//! Each output path maps to a SHA-256 of the request that produced it and of the file's
+//! content. A file counts as reusable only while it still exists with that content, so
+//! deleting or editing a file by hand just makes the next run write it again.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use speak_common::{AppConfig, get_config_path};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Serializes read-modify-write cycles between concurrent saves.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    request: String,
+    content: String,
+}
+
+/// `save_index_path`, or `save_index.json` next to the config file.
+fn index_path(config: &AppConfig) -> PathBuf {
+    match config.save_index_path.as_deref().filter(|p| !p.is_empty()) {
+        Some(path) => PathBuf::from(path),
+        None => get_config_path().with_file_name("save_index.json"),
+    }
+}
+
+pub fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Entries are keyed by absolute path so relative and absolute spellings match.
+fn entry_key(output_path: &str) -> String {
+    std::path::absolute(output_path)
+        .unwrap_or_else(|_| PathBuf::from(output_path))
+        .display()
+        .to_string()
+}
+
+/// A missing or unreadable index is treated as empty; it only ever saves work.
+fn load(path: &Path) -> BTreeMap<String, Entry> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// True when `output_path` was written for `request` and still holds that content.
+pub fn is_current(config: &AppConfig, output_path: &str, request: &str) -> bool {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let index = load(&index_path(config));
+    let Some(entry) = index.get(&entry_key(output_path)) else {
+        return false;
+    };
+    entry.request == request
+        && std::fs::read(output_path).is_ok_and(|content| sha256(&content) == entry.content)
+}
+
+/// Records that `output_path` now holds `content` produced for `request`, dropping
+/// entries whose files no longer exist.
+pub fn record(config: &AppConfig, output_path: &str, request: &str, content: &[u8]) -> Result<()> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = index_path(config);
+    let mut index = load(&path);
+    index.retain(|file, _| Path::new(file).exists());
+    index.insert(
+        entry_key(output_path),
+        Entry {
+            request: request.to_string(),
+            content: sha256(content),
+        },
+    );
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(&index)?)?;
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Speaker lists fetched from VOICEVOX-compatible engines.
+//!
+//! Every successful `/speakers` fetch is cached by base URL, so tools that only need
+//! to list or validate voices keep working from the last known list, and an engine
+//! that comes online after startup is picked up by the background poller.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StyleInfo {
+    pub name: String,
+    pub id: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SpeakerInfo {
+    pub name: String,
+    pub styles: Vec<StyleInfo>,
+}
+
+/// Upper bound for the poller's delay between attempts.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(300);
+
+fn cache() -> &'static Mutex<HashMap<String, Vec<SpeakerInfo>>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Vec<SpeakerInfo>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Fetches `/speakers` and refreshes the cache on success.
+pub async fn fetch_speakers(base_url: &str) -> Option<Vec<SpeakerInfo>> {
+    let client = crate::http::client(&speak_common::load_config(), base_url);
+    let url = format!("{}/speakers", base_url);
+    let speakers = match client.get(&url).send().await {
+        Ok(resp) => resp.json::<Vec<SpeakerInfo>>().await.ok()?,
+        Err(_) => return None,
+    };
+    cache()
+        .lock()
+        .unwrap()
+        .insert(base_url.to_string(), speakers.clone());
+    Some(speakers)
+}
+
+/// Returns the cached list, fetching it if the engine has never been reached.
+pub async fn speakers(base_url: &str) -> Option<Vec<SpeakerInfo>> {
+    let cached = cache().lock().unwrap().get(base_url).cloned();
+    match cached {
+        Some(speakers) => Some(speakers),
+        None => fetch_speakers(base_url).await,
+    }
+}
+
+/// Retries `/speakers` in the background for engines that were offline at startup.
+///
+/// The delay starts at `interval` and doubles after every round that still leaves an
+/// engine unreachable, up to five minutes. Each engine is dropped from the loop once
+/// its list has been fetched; the task ends when none are left.
+pub fn spawn_poller(mut base_urls: Vec<String>, interval: Duration) {
+    if base_urls.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut delay = interval;
+        while !base_urls.is_empty() {
+            tokio::time::sleep(delay).await;
+            let mut still_offline = Vec::new();
+            for base_url in base_urls {
+                match fetch_speakers(&base_url).await {
+                    Some(speakers) => eprintln!(
+                        "🔌 {} に接続しました ({} speakers)",
+                        base_url,
+                        speakers.len()
+                    ),
+                    None => still_offline.push(base_url),
+                }
+            }
+            base_urls = still_offline;
+            delay = (delay * 2).min(MAX_POLL_INTERVAL.max(interval));
+        }
+    });
+}
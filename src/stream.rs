@@ -0,0 +1,140 @@
+//! Text streamed in pieces by `speak_stream_append` / `speak_stream_finish`, so each
+//! sentence can be spoken as soon as it is complete instead of after the whole message.
+//!
+//! A session buffers the text that doesn't end in a sentence boundary yet. Sessions idle
+//! for longer than `stream_session_ttl_secs` are dropped on the next call, together with
+//! their unspoken text, so an abandoned stream never holds memory for long and no
+//! background task is needed.
+
+use speak_common::EngineTarget;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// Used when `stream_session_ttl_secs` is unset.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// Open sessions at most; the least recently used one is dropped to make room.
+const MAX_SESSIONS: usize = 16;
+/// Unfinished text longer than this is spoken anyway, so a stream without punctuation
+/// neither grows without bound nor stays silent until `finish`.
+const MAX_BUFFER_CHARS: usize = 1000;
+
+/// The voice a session speaks with, fixed by its first call.
+#[derive(Clone)]
+pub struct Voice {
+    pub engine: EngineTarget,
+    pub speaker: Option<u32>,
+    pub speed: Option<f32>,
+    pub pitch: Option<f32>,
+}
+
+struct Session {
+    voice: Voice,
+    buffer: String,
+    /// Whether any sentence has been handed out yet; only the first gets `text_prefix`.
+    started: bool,
+    /// Resolves once the previous batch is queued, so batches play in order even when a
+    /// later one synthesizes faster.
+    tail: Option<oneshot::Receiver<()>>,
+    last_active: Instant,
+}
+
+/// Sentences ready to be synthesized and queued.
+pub struct Batch {
+    pub voice: Voice,
+    pub sentences: Vec<String>,
+    /// True for the session's first sentences (they get `text_prefix`).
+    pub first: bool,
+    /// True when this batch ends the session (its last sentence gets `text_suffix`).
+    pub last: bool,
+    /// Wait for this before queueing.
+    pub after: Option<oneshot::Receiver<()>>,
+    /// Signal this once queued.
+    pub done: oneshot::Sender<()>,
+    /// Characters still waiting for a sentence boundary.
+    pub buffered_chars: usize,
+}
+
+fn sessions() -> &'static Mutex<HashMap<String, Session>> {
+    static SESSIONS: OnceLock<Mutex<HashMap<String, Session>>> = OnceLock::new();
+    SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Drops sessions idle for longer than `ttl`, logging the text they never spoke.
+fn sweep(sessions: &mut HashMap<String, Session>, ttl: Duration) {
+    sessions.retain(|id, session| {
+        let alive = session.last_active.elapsed() < ttl;
+        if !alive {
+            eprintln!(
+                "Stream session {} timed out; dropped {} unspoken characters",
+                id,
+                session.buffer.chars().count()
+            );
+        }
+        alive
+    });
+}
+
+/// Byte index just past the last sentence boundary in `text`, if any.
+fn complete_len(text: &str) -> Option<usize> {
+    text.char_indices()
+        .rfind(|(_, c)| matches!(c, '。' | '！' | '？' | '!' | '?' | '\n'))
+        .map(|(i, c)| i + c.len_utf8())
+}
+
+/// Adds `text` to session `id`, creating it with `voice` if needed, and takes out the
+/// sentences that are now complete. With `finish` everything left is taken and the
+/// session ends.
+pub fn push(id: &str, text: &str, finish: bool, voice: Voice, ttl: Duration) -> Batch {
+    let mut sessions = sessions().lock().unwrap();
+    sweep(&mut sessions, ttl);
+    if !sessions.contains_key(id) && sessions.len() >= MAX_SESSIONS {
+        let oldest = sessions
+            .iter()
+            .min_by_key(|(_, session)| session.last_active)
+            .map(|(id, _)| id.clone());
+        if let Some(oldest) = oldest {
+            eprintln!("Too many stream sessions; dropped {}", oldest);
+            sessions.remove(&oldest);
+        }
+    }
+    let session = sessions.entry(id.to_string()).or_insert_with(|| Session {
+        voice,
+        buffer: String::new(),
+        started: false,
+        tail: None,
+        last_active: Instant::now(),
+    });
+    session.buffer.push_str(text);
+    session.last_active = Instant::now();
+
+    let split_at = if finish || session.buffer.chars().count() > MAX_BUFFER_CHARS {
+        Some(session.buffer.len())
+    } else {
+        complete_len(&session.buffer)
+    };
+    let complete: String = match split_at {
+        Some(at) => session.buffer.drain(..at).collect(),
+        None => String::new(),
+    };
+    let sentences = speak_common::text::split_sentences(&complete);
+    let first = !session.started && !sentences.is_empty();
+    session.started |= first;
+
+    let (done, tail) = oneshot::channel();
+    let after = session.tail.replace(tail);
+    let batch = Batch {
+        voice: session.voice.clone(),
+        sentences,
+        first,
+        last: finish,
+        after,
+        done,
+        buffered_chars: session.buffer.chars().count(),
+    };
+    if finish {
+        sessions.remove(id);
+    }
+    batch
+}
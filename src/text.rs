@@ -0,0 +1,560 @@
+//! Text preprocessing shared by every engine before synthesis.
+
+use regex::Regex;
+use speak_common::{AppConfig, NewlineMode, ParentheticalHandling, UrlHandling};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Japanese,
+    English,
+}
+
+impl Language {
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Japanese => "japanese",
+            Language::English => "english",
+        }
+    }
+}
+
+fn is_japanese(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // hiragana, katakana
+        | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+        | '\u{FF66}'..='\u{FF9F}' // half-width katakana
+    )
+}
+
+/// Guesses the dominant language from Unicode ranges.
+///
+/// A kana/kanji character carries roughly a word's worth of meaning, so each one is
+/// weighted like three Latin letters; that keeps a few English terms inside a Japanese
+/// sentence from flipping the result.
+pub fn detect_language(text: &str) -> Language {
+    let japanese = text.chars().filter(|c| is_japanese(*c)).count();
+    let latin = text.chars().filter(|c| c.is_ascii_alphabetic()).count();
+    if latin > japanese * 3 {
+        Language::English
+    } else {
+        Language::Japanese
+    }
+}
+
+/// Produces the text that is actually sent to the engine.
+pub fn prepare(text: &str, config: &AppConfig) -> String {
+    let mut text = match config.newline_mode {
+        NewlineMode::Raw => text.to_string(),
+        _ => collapse_blank_lines(text),
+    };
+    if config.linearize_markdown {
+        text = linearize_markdown(&text);
+    }
+    text = strip_emphasis(&text);
+    if config.newline_mode == NewlineMode::Paragraphs {
+        text = join_wrapped_lines(&text);
+    }
+    text = handle_urls(&text, config.url_handling);
+    text = handle_parentheticals(&text, config.parenthetical_handling);
+    text = spell_matching_patterns(&text, &config.spell_number_patterns);
+    if config.spell_numbers {
+        text = spell_digits(&text);
+    }
+    apply_affixes(&text, config)
+}
+
+/// The steps of `prepare` that need the whole text rather than one sentence: a markdown
+/// table spans several lines and a URL can contain `?`. Long text is run through this
+/// before it is split, and its chunks are prepared with `skip_whole_text_steps`.
+pub fn prepare_whole(text: &str, config: &AppConfig) -> String {
+    let text = if config.linearize_markdown {
+        linearize_markdown(text)
+    } else {
+        text.to_string()
+    };
+    handle_urls(&text, config.url_handling)
+}
+
+/// Turns off what `prepare_whole` already did, so it doesn't run again on each chunk.
+pub fn skip_whole_text_steps(config: &mut AppConfig) {
+    config.linearize_markdown = false;
+    config.url_handling = UrlHandling::Keep;
+}
+
+/// Cuts `text` to at most `limit` characters, ending at the last sentence boundary
+/// (`。！？!?` or a line break) inside the limit, or exactly at the limit if there is none.
+pub fn truncate_at_sentence(text: &str, limit: usize) -> &str {
+    let mut boundary = None;
+    for (count, (i, c)) in text.char_indices().enumerate() {
+        if count == limit {
+            return &text[..boundary.unwrap_or(i)];
+        }
+        if matches!(c, '。' | '！' | '？' | '!' | '?' | '\n') {
+            boundary = Some(i + c.len_utf8());
+        }
+    }
+    text
+}
+
+/// Spoken after text cut by `truncate_over_limit`.
+pub fn omission_note(language: Language) -> &'static str {
+    match language {
+        Language::Japanese => "以下省略。",
+        Language::English => " The rest was omitted.",
+    }
+}
+
+/// Only `http(s)://` and `www.` prefixes count as links, so ordinary text with dots or
+/// slashes (`1.5`, `A/B`, file names) is never touched.
+fn url_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r#"(?:https?://|www\.)[^\s<>"'（）「」、。]+"#).expect("valid URL regex")
+    })
+}
+
+fn email_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"[A-Za-z0-9._%+-]+@([A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,})")
+            .expect("valid email regex")
+    })
+}
+
+fn url_domain(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = host.rsplit('@').next().unwrap_or(host);
+    let host = host.split(':').next().unwrap_or(host);
+    host.strip_prefix("www.").unwrap_or(host)
+}
+
+/// Rewrites URLs and email addresses according to `url_handling`.
+fn handle_urls(text: &str, handling: UrlHandling) -> String {
+    if handling == UrlHandling::Keep {
+        return text.to_string();
+    }
+    // Emails first, so the URL pass never sees their domain part.
+    let text = email_regex().replace_all(text, |caps: &regex::Captures| match handling {
+        UrlHandling::Keep => caps[0].to_string(),
+        UrlHandling::Drop => String::new(),
+        UrlHandling::Placeholder => "メールアドレス".to_string(),
+        UrlHandling::Domain => caps[1].to_string(),
+    });
+    url_regex()
+        .replace_all(&text, |caps: &regex::Captures| match handling {
+            UrlHandling::Keep => caps[0].to_string(),
+            UrlHandling::Drop => String::new(),
+            UrlHandling::Placeholder => "リンク".to_string(),
+            UrlHandling::Domain => url_domain(&caps[0]).to_string(),
+        })
+        .into_owned()
+}
+
+fn is_open_paren(c: char) -> bool {
+    matches!(c, '(' | '（')
+}
+
+fn is_close_paren(c: char) -> bool {
+    matches!(c, ')' | '）')
+}
+
+/// Index of the bracket closing the one at `open`. Half- and full-width brackets pair
+/// with each other since both get mixed in practice. Groups that are unbalanced or span
+/// a line break return `None` and are left alone.
+fn closing_paren(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().skip(open) {
+        if is_open_paren(c) {
+            depth += 1;
+        } else if is_close_paren(c) {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else if c == '\n' {
+            return None;
+        }
+    }
+    None
+}
+
+fn is_pause(c: char) -> bool {
+    matches!(c, '、' | '。' | '！' | '？' | '!' | '?' | ',' | '.' | '\n')
+}
+
+/// Drops or softens parenthesized asides. A nested group goes with its outer one.
+/// A half-width `(` right after ASCII text, as in `f(x)` or a URL, is not an aside
+/// and is kept.
+fn handle_parentheticals(text: &str, handling: ParentheticalHandling) -> String {
+    if handling == ParentheticalHandling::Keep {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let attached = c == '(' && i > 0 && chars[i - 1].is_ascii_graphic();
+        let close = if is_open_paren(c) && !attached {
+            closing_paren(&chars, i)
+        } else {
+            None
+        };
+        let Some(close) = close else {
+            out.push(c);
+            i += 1;
+            continue;
+        };
+        if handling == ParentheticalHandling::Soften {
+            let aside: String = chars[i + 1..close]
+                .iter()
+                .filter(|c| !is_open_paren(**c) && !is_close_paren(**c))
+                .collect();
+            let aside = aside.trim();
+            if !aside.is_empty() {
+                out.truncate(out.trim_end_matches(' ').len());
+                if !out.is_empty() && !out.ends_with(is_pause) {
+                    out.push('、');
+                }
+                out.push_str(aside);
+                if chars.get(close + 1).is_some_and(|next| !is_pause(*next)) {
+                    out.push('、');
+                }
+            }
+        }
+        i = close + 1;
+    }
+    out
+}
+
+/// Separates every run of two or more digits so each digit is read on its own
+/// ("402" → "4、0、2"); single digits are left alone.
+fn spell_digits(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        if c.is_ascii_digit() && chars.peek().is_some_and(|next| next.is_ascii_digit()) {
+            out.push('、');
+        }
+    }
+    out
+}
+
+/// Applies `spell_digits` only inside matches of the configured regexes, e.g.
+/// `"#\\d+"` for order numbers. Invalid patterns are skipped with a warning.
+fn spell_matching_patterns(text: &str, patterns: &[String]) -> String {
+    let mut text = text.to_string();
+    for pattern in patterns {
+        match Regex::new(pattern) {
+            Ok(re) => {
+                text = re
+                    .replace_all(&text, |caps: &regex::Captures| spell_digits(&caps[0]))
+                    .into_owned();
+            }
+            Err(e) => {
+                eprintln!(
+                    "⚠️ spell_number_patterns の正規表現が不正です ({}): {}",
+                    pattern, e
+                )
+            }
+        }
+    }
+    text
+}
+
+/// Splits `| a | b |` into trimmed cells, or returns `None` for a line that isn't a
+/// table row.
+fn table_cells(line: &str) -> Option<Vec<&str>> {
+    let inner = line.trim().strip_prefix('|')?.strip_suffix('|')?;
+    Some(inner.split('|').map(str::trim).collect())
+}
+
+/// True for the `|---|:--:|` line that separates a table header from its body.
+fn is_table_separator(line: &str) -> bool {
+    table_cells(line).is_some_and(|cells| {
+        cells.iter().all(|cell| {
+            let dashes = cell.trim_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+    })
+}
+
+/// Strips a `- `, `* `, `+ ` or `1. ` marker from a list item.
+fn list_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|m| line.strip_prefix(m)) {
+        return Some(item);
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..].strip_prefix(". ")
+}
+
+/// Ends `text` with "。" unless it already ends with sentence punctuation, so every row
+/// or item gets its own pause.
+fn as_sentence(text: &str) -> String {
+    let text = text.trim();
+    if text.ends_with(['。', '！', '？', '.', '!', '?']) {
+        text.to_string()
+    } else {
+        format!("{}。", text)
+    }
+}
+
+/// Reads markdown tables row by row ("1行目、名前 Alice、年齢 30。") and turns list
+/// items into plain sentences. Only a header row directly followed by a separator
+/// counts as a table; everything else passes through unchanged.
+fn linearize_markdown(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut i = 0;
+    while i < lines.len() {
+        let header = table_cells(lines[i]);
+        if let Some(header) =
+            header.filter(|_| lines.get(i + 1).is_some_and(|l| is_table_separator(l)))
+        {
+            i += 2;
+            let mut row_number = 0;
+            while let Some(cells) = lines.get(i).and_then(|l| table_cells(l)) {
+                row_number += 1;
+                let fields: Vec<String> = cells
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, cell)| !cell.is_empty())
+                    .map(
+                        |(col, cell)| match header.get(col).filter(|h| !h.is_empty()) {
+                            Some(name) => format!("{} {}", name, cell),
+                            None => cell.to_string(),
+                        },
+                    )
+                    .collect();
+                out.push(as_sentence(&format!(
+                    "{}行目、{}",
+                    row_number,
+                    fields.join("、")
+                )));
+                i += 1;
+            }
+            continue;
+        }
+        match list_item(lines[i]) {
+            Some(item) if !item.trim().is_empty() => out.push(as_sentence(item)),
+            _ => out.push(lines[i].to_string()),
+        }
+        i += 1;
+    }
+    out.join("\n")
+}
+
+/// Splits `text` at markdown emphasis (`**x**`, `__x__`, `*x*`), marking which pieces
+/// were emphasized. A marker only counts when it has a closing partner on the same
+/// line, hugs non-blank text, and isn't wedged between letters or digits, so `2*3*4`
+/// and `snake__case__name` stay as they are. Punctuation right after a span is joined
+/// to it rather than left as a piece of its own.
+pub fn split_emphasis(text: &str) -> Vec<(String, bool)> {
+    let mut spans: Vec<(String, bool)> = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['*', '_']) {
+        plain.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let emphasized = ["**", "__", "*"]
+            .into_iter()
+            .find(|marker| after.starts_with(marker))
+            .and_then(|marker| {
+                let len = after[marker.len()..].find(marker)?;
+                let inner = &after[marker.len()..marker.len() + len];
+                let end = marker.len() + len + marker.len();
+                let bounded = !plain
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_ascii_alphanumeric())
+                    && !after[end..]
+                        .chars()
+                        .next()
+                        .is_some_and(|c| c.is_ascii_alphanumeric());
+                let tight = !inner.is_empty() && inner.trim() == inner && !inner.contains('\n');
+                (bounded && tight).then_some((inner, end))
+            });
+        match emphasized {
+            Some((inner, end)) => {
+                push_plain(&mut spans, std::mem::take(&mut plain));
+                spans.push((inner.to_string(), true));
+                rest = &after[end..];
+            }
+            None => {
+                plain.push_str(&after[..1]);
+                rest = &after[1..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    push_plain(&mut spans, plain);
+    spans
+}
+
+fn push_plain(spans: &mut Vec<(String, bool)>, text: String) {
+    let punctuation_only = text
+        .chars()
+        .all(|c| c.is_whitespace() || c.is_ascii_punctuation() || "。、！？…".contains(c));
+    match spans.last_mut() {
+        _ if text.is_empty() => {}
+        Some((previous, _)) if punctuation_only => previous.push_str(&text),
+        _ => spans.push((text, false)),
+    }
+}
+
+/// Drops emphasis markers, keeping the emphasized text.
+fn strip_emphasis(text: &str) -> String {
+    split_emphasis(text)
+        .into_iter()
+        .map(|(span, _)| span)
+        .collect()
+}
+
+/// Joins the lines of each paragraph and ends every paragraph with a sentence break.
+/// Lines are joined with a space only between ASCII text, since Japanese has none.
+fn join_wrapped_lines(text: &str) -> String {
+    let paragraphs: Vec<String> = speak_common::text::split_paragraphs(text)
+        .iter()
+        .map(|paragraph| {
+            let mut joined = String::with_capacity(paragraph.len());
+            for line in paragraph.lines().map(str::trim) {
+                let needs_space = joined
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_ascii_graphic())
+                    && line.chars().next().is_some_and(|c| c.is_ascii_graphic());
+                if needs_space {
+                    joined.push(' ');
+                }
+                joined.push_str(line);
+            }
+            as_sentence(&joined)
+        })
+        .collect();
+    paragraphs.join("\n")
+}
+
+/// Trims surrounding whitespace and squeezes runs of blank lines down to one, so pasted
+/// text with large gaps doesn't turn into long stretches of silence.
+fn collapse_blank_lines(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.trim().lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() && lines.last().is_some_and(|l| l.is_empty()) {
+            continue;
+        }
+        lines.push(if line.trim().is_empty() { "" } else { line });
+    }
+    lines.join("\n")
+}
+
+/// Wraps `text` with the configured prefix and suffix. Empty values add nothing.
+fn apply_affixes(text: &str, config: &AppConfig) -> String {
+    format!("{}{}{}", config.text_prefix, text, config.text_suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn affixes_are_added_after_url_parenthetical_and_number_processing() {
+        let config = AppConfig {
+            text_prefix: "(速報) ".to_string(),
+            text_suffix: " https://example.com/x 12".to_string(),
+            url_handling: UrlHandling::Drop,
+            parenthetical_handling: ParentheticalHandling::Drop,
+            spell_numbers: true,
+            ..AppConfig::default()
+        };
+        assert_eq!(
+            prepare(
+                "詳細(補足)は https://example.com/a を参照、402 号室",
+                &config
+            ),
+            "(速報) 詳細は  を参照、4、0、2 号室 https://example.com/x 12"
+        );
+    }
+
+    #[test]
+    fn empty_affixes_add_nothing() {
+        let config = AppConfig::default();
+        assert_eq!(prepare("こんにちは", &config), "こんにちは");
+        assert_eq!(apply_affixes("", &config), "");
+    }
+
+    #[test]
+    fn header_without_separator_is_not_a_table() {
+        let text = "| a | b |\n| 1 | 2 |";
+        assert_eq!(linearize_markdown(text), text);
+    }
+
+    #[test]
+    fn table_rows_are_read_one_by_one_skipping_empty_cells() {
+        let text = "| 名前 | 年齢 |\n|---|:--:|\n| Alice |  |\n| Bob | 30 |";
+        assert_eq!(
+            linearize_markdown(text),
+            "1行目、名前 Alice。\n2行目、名前 Bob、年齢 30。"
+        );
+    }
+
+    #[test]
+    fn cells_beyond_the_header_are_read_without_a_name() {
+        let text = "| a |\n|---|\n| 1 | 2 |";
+        assert_eq!(linearize_markdown(text), "1行目、a 1、2。");
+    }
+
+    #[test]
+    fn list_items_become_sentences() {
+        let text = "- りんご\n1. バナナ\n2. みかん!\n3.14 は円周率\n-1 度";
+        assert_eq!(
+            linearize_markdown(text),
+            "りんご。\nバナナ。\nみかん!\n3.14 は円周率\n-1 度"
+        );
+    }
+
+    fn with_newline_mode(newline_mode: NewlineMode) -> AppConfig {
+        AppConfig {
+            newline_mode,
+            ..AppConfig::default()
+        }
+    }
+
+    #[test]
+    fn raw_mode_passes_line_breaks_through() {
+        let text = "一行目\n\n\n二行目  \n";
+        assert_eq!(prepare(text, &with_newline_mode(NewlineMode::Raw)), text);
+    }
+
+    #[test]
+    fn lines_mode_is_the_default_and_squeezes_blank_lines() {
+        let text = "  一行目\n\n\n\n二行目  \n三行目\n";
+        let expected = "一行目\n\n二行目\n三行目";
+        assert_eq!(prepare(text, &AppConfig::default()), expected);
+        assert_eq!(
+            prepare(text, &with_newline_mode(NewlineMode::Lines)),
+            expected
+        );
+    }
+
+    #[test]
+    fn paragraphs_mode_joins_wrapped_lines() {
+        let config = with_newline_mode(NewlineMode::Paragraphs);
+        assert_eq!(
+            prepare("This is\nwrapped text.\n\n\n日本語の\n文章です", &config),
+            "This is wrapped text.\n日本語の文章です。"
+        );
+        // A space only goes between two ASCII words.
+        assert_eq!(
+            prepare("API\nを呼ぶ\nfoo\nbar", &config),
+            "APIを呼ぶfoo bar。"
+        );
+    }
+}
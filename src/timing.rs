@@ -0,0 +1,86 @@
+//! Per-mora timing derived from a VOICEVOX audio query, for captions and karaoke.
+//!
+//! The lengths in a query are for `speedScale` 1.0; the engine divides every length,
+//! including the leading and trailing silence, by `speedScale` when synthesizing. All
+//! times returned here are in seconds from the start of the audio.
+
+use serde_json::{Value, json};
+
+/// Rounds to milliseconds; finer precision is noise for highlighting text.
+fn seconds(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+fn length(mora: &Value, key: &str, speed: f64) -> f64 {
+    mora.get(key).and_then(Value::as_f64).unwrap_or(0.0) / speed
+}
+
+/// Builds `{unit, duration, phrases, moras}` from a query as sent to `/synthesis`.
+///
+/// Each accent phrase is reported as a word-level entry that ends before its trailing
+/// pause, and each mora (pauses included) with its own start/end and consonant/vowel
+/// durations.
+pub fn from_query(query: &Value) -> Value {
+    let speed = query
+        .get("speedScale")
+        .and_then(Value::as_f64)
+        .filter(|speed| *speed > 0.0)
+        .unwrap_or(1.0);
+    let mut position = length(query, "prePhonemeLength", speed);
+    let mut phrases = Vec::new();
+    let mut moras = Vec::new();
+    let empty = Vec::new();
+
+    let accent_phrases = query
+        .get("accent_phrases")
+        .and_then(Value::as_array)
+        .unwrap_or(&empty);
+    for (index, phrase) in accent_phrases.iter().enumerate() {
+        let phrase_start = position;
+        let mut phrase_end = position;
+        let mut phrase_text = String::new();
+        let phrase_moras = phrase
+            .get("moras")
+            .and_then(Value::as_array)
+            .unwrap_or(&empty);
+        let pause = phrase.get("pause_mora").filter(|pause| !pause.is_null());
+        for (mora, is_pause) in phrase_moras
+            .iter()
+            .map(|mora| (mora, false))
+            .chain(pause.map(|pause| (pause, true)))
+        {
+            let consonant = length(mora, "consonant_length", speed);
+            let vowel = length(mora, "vowel_length", speed);
+            let text = mora.get("text").and_then(Value::as_str).unwrap_or("");
+            if !is_pause {
+                phrase_text.push_str(text);
+            }
+            moras.push(json!({
+                "phrase": index,
+                "text": text,
+                "start": seconds(position),
+                "end": seconds(position + consonant + vowel),
+                "consonant_length": seconds(consonant),
+                "vowel_length": seconds(vowel),
+                "pause": is_pause,
+            }));
+            position += consonant + vowel;
+            if !is_pause {
+                phrase_end = position;
+            }
+        }
+        phrases.push(json!({
+            "text": phrase_text,
+            "start": seconds(phrase_start),
+            "end": seconds(phrase_end),
+        }));
+    }
+    position += length(query, "postPhonemeLength", speed);
+
+    json!({
+        "unit": "seconds",
+        "duration": seconds(position),
+        "phrases": phrases,
+        "moras": moras,
+    })
+}
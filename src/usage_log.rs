@@ -0,0 +1,63 @@
+//! Opt-in usage log of what was spoken. Purely local: nothing is sent anywhere.
+
+use anyhow::Result;
+use serde_json::json;
+use speak_common::AppConfig;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Serializes appends and rotation between concurrent tool calls.
+static LOG_LOCK: Mutex<()> = Mutex::new(());
+
+/// Appends one entry if `usage_log_path` is configured. Failures are only reported on
+/// stderr so logging can never break speech.
+pub fn record(config: &AppConfig, engine: &str, speaker: serde_json::Value, text: &str) {
+    let Some(path) = config.usage_log_path.as_deref() else {
+        return;
+    };
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut entry = json!({
+        "timestamp": timestamp,
+        "engine": engine,
+        "speaker": speaker,
+        "chars": text.chars().count(),
+    });
+    if config.log_text {
+        entry["text"] = json!(text);
+    }
+
+    let max_bytes = config.usage_log_max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+    if let Err(e) = append(Path::new(path), &entry.to_string(), max_bytes) {
+        eprintln!("Failed to write usage log {}: {}", path, e);
+    }
+}
+
+fn append(path: &Path, line: &str, max_bytes: u64) -> Result<()> {
+    let _guard = LOG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Keep a single previous generation so the log can't grow without bound.
+    if fs::metadata(path)
+        .map(|m| m.len() >= max_bytes)
+        .unwrap_or(false)
+    {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        fs::rename(path, rotated)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
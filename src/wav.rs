@@ -0,0 +1,480 @@
+//! 16-bit PCM WAV handling shared by every feature that inspects or rewrites audio
+//! (resampling, export options, waveforms, durations).
+//!
+//! Chunks may come in any order and unknown ones (`LIST`, `fact`, ...) are skipped.
+//! Both plain PCM and `WAVE_FORMAT_EXTENSIBLE` with a PCM sub-format are accepted.
+//! Malformed input is reported as an error; parsing never panics.
+
+use anyhow::Result;
+use std::time::Duration;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Interleaved samples.
+    pub samples: Vec<i16>,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([data[pos], data[pos + 1]])
+}
+
+fn u32_at(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+/// Returns `(sample_rate, channels)` from a `fmt ` chunk body.
+fn parse_fmt(body: &[u8]) -> Result<(u32, u16)> {
+    if body.len() < 16 {
+        return Err(anyhow::anyhow!(
+            "WAV fmt chunk is too short ({} bytes)",
+            body.len()
+        ));
+    }
+    let mut tag = u16_at(body, 0);
+    let channels = u16_at(body, 2);
+    let sample_rate = u32_at(body, 4);
+    let bits = u16_at(body, 14);
+
+    if tag == WAVE_FORMAT_EXTENSIBLE {
+        // cbSize (2) + valid bits (2) + channel mask (4), then the sub-format GUID whose
+        // first two bytes are the actual format tag.
+        if body.len() < 26 {
+            return Err(anyhow::anyhow!(
+                "WAVE_FORMAT_EXTENSIBLE fmt chunk is too short"
+            ));
+        }
+        tag = u16_at(body, 24);
+    }
+
+    if tag != WAVE_FORMAT_PCM || bits != 16 || channels == 0 || sample_rate == 0 {
+        return Err(anyhow::anyhow!(
+            "unsupported WAV format (format {}, {} bits, {} channels, {} Hz); only 16-bit PCM is supported",
+            tag,
+            bits,
+            channels,
+            sample_rate
+        ));
+    }
+    // `encode` writes these back as the 16-bit block align and the 32-bit byte rate.
+    let block_align = u32::from(channels) * 2;
+    if block_align > u32::from(u16::MAX)
+        || u64::from(sample_rate) * u64::from(block_align) > u64::from(u32::MAX)
+    {
+        return Err(anyhow::anyhow!(
+            "WAV header out of range ({} channels at {} Hz)",
+            channels,
+            sample_rate
+        ));
+    }
+    Ok((sample_rate, channels))
+}
+
+impl Wav {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(anyhow::anyhow!("not a WAV file"));
+        }
+
+        let mut format = None;
+        let mut samples_body = None;
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let id = &data[pos..pos + 4];
+            let size = u32_at(data, pos + 4) as usize;
+            let body_start = pos + 8;
+            let declared_end = body_start.saturating_add(size);
+            let body_end = if declared_end <= data.len() {
+                declared_end
+            } else if id == b"data" {
+                // Streamed WAVs often leave the data size as a placeholder; take what's there.
+                data.len()
+            } else {
+                return Err(anyhow::anyhow!(
+                    "truncated WAV chunk {:?} at byte {} ({} bytes declared, {} available)",
+                    String::from_utf8_lossy(id),
+                    pos,
+                    size,
+                    data.len() - body_start
+                ));
+            };
+            let body = &data[body_start..body_end];
+
+            match id {
+                b"fmt " => format = Some(parse_fmt(body)?),
+                b"data" if samples_body.is_none() => samples_body = Some(body),
+                _ => {}
+            }
+            // Chunks are padded to an even length.
+            pos = body_end.saturating_add(size & 1);
+        }
+
+        let (sample_rate, channels) =
+            format.ok_or_else(|| anyhow::anyhow!("WAV has no fmt chunk"))?;
+        let body = samples_body.ok_or_else(|| anyhow::anyhow!("WAV has no data chunk"))?;
+        let samples = body
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        Ok(Wav {
+            sample_rate,
+            channels,
+            samples,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        // Widened so an out-of-range `Wav` built by hand saturates instead of panicking.
+        let data_len = u32::try_from(self.samples.len() * 2).unwrap_or(u32::MAX);
+        let block_align = u32::from(self.channels) * 2;
+        let byte_rate = u64::from(self.sample_rate) * u64::from(block_align);
+        let mut out = Vec::with_capacity(44 + self.samples.len() * 2);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&data_len.saturating_add(36).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&self.channels.to_le_bytes());
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        out.extend_from_slice(&u32::try_from(byte_rate).unwrap_or(u32::MAX).to_le_bytes());
+        out.extend_from_slice(&u16::try_from(block_align).unwrap_or(u16::MAX).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            out.extend_from_slice(&sample.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    /// Resamples every channel with linear interpolation.
+    pub fn resample(&self, target_rate: u32) -> Wav {
+        let channels = self.channels as usize;
+        let frames = self.frames();
+        let out_frames =
+            (frames as u64 * target_rate as u64 / self.sample_rate.max(1) as u64) as usize;
+        let step = self.sample_rate as f64 / target_rate as f64;
+
+        let mut samples = Vec::with_capacity(out_frames * channels);
+        for i in 0..out_frames {
+            let src = i as f64 * step;
+            let left = (src.floor() as usize).min(frames.saturating_sub(1));
+            let right = (left + 1).min(frames.saturating_sub(1));
+            let t = src - left as f64;
+            for ch in 0..channels {
+                let a = self.samples[left * channels + ch] as f64;
+                let b = self.samples[right * channels + ch] as f64;
+                samples.push((a + (b - a) * t).round() as i16);
+            }
+        }
+        Wav {
+            sample_rate: target_rate,
+            channels: self.channels,
+            samples,
+        }
+    }
+}
+
+impl Wav {
+    /// Converts between mono and stereo: stereo is averaged down, mono is duplicated.
+    /// Any other combination is returned unchanged.
+    pub fn with_channels(self, channels: u16) -> Wav {
+        let samples = match (self.channels, channels) {
+            (2, 1) => self
+                .samples
+                .chunks_exact(2)
+                .map(|pair| ((pair[0] as i32 + pair[1] as i32) / 2) as i16)
+                .collect(),
+            (1, 2) => self.samples.iter().flat_map(|&s| [s, s]).collect(),
+            _ => return self,
+        };
+        Wav {
+            sample_rate: self.sample_rate,
+            channels,
+            samples,
+        }
+    }
+
+    /// Places the audio in the stereo field: -1.0 is fully left, 0.0 center, 1.0 fully
+    /// right. Stereo input is mixed down first. The near channel keeps full gain and the
+    /// far one fades out, so a centered clip sounds exactly as it did in mono.
+    pub fn pan(self, pan: f32) -> Wav {
+        let pan = pan.clamp(-1.0, 1.0);
+        let (left, right) = ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0));
+        let mono = self.with_channels(1);
+        let samples = mono
+            .samples
+            .iter()
+            .flat_map(|&s| [(s as f32 * left) as i16, (s as f32 * right) as i16])
+            .collect();
+        Wav {
+            sample_rate: mono.sample_rate,
+            channels: 2,
+            samples,
+        }
+    }
+
+    /// Scales the samples so the loudest one sits at `peak_dbfs` (e.g. -1.0).
+    pub fn normalize_peak(mut self, peak_dbfs: f32) -> Wav {
+        let peak = self
+            .samples
+            .iter()
+            .map(|s| (*s as i32).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        if peak == 0 {
+            return self;
+        }
+        let target = i16::MAX as f32 * 10f32.powf(peak_dbfs / 20.0);
+        let gain = target / peak as f32;
+        for sample in &mut self.samples {
+            *sample = (*sample as f32 * gain)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+        self
+    }
+
+    /// Splits the first channel into `points` equal buckets and returns the minimum and
+    /// maximum of each, normalized to -1.0..=1.0, for drawing a sparkline.
+    pub fn waveform(&self, points: usize) -> Vec<[f32; 2]> {
+        let channels = self.channels as usize;
+        let frames = self.frames();
+        if points == 0 || frames == 0 {
+            return Vec::new();
+        }
+        (0..points)
+            .map(|bucket| {
+                let start = bucket * frames / points;
+                let end = ((bucket + 1) * frames / points).max(start + 1).min(frames);
+                let (min, max) = (start..end)
+                    .map(|frame| self.samples[frame * channels])
+                    .fold((i16::MAX, i16::MIN), |(lo, hi), s| (lo.min(s), hi.max(s)));
+                let scale = i16::MAX as f32;
+                [min as f32 / scale, max as f32 / scale]
+            })
+            .collect()
+    }
+}
+
+/// Appends `ms` of silence, returning the data untouched if it can't be parsed.
+pub fn append_silence(data: Vec<u8>, ms: u64) -> Vec<u8> {
+    match Wav::parse(&data) {
+        Ok(mut wav) => {
+            let frames = wav.sample_rate as u64 * ms / 1000;
+            let samples = frames as usize * wav.channels as usize;
+            wav.samples.extend(std::iter::repeat_n(0, samples));
+            wav.encode()
+        }
+        Err(_) => data,
+    }
+}
+
+/// Joins clips end to end. Every clip must have the sample rate and channel count of
+/// the first.
+pub fn concat(clips: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut clips = clips.iter().map(|data| Wav::parse(data));
+    let Some(first) = clips.next() else {
+        return Err(anyhow::anyhow!("no WAV clips to join"));
+    };
+    let mut joined = first?;
+    for clip in clips {
+        let clip = clip?;
+        if clip.sample_rate != joined.sample_rate || clip.channels != joined.channels {
+            return Err(anyhow::anyhow!(
+                "cannot join WAV clips of different formats ({} Hz, {} channels and {} Hz, {} channels)",
+                joined.sample_rate,
+                joined.channels,
+                clip.sample_rate,
+                clip.channels
+            ));
+        }
+        joined.samples.extend(clip.samples);
+    }
+    Ok(joined.encode())
+}
+
+/// Converts `data` to `target_rate`, returning it untouched when the rate already
+/// matches (no needless quality loss) or when it isn't a WAV this module understands.
+pub fn resample_to(data: Vec<u8>, target_rate: u32) -> Vec<u8> {
+    match Wav::parse(&data) {
+        Ok(wav) if wav.sample_rate != target_rate && wav.frames() > 0 => {
+            wav.resample(target_rate).encode()
+        }
+        _ => data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVE");
+        out.extend_from_slice(&body);
+        out
+    }
+
+    fn fmt(tag: u16, channels: u16, sample_rate: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&sample_rate.to_le_bytes());
+        out.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        out.extend_from_slice(&(channels * 2).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out
+    }
+
+    fn pcm(samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn accepts_chunks_in_any_order() {
+        let data = riff(&[
+            chunk(b"data", &pcm(&[1, -2, 3])),
+            chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 1, 24000)),
+        ]);
+        let wav = Wav::parse(&data).unwrap();
+        assert_eq!((wav.sample_rate, wav.channels), (24000, 1));
+        assert_eq!(wav.samples, vec![1, -2, 3]);
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let data = riff(&[
+            chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 2, 44100)),
+            chunk(b"LIST", b"INFOISFT\x04\0\0\0test"),
+            chunk(b"fact", &4u32.to_le_bytes()),
+            chunk(b"data", &pcm(&[5, 6, 7, 8])),
+        ]);
+        let wav = Wav::parse(&data).unwrap();
+        assert_eq!(wav.channels, 2);
+        assert_eq!(wav.samples, vec![5, 6, 7, 8]);
+        assert_eq!(wav.frames(), 2);
+    }
+
+    #[test]
+    fn accepts_extensible_pcm() {
+        let mut body = fmt(WAVE_FORMAT_EXTENSIBLE, 1, 48000);
+        body.extend_from_slice(&22u16.to_le_bytes()); // cbSize
+        body.extend_from_slice(&16u16.to_le_bytes()); // valid bits
+        body.extend_from_slice(&4u32.to_le_bytes()); // channel mask
+        body.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        body.extend_from_slice(&[
+            0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+        ]);
+        let data = riff(&[chunk(b"fmt ", &body), chunk(b"data", &pcm(&[9]))]);
+        let wav = Wav::parse(&data).unwrap();
+        assert_eq!(wav.sample_rate, 48000);
+        assert_eq!(wav.samples, vec![9]);
+    }
+
+    #[test]
+    fn rejects_extensible_with_other_sub_format() {
+        let mut body = fmt(WAVE_FORMAT_EXTENSIBLE, 1, 48000);
+        body.extend_from_slice(&22u16.to_le_bytes());
+        body.extend_from_slice(&16u16.to_le_bytes());
+        body.extend_from_slice(&4u32.to_le_bytes());
+        body.extend_from_slice(&3u16.to_le_bytes()); // IEEE float
+        body.extend_from_slice(&[0; 14]);
+        let data = riff(&[chunk(b"fmt ", &body), chunk(b"data", &pcm(&[9]))]);
+        assert!(Wav::parse(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_non_data_chunk_is_an_error() {
+        let mut data = riff(&[chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 1, 24000))]);
+        data.extend_from_slice(b"LIST");
+        data.extend_from_slice(&100u32.to_le_bytes());
+        data.extend_from_slice(b"short");
+        let error = Wav::parse(&data).err().unwrap().to_string();
+        assert!(error.contains("truncated"), "{}", error);
+    }
+
+    #[test]
+    fn placeholder_data_size_takes_the_rest() {
+        let mut data = riff(&[chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 1, 24000))]);
+        data.extend_from_slice(b"data");
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&pcm(&[1, 2, 3, 4]));
+        let wav = Wav::parse(&data).unwrap();
+        assert_eq!(wav.samples, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn odd_sized_chunks_are_padded() {
+        let data = riff(&[
+            chunk(b"fmt ", &fmt(WAVE_FORMAT_PCM, 1, 24000)),
+            chunk(b"junk", b"abc"),
+            chunk(b"data", &pcm(&[42, -42])),
+        ]);
+        let wav = Wav::parse(&data).unwrap();
+        assert_eq!(wav.samples, vec![42, -42]);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        assert!(Wav::parse(b"").is_err());
+        assert!(Wav::parse(b"RIFF\0\0\0\0WAVE").is_err());
+        let no_fmt = riff(&[chunk(b"data", &pcm(&[1]))]);
+        assert!(Wav::parse(&no_fmt).is_err());
+        let short_fmt = riff(&[chunk(b"fmt ", &[1, 0, 1, 0]), chunk(b"data", &pcm(&[1]))]);
+        assert!(Wav::parse(&short_fmt).is_err());
+    }
+
+    #[test]
+    fn rejects_channel_counts_encode_cannot_write() {
+        let mut body = fmt(WAVE_FORMAT_PCM, 1, 24000);
+        body[2..4].copy_from_slice(&40000u16.to_le_bytes());
+        let data = riff(&[chunk(b"fmt ", &body), chunk(b"data", &pcm(&[1]))]);
+        assert!(Wav::parse(&data).is_err());
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let wav = Wav {
+            sample_rate: 22050,
+            channels: 2,
+            samples: vec![1, -1, 300, -300],
+        };
+        let parsed = Wav::parse(&wav.encode()).unwrap();
+        assert_eq!((parsed.sample_rate, parsed.channels), (22050, 2));
+        assert_eq!(parsed.samples, wav.samples);
+    }
+
+    #[test]
+    fn encode_saturates_instead_of_panicking() {
+        let wav = Wav {
+            sample_rate: u32::MAX,
+            channels: u16::MAX,
+            samples: vec![0; 4],
+        };
+        assert_eq!(wav.encode().len(), 44 + 8);
+    }
+}
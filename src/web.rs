@@ -0,0 +1,66 @@
+//! Optional HTTP mode for scripts and web pages that don't speak MCP.
+//!
+//! Built only with the `http` cargo feature. `POST /speak` takes the same JSON as the
+//! `speak_auto` tool and returns the synthesized WAV, or plays it on the server when
+//! `"play": true` is set. The server binds to localhost only.
+
+use crate::{SpeakAutoArgs, speak_auto_with, synthesize_auto};
+use anyhow::Result;
+use async_mcp::types::ToolResponseContent;
+use axum::Router;
+use axum::extract::Json;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+struct SpeakRequest {
+    #[serde(flatten)]
+    speak: SpeakAutoArgs,
+    /// Play through the server's speakers instead of returning the audio.
+    #[serde(default)]
+    play: bool,
+}
+
+pub async fn serve(port: u16) -> Result<()> {
+    let app = Router::new().route("/speak", post(speak));
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    eprintln!("🌐 HTTP モード: http://127.0.0.1:{}/speak", port);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn speak(Json(req): Json<SpeakRequest>) -> Response {
+    if !req.play {
+        return match synthesize_auto(req.speak).await {
+            Ok(wav) => ([(header::CONTENT_TYPE, "audio/wav")], wav).into_response(),
+            Err(e) => error(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+    }
+
+    match speak_auto_with(req.speak).await {
+        Ok(response) => {
+            let text: Vec<&str> = response
+                .content
+                .iter()
+                .filter_map(|content| match content {
+                    ToolResponseContent::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            let status = if response.is_error == Some(true) {
+                StatusCode::INTERNAL_SERVER_ERROR
+            } else {
+                StatusCode::OK
+            };
+            (status, Json(json!({ "message": text.join("\n") }))).into_response()
+        }
+        Err(e) => error(StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}